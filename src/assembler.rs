@@ -0,0 +1,1120 @@
+use super::disassembler::Syntax;
+use super::memory::PROGRAM_START;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// Nesting limit for `include`, which also stops files from including themselves forever.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Names of operands which cannot be used as labels or constants.
+const RESERVED: [&str; 10] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "EQU"];
+
+/// Error raised while assembling, pointing at the offending line and column (both from 1).
+#[derive(Debug, PartialEq)]
+pub struct AssemblyError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// Bytes assembled from one source line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceMapEntry {
+    pub address: u16,
+    pub length: u16,
+    pub file: String,
+    pub line: usize,
+}
+
+/// Source line of every assembled statement, in the order of the ROM.
+/// The text form written by `chip8-asm --map` has one entry per line, holding the address and
+/// length in hex, the line number and the file name:
+///
+/// ```text
+/// 0200 0002 3 game.8s
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    pub entries: Vec<SourceMapEntry>,
+}
+
+impl SourceMap {
+    /// Entry of the statement assembled to `address`.
+    pub fn lookup(&self, address: u16) -> Option<&SourceMapEntry> {
+        self.entries
+            .iter()
+            .find(|entry| address >= entry.address && (address - entry.address) < entry.length)
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{:04X} {:04X} {} {}",
+                entry.address, entry.length, entry.line, entry.file
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SourceMap {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || format!("invalid source map entry on line {}", index + 1);
+            let mut fields = line.splitn(4, ' ');
+            let mut next = || fields.next().ok_or_else(invalid);
+            let address = u16::from_str_radix(next()?, 16).map_err(|_| invalid())?;
+            let length = u16::from_str_radix(next()?, 16).map_err(|_| invalid())?;
+            let number = next()?.parse().map_err(|_| invalid())?;
+            let file = next()?.to_string();
+            entries.push(SourceMapEntry {
+                address,
+                length,
+                file,
+                line: number,
+            });
+        }
+        Ok(SourceMap { entries })
+    }
+}
+
+/// Assemble Cowgod syntax source into a ROM loaded at `PROGRAM_START`.
+/// Included files are looked up relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    assemble_with_map(source, Syntax::Cowgod).map(|(rom, _)| rom)
+}
+
+/// Assemble source in `syntax`, also returning where every statement ended up.
+/// Octo source is read one statement per line, as `chip8-disasm --syntax octo` writes it.
+pub fn assemble_with_map(
+    source: &str,
+    syntax: Syntax,
+) -> Result<(Vec<u8>, SourceMap), AssemblyError> {
+    let mut lines = Vec::new();
+    read_lines(
+        Rc::from("<input>"),
+        source,
+        Path::new("."),
+        0,
+        syntax,
+        &mut lines,
+    )?;
+    Assembler::new(lines).run()
+}
+
+/// Assemble the Cowgod syntax source file at `path`. Included files are looked up relative to
+/// the file including them.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AssemblyError> {
+    assemble_file_with_map(path, Syntax::Cowgod).map(|(rom, _)| rom)
+}
+
+/// Assemble the source file at `path` in `syntax`, also returning where every statement ended
+/// up.
+pub fn assemble_file_with_map<P: AsRef<Path>>(
+    path: P,
+    syntax: Syntax,
+) -> Result<(Vec<u8>, SourceMap), AssemblyError> {
+    let path = path.as_ref();
+    let name: Rc<str> = Rc::from(path.display().to_string());
+    let source = fs::read_to_string(path).map_err(|err| AssemblyError {
+        file: name.to_string(),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    let mut lines = Vec::new();
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    read_lines(name, &source, dir, 0, syntax, &mut lines)?;
+    Assembler::new(lines).run()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Symbol(&'static str),
+}
+
+#[derive(Clone, Debug)]
+struct Spanned {
+    token: Token,
+    column: usize,
+}
+
+struct SourceLine {
+    file: Rc<str>,
+    number: usize,
+    tokens: Vec<Spanned>,
+}
+
+impl SourceLine {
+    fn error<S: Into<String>>(&self, column: usize, message: S) -> AssemblyError {
+        AssemblyError {
+            file: self.file.to_string(),
+            line: self.number,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Tokenize `source` line by line into `out`, replacing `include` lines by the included lines.
+fn read_lines(
+    file: Rc<str>,
+    source: &str,
+    dir: &Path,
+    depth: usize,
+    syntax: Syntax,
+    out: &mut Vec<SourceLine>,
+) -> Result<(), AssemblyError> {
+    for (index, text) in source.lines().enumerate() {
+        let mut line = SourceLine {
+            file: Rc::clone(&file),
+            number: index + 1,
+            tokens: Vec::new(),
+        };
+        let tokens = match syntax {
+            Syntax::Cowgod => tokenize(text),
+            Syntax::Octo => tokenize_octo(text),
+        };
+        line.tokens = tokens.map_err(|(column, message)| line.error(column, message))?;
+        let is_include = match line.tokens.first() {
+            Some(Spanned {
+                token: Token::Ident(name),
+                ..
+            }) => name.eq_ignore_ascii_case("include"),
+            _ => false,
+        };
+        if !is_include {
+            out.push(line);
+            continue;
+        }
+        let name = match line.tokens.get(1..) {
+            Some(
+                [Spanned {
+                    token: Token::Str(name),
+                    ..
+                }],
+            ) => name.clone(),
+            _ => return Err(line.error(line.tokens[0].column, "include needs a file name")),
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(line.error(line.tokens[1].column, "includes are nested too deeply"));
+        }
+        let path: PathBuf = dir.join(&name);
+        let included = fs::read_to_string(&path).map_err(|err| {
+            line.error(
+                line.tokens[1].column,
+                format!("cannot include {}: {}", name, err),
+            )
+        })?;
+        let included_dir = path.parent().unwrap_or(dir).to_path_buf();
+        let included_file: Rc<str> = Rc::from(path.display().to_string());
+        read_lines(
+            included_file,
+            &included,
+            &included_dir,
+            depth + 1,
+            syntax,
+            out,
+        )?;
+    }
+    Ok(())
+}
+
+fn tokenize(text: &str) -> Result<Vec<Spanned>, (usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c == ';' {
+            break;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "_.".contains(chars[i])) {
+                i += 1;
+            }
+            Token::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() || c == '#' || c == '$' {
+            let (radix, skip) = match (c, chars.get(i + 1)) {
+                ('#', _) | ('$', _) => (16, 1),
+                ('0', Some('x')) | ('0', Some('X')) => (16, 2),
+                ('0', Some('b')) | ('0', Some('B')) => (2, 2),
+                _ => (10, 0),
+            };
+            i += skip;
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            let value = i64::from_str_radix(&digits, radix).map_err(|_| {
+                let number: String = chars[column - 1..i].iter().collect();
+                (column, format!("invalid number {}", number))
+            })?;
+            Token::Number(value)
+        } else if c == '"' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err((column, "unterminated string".to_string()));
+            }
+            i += 1;
+            Token::Str(chars[start..i - 1].iter().collect())
+        } else {
+            let two: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = match two.as_str() {
+                "<<" => "<<",
+                ">>" => ">>",
+                _ => match c {
+                    ',' => ",",
+                    ':' => ":",
+                    '(' => "(",
+                    ')' => ")",
+                    '[' => "[",
+                    ']' => "]",
+                    '+' => "+",
+                    '-' => "-",
+                    '*' => "*",
+                    '/' => "/",
+                    '%' => "%",
+                    '&' => "&",
+                    '|' => "|",
+                    '^' => "^",
+                    '~' => "~",
+                    '=' => "=",
+                    _ => return Err((column, format!("unexpected character {}", c))),
+                },
+            };
+            i += symbol.len();
+            Token::Symbol(symbol)
+        };
+        tokens.push(Spanned { token, column });
+    }
+    Ok(tokens)
+}
+
+/// Tokenize a line of Octo into the tokens of the same statement in Cowgod syntax, keeping the
+/// columns of the Octo words. A line holds at most one statement or one row of bytes, optionally
+/// after a `: label`, and `#` starts a comment.
+fn tokenize_octo(text: &str) -> Result<Vec<Spanned>, (usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if chars[i] == '#' {
+            break;
+        }
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        words.push((start + 1, chars[start..i].iter().collect::<String>()));
+    }
+
+    let mut tokens = Vec::new();
+    let mut rest = &words[..];
+    if let [(colon, word), (column, name), tail @ ..] = rest {
+        if word == ":" {
+            tokens.extend(octo_part(*column, name)?);
+            tokens.push(Spanned {
+                token: Token::Symbol(":"),
+                column: *colon,
+            });
+            rest = tail;
+        }
+    }
+    if rest.is_empty() {
+        return Ok(tokens);
+    }
+
+    let w: Vec<&str> = rest.iter().map(|(_, word)| word.as_str()).collect();
+    let c: Vec<usize> = rest.iter().map(|(column, _)| *column).collect();
+    // operands are lists of words, or of operand keywords put at the column of a word
+    let at = |n: usize| vec![(c[n], w[n])];
+    let key = |n: usize, keyword: &'static str| vec![(c[n], keyword)];
+    let (mnemonic, operands) = match w[..] {
+        ["clear"] => ("CLS", vec![]),
+        ["return"] => ("RET", vec![]),
+        ["scroll-right"] => ("SCR", vec![]),
+        ["scroll-left"] => ("SCL", vec![]),
+        ["exit"] => ("EXIT", vec![]),
+        ["lores"] => ("LOW", vec![]),
+        ["hires"] => ("HIGH", vec![]),
+        ["audio"] => ("AUDIO", vec![]),
+        ["include", _] => ("include", vec![at(1)]),
+        ["scroll-down", _] => ("SCD", vec![at(1)]),
+        ["scroll-up", _] => ("SCU", vec![at(1)]),
+        ["jump", _] => ("JP", vec![at(1)]),
+        ["jump0", _] => ("JP", vec![key(0, "V0"), at(1)]),
+        [":call", _] => ("CALL", vec![at(1)]),
+        ["if", _, "!=", _, "then"] => ("SE", vec![at(1), at(3)]),
+        ["if", _, "==", _, "then"] => ("SNE", vec![at(1), at(3)]),
+        ["if", _, "-key", "then"] => ("SKP", vec![at(1)]),
+        ["if", _, "key", "then"] => ("SKNP", vec![at(1)]),
+        ["save", _, "-", _] => ("SAVE", vec![at(1), at(3)]),
+        ["load", _, "-", _] => ("LOAD", vec![at(1), at(3)]),
+        ["save", _] => ("LD", vec![key(0, "[I]"), at(1)]),
+        ["load", _] => ("LD", vec![at(1), key(0, "[I]")]),
+        ["saveflags", _] => ("LD", vec![key(0, "R"), at(1)]),
+        ["loadflags", _] => ("LD", vec![at(1), key(0, "R")]),
+        ["bcd", _] => ("LD", vec![key(0, "B"), at(1)]),
+        ["plane", _] => ("PLANE", vec![at(1)]),
+        ["sprite", _, _, _] => ("DRW", vec![at(1), at(2), at(3)]),
+        ["delay", ":=", _] => ("LD", vec![key(0, "DT"), at(2)]),
+        ["buzzer", ":=", _] => ("LD", vec![key(0, "ST"), at(2)]),
+        ["pitch", ":=", _] => ("PITCH", vec![at(2)]),
+        ["i", ":=", "long", _] => ("LD", vec![key(0, "I"), vec![(c[2], "LONG"), (c[3], w[3])]]),
+        ["i", ":=", "hex", _] => ("LD", vec![key(0, "F"), at(3)]),
+        ["i", ":=", "bighex", _] => ("LD", vec![key(0, "HF"), at(3)]),
+        ["i", ":=", _] => ("LD", vec![key(0, "I"), at(2)]),
+        ["i", "+=", _] => ("ADD", vec![key(0, "I"), at(2)]),
+        [_, ":=", "random", _] => ("RND", vec![at(0), at(3)]),
+        [_, ":=", "delay"] => ("LD", vec![at(0), key(2, "DT")]),
+        [_, ":=", "key"] => ("LD", vec![at(0), key(2, "K")]),
+        _ if w.iter().all(|word| is_octo_number(word)) => ("DB", (0..w.len()).map(at).collect()),
+        [_, operator, _] => match octo_operator(operator) {
+            Some(mnemonic) => (mnemonic, vec![at(0), at(2)]),
+            None => return Err((c[1], format!("unknown operator {}", operator))),
+        },
+        _ => return Err((c[0], format!("unknown statement {}", w.join(" ")))),
+    };
+
+    tokens.push(Spanned {
+        token: Token::Ident(mnemonic.to_string()),
+        column: c[0],
+    });
+    for (n, operand) in operands.into_iter().enumerate() {
+        if n > 0 {
+            tokens.push(Spanned {
+                token: Token::Symbol(","),
+                column: operand[0].0,
+            });
+        }
+        for (column, part) in operand {
+            tokens.extend(octo_part(column, part)?);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Cowgod mnemonic of the Octo operator between two operands, like `+=` for `ADD`.
+fn octo_operator(operator: &str) -> Option<&'static str> {
+    Some(match operator {
+        ":=" => "LD",
+        "|=" => "OR",
+        "&=" => "AND",
+        "^=" => "XOR",
+        "+=" => "ADD",
+        "-=" => "SUB",
+        ">>=" => "SHR",
+        "=-" => "SUBN",
+        "<<=" => "SHL",
+        _ => return None,
+    })
+}
+
+fn is_octo_number(word: &str) -> bool {
+    word.trim_start_matches('-')
+        .starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Tokens of one Octo word at `column`, which is a name, a number or an operand keyword.
+fn octo_part(column: usize, word: &str) -> Result<Vec<Spanned>, (usize, String)> {
+    let shift = |at: usize| column + at - 1;
+    let mut tokens = tokenize(word).map_err(|(at, message)| (shift(at), message))?;
+    for token in &mut tokens {
+        token.column = shift(token.column);
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Name(String, usize),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>, usize),
+}
+
+/// Precedence climbing parser over the tokens of one operand.
+struct Parser<'a> {
+    tokens: &'a [Spanned],
+    position: usize,
+    end_column: usize,
+}
+
+impl<'a> Parser<'a> {
+    const LEVELS: [&'static [&'static str]; 6] = [
+        &["|"],
+        &["^"],
+        &["&"],
+        &["<<", ">>"],
+        &["+", "-"],
+        &["*", "/", "%"],
+    ];
+
+    fn parse(tokens: &'a [Spanned], end_column: usize) -> Result<Expr, (usize, String)> {
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            end_column,
+        };
+        let expr = parser.binary(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expr),
+            Some(token) => Err((token.column, "unexpected token in expression".to_string())),
+        }
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end_column, |token| token.column)
+    }
+
+    fn symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Spanned {
+                token: Token::Symbol(symbol),
+                ..
+            }) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, (usize, String)> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = self.symbol().filter(|s| Self::LEVELS[level].contains(s)) {
+            let column = self.column();
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right), column);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, (usize, String)> {
+        if let Some(operator) = self.symbol().filter(|s| ["-", "+", "~"].contains(s)) {
+            self.position += 1;
+            return Ok(Expr::Unary(operator, Box::new(self.unary()?)));
+        }
+        let column = self.column();
+        let token = self.tokens.get(self.position).map(|t| t.token.clone());
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Ident(name)) => Ok(Expr::Name(name, column)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.binary(0)?;
+                if self.symbol() != Some(")") {
+                    return Err((self.column(), "expected )".to_string()));
+                }
+                self.position += 1;
+                Ok(expr)
+            }
+            _ => Err((column, "expected an expression".to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    V(u16),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr),
+}
+
+struct Statement {
+    line: usize,
+    column: usize,
+    mnemonic: String,
+    operands: Vec<(Operand, usize)>,
+}
+
+struct Assembler {
+    lines: Vec<SourceLine>,
+    labels: BTreeMap<String, i64>,
+    constants: BTreeMap<String, (Expr, usize)>,
+    statements: Vec<Statement>,
+}
+
+fn register(name: &str) -> Option<u16> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+            digit.to_digit(16).map(|x| x as u16)
+        }
+        _ => None,
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    register(name).is_some() || RESERVED.iter().any(|r| r.eq_ignore_ascii_case(name))
+}
+
+impl Assembler {
+    fn new(lines: Vec<SourceLine>) -> Self {
+        Assembler {
+            lines,
+            labels: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            statements: Vec::new(),
+        }
+    }
+
+    /// First pass: define labels and constants and work out where every statement goes.
+    /// Second pass: evaluate the operands and encode.
+    fn run(mut self) -> Result<(Vec<u8>, SourceMap), AssemblyError> {
+        let mut address = PROGRAM_START as i64;
+        for index in 0..self.lines.len() {
+            let line = &self.lines[index];
+            let mut tokens = &line.tokens[..];
+            if let [Spanned {
+                token: Token::Ident(name),
+                column,
+            }, Spanned {
+                token: Token::Symbol(":"),
+                ..
+            }, ..] = tokens
+            {
+                self.define(index, name, *column)?;
+                self.labels.insert(name.clone(), address);
+                tokens = &tokens[2..];
+            }
+            let (mnemonic, column) = match tokens.first() {
+                None => continue,
+                Some(Spanned {
+                    token: Token::Ident(name),
+                    column,
+                }) => (name.clone(), *column),
+                Some(token) => return Err(line.error(token.column, "expected an instruction")),
+            };
+            let is_constant = match tokens.get(1).map(|t| &t.token) {
+                Some(Token::Ident(word)) => word.eq_ignore_ascii_case("equ"),
+                Some(Token::Symbol("=")) => true,
+                _ => false,
+            };
+            if is_constant {
+                self.define(index, &mnemonic, column)?;
+                let end_column = self.end_column(index);
+                let line = &self.lines[index];
+                let expr = Parser::parse(&tokens[2..], end_column)
+                    .map_err(|(column, message)| line.error(column, message))?;
+                self.constants.insert(mnemonic, (expr, index));
+                continue;
+            }
+            let operands = self.operands(index, &tokens[1..])?;
+            let statement = Statement {
+                line: index,
+                column,
+                mnemonic: mnemonic.to_ascii_uppercase(),
+                operands,
+            };
+            address += size(&statement) as i64;
+            self.statements.push(statement);
+        }
+
+        let mut rom = Vec::new();
+        let mut map = SourceMap::default();
+        for statement in &self.statements {
+            let start = rom.len();
+            self.encode(statement, &mut rom)?;
+            let line = &self.lines[statement.line];
+            map.entries.push(SourceMapEntry {
+                address: (PROGRAM_START + start) as u16,
+                length: (rom.len() - start) as u16,
+                file: line.file.to_string(),
+                line: line.number,
+            });
+        }
+        Ok((rom, map))
+    }
+
+    fn end_column(&self, index: usize) -> usize {
+        self.lines[index]
+            .tokens
+            .last()
+            .map_or(1, |token| token.column + 1)
+    }
+
+    fn define(&self, index: usize, name: &str, column: usize) -> Result<(), AssemblyError> {
+        let line = &self.lines[index];
+        if is_reserved(name) {
+            return Err(line.error(column, format!("{} is a reserved name", name)));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return Err(line.error(column, format!("{} is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn operands(
+        &self,
+        index: usize,
+        tokens: &[Spanned],
+    ) -> Result<Vec<(Operand, usize)>, AssemblyError> {
+        let line = &self.lines[index];
+        let end_column = self.end_column(index);
+        let mut operands = Vec::new();
+        if tokens.is_empty() {
+            return Ok(operands);
+        }
+        let mut parts = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            match token.token {
+                Token::Symbol("(") | Token::Symbol("[") => depth += 1,
+                Token::Symbol(")") | Token::Symbol("]") => depth -= 1,
+                Token::Symbol(",") if depth == 0 => {
+                    parts.push((&tokens[start..i], token.column));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        parts.push((&tokens[start..], end_column));
+        for (part, end) in parts {
+            let column = match part.first() {
+                Some(token) => token.column,
+                None => return Err(line.error(end, "missing operand")),
+            };
+            let operand = operand(part, end_column)
+                .map_err(|(column, message)| line.error(column, message))?;
+            operands.push((operand, column));
+        }
+        Ok(operands)
+    }
+
+    fn value(
+        &self,
+        expr: &Expr,
+        index: usize,
+        visiting: &mut BTreeSet<String>,
+    ) -> Result<i64, AssemblyError> {
+        let line = &self.lines[index];
+        Ok(match expr {
+            Expr::Number(value) => *value,
+            Expr::Name(name, column) => {
+                if let Some(address) = self.labels.get(name) {
+                    *address
+                } else if let Some((expr, defined)) = self.constants.get(name) {
+                    if !visiting.insert(name.clone()) {
+                        return Err(
+                            line.error(*column, format!("{} is defined in terms of itself", name))
+                        );
+                    }
+                    let value = self.value(expr, *defined, visiting)?;
+                    visiting.remove(name);
+                    value
+                } else {
+                    return Err(line.error(*column, format!("unknown symbol {}", name)));
+                }
+            }
+            Expr::Unary(operator, operand) => {
+                let value = self.value(operand, index, visiting)?;
+                match *operator {
+                    "-" => value.wrapping_neg(),
+                    "~" => !value,
+                    _ => value,
+                }
+            }
+            Expr::Binary(operator, left, right, column) => {
+                let left = self.value(left, index, visiting)?;
+                let right = self.value(right, index, visiting)?;
+                match *operator {
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" | "%" if right == 0 => return Err(line.error(*column, "division by zero")),
+                    "/" => left / right,
+                    "%" => left % right,
+                    "&" => left & right,
+                    "|" => left | right,
+                    "^" => left ^ right,
+                    "<<" => left.wrapping_shl(right as u32),
+                    _ => left.wrapping_shr(right as u32),
+                }
+            }
+        })
+    }
+
+    /// Evaluate `expr` and check that it lies within `min..=max`.
+    fn checked(
+        &self,
+        expr: &Expr,
+        column: usize,
+        index: usize,
+        min: i64,
+        max: i64,
+    ) -> Result<u16, AssemblyError> {
+        let value = self.value(expr, index, &mut BTreeSet::new())?;
+        if value < min || value > max {
+            return Err(self.lines[index].error(
+                column,
+                format!("value {} is out of range {}..={}", value, min, max),
+            ));
+        }
+        Ok((value & 0xFFFF) as u16)
+    }
+
+    fn encode(&self, statement: &Statement, rom: &mut Vec<u8>) -> Result<(), AssemblyError> {
+        use Operand::*;
+        let index = statement.line;
+        let line = &self.lines[index];
+        let operands = &statement.operands;
+        let shapes: Vec<&Operand> = operands.iter().map(|(operand, _)| operand).collect();
+        let column = |n: usize| operands[n].1;
+        let address_of = |n: usize, expr: &Expr| self.checked(expr, column(n), index, 0, 0xFFF);
+        let byte = |n: usize, expr: &Expr| self.checked(expr, column(n), index, -128, 0xFF);
+        let nibble = |n: usize, expr: &Expr| self.checked(expr, column(n), index, 0, 0xF);
+
+        match statement.mnemonic.as_str() {
+            "DB" | "DW" => {
+                if operands.is_empty() {
+                    return Err(line.error(statement.column, "missing operand"));
+                }
+                for (n, (operand, _)) in operands.iter().enumerate() {
+                    let expr = match operand {
+                        Value(expr) => expr,
+                        _ => return Err(line.error(column(n), "expected a value")),
+                    };
+                    if statement.mnemonic == "DB" {
+                        rom.push(byte(n, expr)? as u8);
+                    } else {
+                        let word = self.checked(expr, column(n), index, -0x8000, 0xFFFF)?;
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let opcode: u16 = match (statement.mnemonic.as_str(), &shapes[..]) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("AUDIO", []) => 0xF002,
+            ("SCD", [Value(n)]) => 0x00C0 | nibble(0, n)?,
+            ("SCU", [Value(n)]) => 0x00D0 | nibble(0, n)?,
+            ("SYS", [Value(a)]) => address_of(0, a)?,
+            ("JP", [Value(a)]) => 0x1000 | address_of(0, a)?,
+            ("JP", [V(0), Value(a)]) => 0xB000 | address_of(1, a)?,
+            ("CALL", [Value(a)]) => 0x2000 | address_of(0, a)?,
+            ("SE", [V(x), Value(kk)]) => 0x3000 | x << 8 | byte(1, kk)? & 0xFF,
+            ("SNE", [V(x), Value(kk)]) => 0x4000 | x << 8 | byte(1, kk)? & 0xFF,
+            ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+            ("SAVE", [V(x), V(y)]) => 0x5002 | x << 8 | y << 4,
+            ("LOAD", [V(x), V(y)]) => 0x5003 | x << 8 | y << 4,
+            ("LD", [V(x), Value(kk)]) => 0x6000 | x << 8 | byte(1, kk)? & 0xFF,
+            ("ADD", [V(x), Value(kk)]) => 0x7000 | x << 8 | byte(1, kk)? & 0xFF,
+            ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+            ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+            ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+            ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+            ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+            ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+            ("SHR", [V(x)]) => 0x8006 | x << 8,
+            ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+            ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+            ("SHL", [V(x)]) => 0x800E | x << 8,
+            ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+            ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+            ("LD", [I, Value(a)]) => 0xA000 | address_of(1, a)?,
+            ("RND", [V(x), Value(kk)]) => 0xC000 | x << 8 | byte(1, kk)? & 0xFF,
+            ("DRW", [V(x), V(y), Value(n)]) => 0xD000 | x << 8 | y << 4 | nibble(2, n)?,
+            ("SKP", [V(x)]) => 0xE09E | x << 8,
+            ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+            ("LD", [I, Long(a)]) => {
+                let target = self.checked(a, column(1), index, 0, 0xFFFF)?;
+                rom.extend_from_slice(&[0xF0, 0x00]);
+                rom.extend_from_slice(&target.to_be_bytes());
+                return Ok(());
+            }
+            ("PLANE", [Value(n)]) => 0xF001 | nibble(0, n)? << 8,
+            ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+            ("LD", [V(x), K]) => 0xF00A | x << 8,
+            ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+            ("LD", [St, V(x)]) => 0xF018 | x << 8,
+            ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+            ("LD", [F, V(x)]) => 0xF029 | x << 8,
+            ("LD", [Hf, V(x)]) => 0xF030 | x << 8,
+            ("LD", [B, V(x)]) => 0xF033 | x << 8,
+            ("PITCH", [V(x)]) => 0xF03A | x << 8,
+            ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+            ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+            ("LD", [R, V(x)]) => 0xF075 | x << 8,
+            ("LD", [V(x), R]) => 0xF085 | x << 8,
+            (mnemonic, _) if is_mnemonic(mnemonic) => {
+                return Err(line.error(
+                    statement.column,
+                    format!("invalid operands for {}", mnemonic),
+                ))
+            }
+            (mnemonic, _) => {
+                return Err(line.error(
+                    statement.column,
+                    format!("unknown instruction {}", mnemonic),
+                ))
+            }
+        };
+        rom.extend_from_slice(&opcode.to_be_bytes());
+        Ok(())
+    }
+}
+
+const MNEMONICS: [&str; 34] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "SYS", "JP", "CALL",
+    "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL",
+    "RND", "DRW", "SKP", "SKNP", "PLANE", "PITCH", "DB", "DW",
+];
+
+fn is_mnemonic(name: &str) -> bool {
+    MNEMONICS.contains(&name)
+}
+
+/// Number of bytes a statement assembles to, which never depends on the values of its operands.
+fn size(statement: &Statement) -> usize {
+    match statement.mnemonic.as_str() {
+        "DB" => statement.operands.len(),
+        "DW" => statement.operands.len() * 2,
+        _ if statement
+            .operands
+            .iter()
+            .any(|(operand, _)| matches!(operand, Operand::Long(_))) =>
+        {
+            4
+        }
+        _ => 2,
+    }
+}
+
+fn operand(tokens: &[Spanned], end_column: usize) -> Result<Operand, (usize, String)> {
+    let keyword = |name: &str| match name.to_ascii_uppercase().as_str() {
+        "I" => Some(Operand::I),
+        "DT" => Some(Operand::Dt),
+        "ST" => Some(Operand::St),
+        "K" => Some(Operand::K),
+        "F" => Some(Operand::F),
+        "HF" => Some(Operand::Hf),
+        "B" => Some(Operand::B),
+        "R" => Some(Operand::R),
+        _ => register(name).map(Operand::V),
+    };
+    match tokens {
+        [Spanned {
+            token: Token::Ident(name),
+            ..
+        }] => {
+            if let Some(operand) = keyword(name) {
+                return Ok(operand);
+            }
+        }
+        [Spanned {
+            token: Token::Symbol("["),
+            ..
+        }, Spanned {
+            token: Token::Ident(name),
+            ..
+        }, Spanned {
+            token: Token::Symbol("]"),
+            ..
+        }] if name.eq_ignore_ascii_case("i") => return Ok(Operand::IndirectI),
+        [Spanned {
+            token: Token::Ident(name),
+            ..
+        }, rest @ ..]
+            if name.eq_ignore_ascii_case("long") =>
+        {
+            return Ok(Operand::Long(Parser::parse(rest, end_column)?));
+        }
+        _ => {}
+    }
+    Ok(Operand::Value(Parser::parse(tokens, end_column)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disassembler::{mnemonic, Disassembly, Syntax};
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            SPEED EQU 2 * 3       ; constants may use expressions
+            start:
+                LD V0, SPEED + 1
+                LD I, sprite
+                DRW V0, V1, 4
+                JP start
+            sprite:
+                db 0xF0, #90, 0b1001_0000, -1
+                dw sprite, $ABCD
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            rom,
+            [
+                0x60, 0x07, 0xa2, 0x08, 0xd0, 0x14, 0x12, 0x00, 0xf0, 0x90, 0x90, 0xff, 0x02, 0x08,
+                0xab, 0xcd
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = "start:\n    LD I, long data\n\n    JP start\ndata: db 1, 2, 3\n";
+        let (rom, map) = assemble_with_map(source, Syntax::Cowgod).unwrap();
+        assert_eq!(rom.len(), 9);
+        let lines: Vec<_> = map
+            .entries
+            .iter()
+            .map(|e| (e.address, e.length, e.line))
+            .collect();
+        assert_eq!(lines, [(0x200, 4, 2), (0x204, 2, 4), (0x206, 3, 5)]);
+        assert_eq!(map.lookup(0x207).unwrap().line, 5);
+        assert_eq!(map.lookup(0x209), None);
+
+        let text = map.to_string();
+        assert!(text.starts_with("0200 0004 2 <input>\n"));
+        assert_eq!(text.parse::<SourceMap>().unwrap(), map);
+        assert!("0200 zz 1 a".parse::<SourceMap>().is_err());
+    }
+
+    fn assemble_in(source: &str, syntax: Syntax) -> Result<Vec<u8>, AssemblyError> {
+        assemble_with_map(source, syntax).map(|(rom, _)| rom)
+    }
+
+    #[test]
+    fn test_round_trip_every_instruction() {
+        for &syntax in &[Syntax::Cowgod, Syntax::Octo] {
+            for op in 0..=0xFFFFu32 {
+                let bytes = (op as u16).to_be_bytes();
+                if let Some(text) = mnemonic(&bytes, syntax) {
+                    assert_eq!(assemble_in(&text, syntax).unwrap(), bytes, "{}", text);
+                }
+            }
+            let long = [0xf0, 0x00, 0x12, 0x34];
+            let text = mnemonic(&long, syntax).unwrap();
+            assert_eq!(assemble_in(&text, syntax).unwrap(), long);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_listing() {
+        let rom = [
+            0x00, 0xe0, 0xa2, 0x0e, 0x22, 0x0a, 0x3a, 0x01, 0x12, 0x06, 0x00, 0xee, 0x00, 0xfd,
+            0xf0, 0x90, 0x90, 0xf0, 0x81, 0xf0, 0x00, 0x02, 0x0e, 0xb2, 0x0a,
+        ];
+        for &syntax in &[Syntax::Cowgod, Syntax::Octo] {
+            let listing = Disassembly::new(&rom).listing(syntax);
+            assert_eq!(assemble_in(&listing, syntax).unwrap(), rom, "{}", listing);
+        }
+    }
+
+    #[test]
+    fn test_octo() {
+        let source = "
+            : start              # labels come first
+                v0 := 0x07
+                i := sprite
+                sprite v0 v1 4
+                if v0 != 7 then
+                jump start
+            : sprite 0xF0 0x90 -1
+        ";
+        assert_eq!(
+            assemble_in(source, Syntax::Octo).unwrap(),
+            [0x60, 0x07, 0xa2, 0x0a, 0xd0, 0x14, 0x30, 0x07, 0x12, 0x00, 0xf0, 0x90, 0xff]
+        );
+        let err = assemble_in("  v0 %= v1", Syntax::Octo).unwrap_err();
+        assert_eq!(
+            (err.column, err.message.as_str()),
+            (6, "unknown operator %=")
+        );
+        let err = assemble_in("  v0 := 0x100", Syntax::Octo).unwrap_err();
+        assert_eq!(err.column, 9);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str| assemble(source).unwrap_err();
+        assert_eq!(
+            error("CLS\n  LD V0, missing").to_string(),
+            "<input>:2:10: unknown symbol missing"
+        );
+        let err = error("  LD V0, 256");
+        assert_eq!((err.line, err.column), (1, 10));
+        assert_eq!(err.message, "value 256 is out of range -128..=255");
+        assert_eq!(error("  FOO V0").message, "unknown instruction FOO");
+        assert_eq!(error("  LD DT, 3").message, "invalid operands for LD");
+        assert_eq!(error("a:\na:").message, "a is already defined");
+        assert_eq!(error("dt: CLS").message, "dt is a reserved name");
+        assert_eq!(
+            error("X EQU Y\nY EQU X\n db X").message,
+            "X is defined in terms of itself"
+        );
+        assert_eq!(error("  include \"missing.asm\"").column, 11);
+        assert_eq!(error("  LD V0, (1 + 2").message, "expected )");
+    }
+}
@@ -0,0 +1,84 @@
+use chip_8::emulator::{assemble_file_with_map, Syntax};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: chip8-asm [--syntax cowgod|octo] [-o OUTPUT] [--map FILE] SOURCE";
+
+struct Options {
+    source: String,
+    /// Syntax of the source, the one `chip8-disasm` wrote it in.
+    syntax: Syntax,
+    output: String,
+    /// File receiving the source map, for mapping coverage back to the source.
+    map: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut source = None;
+    let mut syntax = Syntax::Cowgod;
+    let mut output = None;
+    let mut map = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let value = args.next().ok_or("--syntax needs a value")?;
+                syntax = value.parse()?;
+            }
+            "-o" => {
+                let value = args.next().ok_or("-o needs a value")?;
+                output = Some(value.clone());
+            }
+            "--map" => {
+                let value = args.next().ok_or("--map needs a value")?;
+                map = Some(value.clone());
+            }
+            _ if source.is_none() => source = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let source: String = source.ok_or("missing source")?;
+    let output = output.unwrap_or_else(|| {
+        Path::new(&source)
+            .with_extension("ch8")
+            .display()
+            .to_string()
+    });
+    Ok(Options {
+        source,
+        syntax,
+        output,
+        map,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let (rom, map) = match assemble_file_with_map(&options.source, options.syntax) {
+        Ok(assembled) => assembled,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    if let Err(err) = fs::write(&options.output, rom) {
+        eprintln!("failed to write {}: {}", options.output, err);
+        process::exit(1);
+    }
+    if let Some(path) = &options.map {
+        if let Err(err) = fs::write(path, map.to_string()) {
+            eprintln!("failed to write {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use chip_8::emulator::{Disassembly, Syntax};
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "usage: chip8-disasm [--syntax cowgod|octo] ROM";
+
+struct Options {
+    rom_location: String,
+    syntax: Syntax,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_location = None;
+    let mut syntax = Syntax::Cowgod;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let value = args.next().ok_or("--syntax needs a value")?;
+                syntax = value.parse()?;
+            }
+            _ if rom_location.is_none() => rom_location = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let rom_location = rom_location.ok_or("missing ROM")?;
+    Ok(Options {
+        rom_location,
+        syntax,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom_location) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("failed to read {}: {}", options.rom_location, err);
+            process::exit(1);
+        }
+    };
+    print!("{}", Disassembly::new(&rom).listing(options.syntax));
+}
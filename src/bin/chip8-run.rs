@@ -0,0 +1,416 @@
+use chip_8::emulator::{
+    EmulationError, GifRecorder, ImageFormat, KeyScript, Machine, Movie, Palette, Profile,
+    Screenshot, CYCLES_PER_FRAME,
+};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+const USAGE: &str =
+    "usage: chip8-run [--profile vip|chip48|schip|xochip] [--quirk NAME=on|off]... \
+                     [--cycles N] [--frames N] [--until-pc ADDR] [--until-halt] \
+                     [--keys SCRIPT | --movie FILE] [--record-movie FILE] [--dump FILE|-] \
+                     [--gif FILE] [--scale N] [--palette COLOURS] [--golden FILE] ROM
+
+Runs a ROM without a terminal for at most --frames frames, or until the program counter reaches
+ADDR or the program ends in a halt loop, feeding keys from SCRIPT. A movie is replayed with the
+settings it was recorded with, for all of its frames unless --frames is given. The run is
+recorded as a movie with --record-movie and as an animated GIF with --gif. The final screen is
+written to FILE as text, or as an image if FILE ends in .png or .pbm, and compared with the text
+of the golden file. Images are scaled by N and drawn with the four comma separated hex COLOURS
+of unlit pixels, plane 1, plane 2 and both planes.
+Exits with 0 on success, 1 if the screen differs from the golden file or from the end of the
+replayed movie, the stop condition was not reached or the emulation failed, and 2 on other
+errors.";
+
+/// Frames run when no limit is given, 10 seconds of emulated time.
+const DEFAULT_FRAMES: u64 = 600;
+
+struct Options {
+    rom_location: String,
+    profile: Profile,
+    overrides: Vec<String>,
+    cycles_per_frame: usize,
+    frames: Option<u64>,
+    until_pc: Option<u16>,
+    until_halt: bool,
+    keys: Option<String>,
+    movie: Option<String>,
+    record_movie: Option<String>,
+    dump: Option<String>,
+    gif: Option<String>,
+    scale: usize,
+    palette: Palette,
+    golden: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_location = None;
+    let mut profile = Profile::default();
+    let mut overrides = Vec::new();
+    let mut cycles_per_frame = CYCLES_PER_FRAME;
+    let mut frames = None;
+    let mut until_pc = None;
+    let mut until_halt = false;
+    let mut keys = None;
+    let mut movie = None;
+    let mut record_movie = None;
+    let mut dump = None;
+    let mut gif = None;
+    let mut scale = 1;
+    let mut palette = Palette::default();
+    let mut golden = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => {
+                let value = args.next().ok_or("--profile needs a value")?;
+                profile = value.parse()?;
+            }
+            "--quirk" => {
+                let value = args.next().ok_or("--quirk needs a value")?;
+                overrides.push(value.clone());
+            }
+            "--cycles" => {
+                let value = args.next().ok_or("--cycles needs a value")?;
+                cycles_per_frame = value
+                    .parse()
+                    .map_err(|_| format!("invalid cycle count {}", value))?;
+            }
+            "--frames" => {
+                let value = args.next().ok_or("--frames needs a value")?;
+                let count = value
+                    .parse()
+                    .map_err(|_| format!("invalid frame count {}", value))?;
+                frames = Some(count);
+            }
+            "--until-pc" => {
+                let value = args.next().ok_or("--until-pc needs a value")?;
+                let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+                let address = u16::from_str_radix(digits, 16)
+                    .map_err(|_| format!("invalid address {}", value))?;
+                until_pc = Some(address);
+            }
+            "--until-halt" => until_halt = true,
+            "--keys" => {
+                let value = args.next().ok_or("--keys needs a value")?;
+                keys = Some(value.clone());
+            }
+            "--movie" => {
+                let value = args.next().ok_or("--movie needs a value")?;
+                movie = Some(value.clone());
+            }
+            "--record-movie" => {
+                let value = args.next().ok_or("--record-movie needs a value")?;
+                record_movie = Some(value.clone());
+            }
+            "--dump" => {
+                let value = args.next().ok_or("--dump needs a value")?;
+                dump = Some(value.clone());
+            }
+            "--gif" => {
+                let value = args.next().ok_or("--gif needs a value")?;
+                gif = Some(value.clone());
+            }
+            "--scale" => {
+                let value = args.next().ok_or("--scale needs a value")?;
+                scale = match value.parse() {
+                    Ok(scale) if scale > 0 => scale,
+                    _ => return Err(format!("invalid scale {}", value)),
+                };
+            }
+            "--palette" => {
+                let value = args.next().ok_or("--palette needs a value")?;
+                palette = value.parse()?;
+            }
+            "--golden" => {
+                let value = args.next().ok_or("--golden needs a value")?;
+                golden = Some(value.clone());
+            }
+            _ if rom_location.is_none() => rom_location = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let rom_location = rom_location.ok_or("missing ROM")?;
+    if keys.is_some() && movie.is_some() {
+        return Err("--keys and --movie cannot be used together".to_string());
+    }
+    Ok(Options {
+        rom_location,
+        profile,
+        overrides,
+        cycles_per_frame,
+        frames,
+        until_pc,
+        until_halt,
+        keys,
+        movie,
+        record_movie,
+        dump,
+        gif,
+        scale,
+        palette,
+        golden,
+    })
+}
+
+/// Build the machine described by `options` with `rom` loaded.
+fn setup(options: &Options, rom: &[u8]) -> Result<Machine, String> {
+    let mut machine = Machine::with_profile(options.profile);
+    for value in &options.overrides {
+        let mut parts = value.splitn(2, '=');
+        let name = parts.next().unwrap_or_default();
+        let enabled = match parts.next() {
+            Some("on") => true,
+            Some("off") => false,
+            _ => return Err(format!("invalid quirk {}", value)),
+        };
+        if !machine.quirks.set(name, enabled) {
+            return Err(format!("unknown quirk {}", name));
+        }
+    }
+    machine.cycles_per_frame = options.cycles_per_frame;
+    machine
+        .memory
+        .load_bytes(rom)
+        .map_err(|err| format!("failed to load {}: {}", options.rom_location, err))?;
+    Ok(machine)
+}
+
+/// Read a key script or a movie from `path`.
+fn read<T: FromStr<Err = String>>(path: &str) -> Result<T, String> {
+    fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| text.parse())
+        .map_err(|err| format!("failed to read {}: {}", path, err))
+}
+
+/// Where the keys held in every frame come from.
+enum Input {
+    Script(KeyScript),
+    Movie(Movie),
+}
+
+impl Input {
+    fn apply(&self, frame: u64, machine: &mut Machine) {
+        match self {
+            Input::Script(script) => script.apply(frame, machine),
+            Input::Movie(movie) => movie.apply(frame, machine),
+        }
+    }
+}
+
+/// Why a run ended.
+enum Outcome {
+    ReachedPc(u64),
+    Halted(u64),
+    FrameLimit,
+}
+
+/// Run `frames` frames, applying the input at the start of every frame and checking the stop
+/// conditions before every instruction. Every completed frame is recorded into `movie`, and
+/// captured by `recorder` as is the frame a stop condition was reached in.
+fn run(
+    machine: &mut Machine,
+    options: &Options,
+    frames: u64,
+    input: &Input,
+    mut movie: Option<&mut Movie>,
+    mut recorder: Option<&mut GifRecorder>,
+) -> Result<Outcome, EmulationError> {
+    for frame in 0..frames {
+        input.apply(frame, machine);
+        if let Some(movie) = movie.as_deref_mut() {
+            movie.record(machine);
+        }
+        let mut outcome = None;
+        for _ in 0..machine.cycles_per_frame {
+            if options.until_pc == Some(machine.register.pc) {
+                outcome = Some(Outcome::ReachedPc(frame));
+            } else if options.until_halt && machine.is_in_halt_loop() {
+                outcome = Some(Outcome::Halted(frame));
+            }
+            if outcome.is_some() {
+                break;
+            }
+            machine.step()?;
+        }
+        match (&outcome, movie.as_deref_mut()) {
+            (None, Some(movie)) => {
+                machine.end_frame();
+                movie.set_screen(&machine.graphic);
+            }
+            (None, None) => machine.end_frame(),
+            // a movie replays whole frames only
+            (Some(_), Some(movie)) => {
+                movie.keys.pop();
+            }
+            (Some(_), None) => {}
+        }
+        if let Some(recorder) = recorder.as_deref_mut() {
+            recorder.capture(&machine.graphic)?;
+        }
+        if let Some(outcome) = outcome {
+            return Ok(outcome);
+        }
+    }
+    Ok(Outcome::FrameLimit)
+}
+
+fn write_dump(machine: &Machine, options: &Options, path: &str) -> Result<(), String> {
+    if path == "-" {
+        print!("{}", machine.graphic.to_text());
+        return io::stdout().flush().map_err(|err| err.to_string());
+    }
+    let data = match ImageFormat::from_path(path) {
+        Some(format) => {
+            let mut screenshot = Screenshot::new(format);
+            screenshot.scale = options.scale;
+            screenshot.palette = options.palette;
+            screenshot.encode(&machine.graphic)
+        }
+        None => machine.graphic.to_text().into_bytes(),
+    };
+    fs::write(path, data).map_err(|err| format!("failed to write {}: {}", path, err))
+}
+
+/// Compare the screen with the golden file, ignoring trailing whitespace and blank lines.
+/// Returns whether they match, after reporting the first differing row.
+fn compare(machine: &Machine, path: &str) -> Result<bool, String> {
+    let golden =
+        fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+    let screen = machine.graphic.to_text();
+    let rows = |text: &str| -> Vec<String> {
+        text.lines()
+            .map(|line| line.trim_end().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()
+    };
+    let (expected, actual) = (rows(&golden), rows(&screen));
+    if expected == actual {
+        return Ok(true);
+    }
+    match expected.iter().zip(&actual).position(|(a, b)| a != b) {
+        Some(row) => {
+            let pixels = expected[row]
+                .chars()
+                .zip(actual[row].chars())
+                .filter(|(a, b)| a != b)
+                .count();
+            eprintln!(
+                "screen differs from {} in row {} ({} pixels)\n- {}\n+ {}",
+                path, row, pixels, expected[row], actual[row]
+            );
+        }
+        None => eprintln!(
+            "screen has {} rows while {} has {}",
+            actual.len(),
+            path,
+            expected.len()
+        ),
+    }
+    eprint!("actual screen:\n{}", screen);
+    Ok(false)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    let fail = |err: String| -> ! {
+        eprintln!("{}", err);
+        process::exit(2);
+    };
+
+    let rom = fs::read(&options.rom_location)
+        .unwrap_or_else(|err| fail(format!("failed to load {}: {}", options.rom_location, err)));
+    let input = match (&options.keys, &options.movie) {
+        (_, Some(path)) => Input::Movie(read(path).unwrap_or_else(|err| fail(err))),
+        (Some(path), None) => Input::Script(read(path).unwrap_or_else(|err| fail(err))),
+        (None, None) => Input::Script(KeyScript::new()),
+    };
+    let (mut machine, profile, frames) = match &input {
+        Input::Movie(movie) => {
+            let machine = movie.machine(&rom).unwrap_or_else(|err| fail(err));
+            (
+                machine,
+                movie.profile,
+                options.frames.unwrap_or(movie.frames()),
+            )
+        }
+        Input::Script(_) => {
+            let machine = setup(&options, &rom).unwrap_or_else(|err| fail(err));
+            (
+                machine,
+                options.profile,
+                options.frames.unwrap_or(DEFAULT_FRAMES),
+            )
+        }
+    };
+    let mut movie = options
+        .record_movie
+        .as_ref()
+        .map(|_| Movie::new(&rom, profile, &machine));
+
+    let mut recorder = options.gif.as_ref().map(|path| {
+        GifRecorder::create(path, options.scale, &options.palette)
+            .unwrap_or_else(|err| fail(format!("failed to create {}: {}", path, err)))
+    });
+
+    let mut passed = true;
+    let result = run(
+        &mut machine,
+        &options,
+        frames,
+        &input,
+        movie.as_mut(),
+        recorder.as_mut(),
+    );
+    match result {
+        Ok(Outcome::ReachedPc(frame)) => eprintln!("reached PC in frame {}", frame),
+        Ok(Outcome::Halted(frame)) => eprintln!("halted in frame {}", frame),
+        Ok(Outcome::FrameLimit) if options.until_pc.is_some() || options.until_halt => {
+            eprintln!("stop condition not reached in {} frames", frames);
+            passed = false;
+        }
+        Ok(Outcome::FrameLimit) => match &input {
+            Input::Movie(movie)
+                if frames == movie.frames() && !movie.matches_screen(&machine.graphic) =>
+            {
+                eprintln!("screen differs from the end of the movie");
+                passed = false;
+            }
+            _ => {}
+        },
+        Err(err) => {
+            eprintln!("emulation failed at {:03X}: {}", machine.register.pc, err);
+            passed = false;
+        }
+    }
+
+    if let (Some(movie), Some(path)) = (movie, &options.record_movie) {
+        fs::write(path, movie.to_string())
+            .unwrap_or_else(|err| fail(format!("failed to write {}: {}", path, err)));
+    }
+    if let (Some(recorder), Some(path)) = (recorder, &options.gif) {
+        recorder
+            .finish()
+            .unwrap_or_else(|err| fail(format!("failed to write {}: {}", path, err)));
+    }
+    if let Some(path) = &options.dump {
+        write_dump(&machine, &options, path).unwrap_or_else(|err| fail(err));
+    }
+    if let Some(path) = &options.golden {
+        passed &= compare(&machine, path).unwrap_or_else(|err| fail(err));
+    }
+    if !passed {
+        process::exit(1);
+    }
+}
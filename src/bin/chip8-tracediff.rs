@@ -0,0 +1,158 @@
+use chip_8::emulator::TraceLine;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+const USAGE: &str = "usage: chip8-tracediff [--context N] TRACE TRACE
+
+Compares two traces written with chip-8 --trace and reports the first differing cycle.
+Exits with 0 if the traces match, 1 if they differ and 2 on errors.";
+
+/// Lines shown before the first difference by default.
+const DEFAULT_CONTEXT: usize = 5;
+
+struct Options {
+    traces: Vec<String>,
+    context: usize,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut traces = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().ok_or("--context needs a value")?;
+                context = value
+                    .parse()
+                    .map_err(|_| format!("invalid line count {}", value))?;
+            }
+            _ if traces.len() < 2 => traces.push(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    if traces.len() < 2 {
+        return Err("missing trace".to_string());
+    }
+    Ok(Options { traces, context })
+}
+
+/// Trace lines of one file with their line numbers, skipping comments and blank lines.
+struct Trace {
+    name: String,
+    lines: io::Lines<BufReader<File>>,
+    number: usize,
+}
+
+impl Trace {
+    fn open(name: &str) -> Result<Self, String> {
+        let file = File::open(name).map_err(|err| format!("failed to open {}: {}", name, err))?;
+        Ok(Trace {
+            name: name.to_string(),
+            lines: BufReader::new(file).lines(),
+            number: 0,
+        })
+    }
+
+    fn next(&mut self) -> Result<Option<(String, TraceLine)>, String> {
+        while let Some(line) = self.lines.next() {
+            self.number += 1;
+            let line = line.map_err(|err| format!("failed to read {}: {}", self.name, err))?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line
+                .parse()
+                .map_err(|err| format!("{}:{}: {}", self.name, self.number, err))?;
+            return Ok(Some((line, parsed)));
+        }
+        Ok(None)
+    }
+}
+
+/// Compare the traces, printing the first difference. Returns whether they match.
+fn compare(options: &Options) -> Result<bool, String> {
+    let mut left = Trace::open(&options.traces[0])?;
+    let mut right = Trace::open(&options.traces[1])?;
+    let mut context = VecDeque::with_capacity(options.context + 1);
+    let mut cycles = 0;
+    loop {
+        let (a, b) = match (left.next()?, right.next()?) {
+            (None, None) => {
+                println!("traces match for {} cycles", cycles);
+                return Ok(true);
+            }
+            (Some((text, line)), None) => {
+                println!(
+                    "{} ends after {} cycles while {} continues with cycle {}",
+                    right.name, cycles, left.name, line.cycle
+                );
+                print_context(&context);
+                println!("- {}", text);
+                return Ok(false);
+            }
+            (None, Some((text, line))) => {
+                println!(
+                    "{} ends after {} cycles while {} continues with cycle {}",
+                    left.name, cycles, right.name, line.cycle
+                );
+                print_context(&context);
+                println!("+ {}", text);
+                return Ok(false);
+            }
+            (Some(a), Some(b)) => (a, b),
+        };
+        let differences = a.1.differences(&b.1);
+        if !differences.is_empty() {
+            println!(
+                "first difference at cycle {} ({}:{}, {}:{}) in {}",
+                a.1.cycle,
+                left.name,
+                left.number,
+                right.name,
+                right.number,
+                differences.join(", ")
+            );
+            print_context(&context);
+            println!("- {}", a.0);
+            println!("+ {}", b.0);
+            return Ok(false);
+        }
+        cycles += 1;
+        if options.context > 0 {
+            if context.len() == options.context {
+                context.pop_front();
+            }
+            context.push_back(a.0);
+        }
+    }
+}
+
+fn print_context(context: &VecDeque<String>) {
+    for line in context {
+        println!("  {}", line);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match compare(&options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    }
+}
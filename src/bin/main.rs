@@ -1,4 +1,9 @@
-use chip_8::emulator::{Cpu, Graphic, Memory, Register};
+use chip_8::emulator::{
+    frame_duration, parse_color, Coverage, Cpu, DapServer, Debugger, Disassembly, Event,
+    FramePacer, GifRecorder, ImageFormat, Machine, Movie, Profile, Profiler, Quirks, RewindBuffer,
+    Screenshot, SourceMap, Syntax, SystemClock, Tracer, CYCLES_PER_FRAME, DEBUGGER_HELP,
+    REWIND_BUDGET, REWIND_INTERVAL,
+};
 use console::{Console, Keyboard};
 use log::LevelFilter;
 use log4rs::append::file::FileAppender;
@@ -6,18 +11,23 @@ use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use std::collections::HashMap;
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::net::TcpListener;
+use std::process;
 use std::sync::atomic::AtomicBool;
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
 
 struct Keypad {
     keymap: HashMap<char, u8>,
-    bus: Arc<Mutex<mpsc::Sender<u8>>>,
+    bus: Arc<Mutex<mpsc::Sender<Event>>>,
 }
 
 impl Keypad {
-    fn new(bus: Arc<Mutex<mpsc::Sender<u8>>>) -> Self {
+    fn new(bus: Arc<Mutex<mpsc::Sender<Event>>>) -> Self {
         let map: [(char, u8); 16] = [
             ('1', 0x1),
             ('2', 0x2),
@@ -43,13 +53,21 @@ impl Keypad {
 
 impl Keyboard for Keypad {
     fn press(&self, key: char) {
-        match self.keymap.get(&key) {
-            Some(value) => {
-                let bus = self.bus.lock().unwrap();
-                bus.send(*value).unwrap();
-            }
-            None => { /* do nothing */ }
-        }
+        let event = match (self.keymap.get(&key), key) {
+            (Some(value), _) => Event::Tap(*value),
+            (None, 't') => Event::ToggleTurbo,
+            (None, 'm') => Event::ToggleSlowMotion,
+            (None, 'p') => Event::TogglePause,
+            (None, '5'..='9') => Event::SelectSlot(key as u8 - b'5'),
+            (None, 'o') => Event::SaveState,
+            (None, 'l') => Event::LoadState,
+            (None, 'b') => Event::Rewind,
+            (None, 'i') => Event::Screenshot,
+            (None, 'g') => Event::ToggleRecording,
+            _ => return,
+        };
+        let bus = self.bus.lock().unwrap();
+        bus.send(event).unwrap();
     }
 }
 
@@ -66,32 +84,531 @@ fn setup_logger() {
     log4rs::init_config(config).unwrap();
 }
 
+const USAGE: &str = "usage: chip-8 [--profile vip|chip48|schip|xochip] [--quirk NAME=on|off]... \
+                     [--cycles N] [--trace FILE] \
+                     [--rewind-interval FRAMES] [--rewind-memory MIB] [--profile-report FILE|-] \
+                     [--coverage FILE] [--lcov FILE [--source-map FILE]] [--screenshot FILE] \
+                     [--screenshot-format png|pbm] [--scale N] [--fg COLOUR] [--bg COLOUR] \
+                     [--palette COLOURS] [--gif FILE] [--record-movie FILE | --movie FILE] [--debug] ROM
+       chip-8 [--profile ...] [--quirk ...]... [--cycles N] --dap|--dap-port PORT
+
+hotkeys: t turbo, m slow motion, p pause, 5-9 select save slot, o save state, l load state, \
+                     hold b to rewind, i screenshot, g start/stop GIF recording";
+
+/// Where the Debug Adapter Protocol server talks to its client.
+enum Dap {
+    Stdio,
+    Tcp(u16),
+}
+
+struct Options {
+    rom_location: String,
+    profile: Profile,
+    quirks: Quirks,
+    cycles_per_frame: usize,
+    debug: bool,
+    dap: Option<Dap>,
+    /// File receiving a trace of every executed instruction.
+    trace: Option<String>,
+    rewind_interval: u32,
+    /// Memory for rewind snapshots in bytes, 0 disables rewinding.
+    rewind_budget: usize,
+    /// File receiving the profiler report on exit, `-` for stdout.
+    profile_report: Option<String>,
+    /// File receiving the disassembly annotated with coverage on exit.
+    coverage: Option<String>,
+    /// File receiving the coverage in lcov format on exit.
+    lcov: Option<String>,
+    /// Source map written by `chip8-asm --map`, for reporting lcov coverage per source line.
+    source_map: Option<String>,
+    /// Format, scale and colours of screenshots.
+    screenshot: Screenshot,
+    /// File receiving a screenshot of the last frame on exit.
+    final_screenshot: Option<String>,
+    /// File receiving a GIF recording of the whole session.
+    gif: Option<String>,
+    /// File receiving a movie of the whole session.
+    record_movie: Option<String>,
+    /// Movie replayed at the start of the session, whose settings replace the profile, quirks
+    /// and cycles given.
+    movie: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_location = None;
+    let mut profile = Profile::default();
+    let mut overrides = Vec::new();
+    let mut cycles_per_frame = CYCLES_PER_FRAME;
+    let mut debug = false;
+    let mut dap = None;
+    let mut trace = None;
+    let mut rewind_interval = REWIND_INTERVAL;
+    let mut rewind_budget = REWIND_BUDGET;
+    let mut profile_report = None;
+    let mut coverage = None;
+    let mut lcov = None;
+    let mut source_map = None;
+    let mut screenshot = Screenshot::new(ImageFormat::Png);
+    let mut final_screenshot = None;
+    let mut gif = None;
+    let mut record_movie = None;
+    let mut movie = None;
+    let mut foreground = None;
+    let mut background = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => {
+                let value = args.next().ok_or("--profile needs a value")?;
+                profile = value.parse()?;
+            }
+            "--quirk" => {
+                let value = args.next().ok_or("--quirk needs a value")?;
+                overrides.push(value);
+            }
+            "--cycles" => {
+                let value = args.next().ok_or("--cycles needs a value")?;
+                cycles_per_frame = value
+                    .parse()
+                    .map_err(|_| format!("invalid cycle count {}", value))?;
+            }
+            "--trace" => {
+                let value = args.next().ok_or("--trace needs a value")?;
+                trace = Some(value.clone());
+            }
+            "--rewind-interval" => {
+                let value = args.next().ok_or("--rewind-interval needs a value")?;
+                rewind_interval = match value.parse() {
+                    Ok(frames) if frames > 0 => frames,
+                    _ => return Err(format!("invalid frame count {}", value)),
+                };
+            }
+            "--rewind-memory" => {
+                let value = args.next().ok_or("--rewind-memory needs a value")?;
+                let mib: usize = value
+                    .parse()
+                    .map_err(|_| format!("invalid memory size {}", value))?;
+                rewind_budget = mib * 1024 * 1024;
+            }
+            "--profile-report" => {
+                let value = args.next().ok_or("--profile-report needs a value")?;
+                profile_report = Some(value.clone());
+            }
+            "--coverage" => {
+                let value = args.next().ok_or("--coverage needs a value")?;
+                coverage = Some(value.clone());
+            }
+            "--lcov" => {
+                let value = args.next().ok_or("--lcov needs a value")?;
+                lcov = Some(value.clone());
+            }
+            "--source-map" => {
+                let value = args.next().ok_or("--source-map needs a value")?;
+                source_map = Some(value.clone());
+            }
+            "--screenshot" => {
+                let value = args.next().ok_or("--screenshot needs a value")?;
+                final_screenshot = Some(value.clone());
+            }
+            "--screenshot-format" => {
+                let value = args.next().ok_or("--screenshot-format needs a value")?;
+                screenshot.format = value.parse()?;
+            }
+            "--scale" => {
+                let value = args.next().ok_or("--scale needs a value")?;
+                screenshot.scale = match value.parse() {
+                    Ok(scale) if scale > 0 => scale,
+                    _ => return Err(format!("invalid scale {}", value)),
+                };
+            }
+            "--fg" => {
+                let value = args.next().ok_or("--fg needs a value")?;
+                foreground = Some(parse_color(value)?);
+            }
+            "--bg" => {
+                let value = args.next().ok_or("--bg needs a value")?;
+                background = Some(parse_color(value)?);
+            }
+            "--palette" => {
+                let value = args.next().ok_or("--palette needs a value")?;
+                screenshot.palette = value.parse()?;
+            }
+            "--gif" => {
+                let value = args.next().ok_or("--gif needs a value")?;
+                gif = Some(value.clone());
+            }
+            "--record-movie" => {
+                let value = args.next().ok_or("--record-movie needs a value")?;
+                record_movie = Some(value.clone());
+            }
+            "--movie" => {
+                let value = args.next().ok_or("--movie needs a value")?;
+                movie = Some(value.clone());
+            }
+            "--debug" => debug = true,
+            "--dap" => dap = Some(Dap::Stdio),
+            "--dap-port" => {
+                let value = args.next().ok_or("--dap-port needs a value")?;
+                let port = value
+                    .parse()
+                    .map_err(|_| format!("invalid port {}", value))?;
+                dap = Some(Dap::Tcp(port));
+            }
+            _ if rom_location.is_none() => rom_location = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    let mut quirks = profile.quirks();
+    for value in overrides {
+        let mut parts = value.splitn(2, '=');
+        let name = parts.next().unwrap_or_default();
+        let enabled = match parts.next() {
+            Some("on") => true,
+            Some("off") => false,
+            _ => return Err(format!("invalid quirk {}", value)),
+        };
+        if !quirks.set(name, enabled) {
+            return Err(format!("unknown quirk {}", name));
+        }
+    }
+
+    if let Some(color) = foreground {
+        screenshot.palette.set_foreground(color);
+    }
+    if let Some(color) = background {
+        screenshot.palette.set_background(color);
+    }
+
+    if debug && gif.is_some() {
+        return Err("--gif cannot be used with --debug".to_string());
+    }
+    if debug && (record_movie.is_some() || movie.is_some()) {
+        return Err("movies cannot be used with --debug".to_string());
+    }
+    if record_movie.is_some() && movie.is_some() {
+        return Err("--record-movie and --movie cannot be used together".to_string());
+    }
+
+    // with --dap the ROM comes from the launch request
+    let rom_location = match rom_location {
+        Some(rom_location) => rom_location,
+        None if dap.is_some() => String::new(),
+        None => return Err("missing ROM".to_string()),
+    };
+    Ok(Options {
+        rom_location,
+        profile,
+        quirks,
+        cycles_per_frame,
+        debug,
+        dap,
+        trace,
+        rewind_interval,
+        rewind_budget,
+        profile_report,
+        coverage,
+        lcov,
+        source_map,
+        screenshot,
+        final_screenshot,
+        gif,
+        record_movie,
+        movie,
+    })
+}
+
+/// Run the machine under the debugger, reading commands from stdin and printing the screen and
+/// the state panels after every stop. Returns the machine once the user quits.
+fn debug(machine: Machine) -> Machine {
+    let mut debugger = Debugger::new(machine);
+    let (line_sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let sent = line.map(|line| line_sender.send(line).is_ok());
+            if sent.ok() != Some(true) {
+                break;
+            }
+        }
+    });
+
+    println!("{}\n{}", debugger.view(), DEBUGGER_HELP);
+    let mut pacer = FramePacer::new(SystemClock::new());
+    loop {
+        if debugger.is_running() {
+            // any input pauses
+            if lines.try_recv().is_ok() {
+                debugger.pause();
+                println!("{}", debugger.view());
+                continue;
+            }
+            thread::sleep(pacer.wait());
+            if let Some(stop) = debugger.run_frame() {
+                println!("{}\n{}", debugger.view(), stop);
+            }
+            pacer.advance(frame_duration());
+            continue;
+        }
+
+        print!("(chip-8) ");
+        io::stdout().flush().unwrap();
+        let line = match lines.recv() {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim() == "q" {
+            break;
+        }
+        let output = debugger.command(&line);
+        if !debugger.is_running() {
+            println!("{}", debugger.view());
+        }
+        match output {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(err) => println!("error: {}", err),
+        }
+        pacer.reset();
+    }
+    debugger.machine
+}
+
+/// Write the report of the profiler of `machine` to `path`, or to stdout if it is `-`.
+fn write_profile_report(machine: &Machine, path: &str) {
+    let profiler = match &machine.profiler {
+        Some(profiler) => profiler,
+        None => return,
+    };
+    let report = profiler.report(&machine.memory.all);
+    if path == "-" {
+        print!("{}", report);
+    } else if let Err(err) = fs::write(path, report) {
+        eprintln!("failed to write {}: {}", path, err);
+    }
+}
+
+/// Write the reports of the coverage of `machine` requested by `options`. The lcov report refers
+/// to the source files of the source map or, without one, to the annotated listing.
+fn write_coverage(machine: &Machine, options: &Options) {
+    let coverage = match &machine.memory.coverage {
+        Some(coverage) => coverage,
+        None => return,
+    };
+    let rom = match fs::read(&options.rom_location) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("failed to read {}: {}", options.rom_location, err);
+            return;
+        }
+    };
+    let disassembly = Disassembly::new(&rom);
+    let mut reports = Vec::new();
+    if let Some(path) = &options.coverage {
+        reports.push((
+            path,
+            coverage.annotated_listing(&disassembly, Syntax::Cowgod),
+        ));
+    }
+    if let Some(path) = &options.lcov {
+        let report = match &options.source_map {
+            Some(map_path) => {
+                let map = fs::read_to_string(map_path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|text| text.parse::<SourceMap>());
+                match map {
+                    Ok(map) => coverage.lcov_with_map(&map),
+                    Err(err) => {
+                        eprintln!("failed to read {}: {}", map_path, err);
+                        return;
+                    }
+                }
+            }
+            None => {
+                let name = options.coverage.as_ref().unwrap_or(&options.rom_location);
+                coverage.lcov(&disassembly, name)
+            }
+        };
+        reports.push((path, report));
+    }
+    for (path, report) in reports {
+        if let Err(err) = fs::write(path, report) {
+            eprintln!("failed to write {}: {}", path, err);
+        }
+    }
+}
+
+/// Write the screen of `machine` to `path`, in the format given by its extension if it has a
+/// known one.
+fn write_screenshot(machine: &Machine, options: &Options, path: &str) {
+    let mut screenshot = options.screenshot;
+    if let Some(format) = ImageFormat::from_path(path) {
+        screenshot.format = format;
+    }
+    if let Err(err) = screenshot.save(&machine.graphic, path) {
+        eprintln!("failed to write {}: {}", path, err);
+    }
+}
+
+/// Read the movie at `path`.
+fn read_movie(path: &str) -> Result<Movie, String> {
+    fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| text.parse())
+        .map_err(|err| format!("failed to read {}: {}", path, err))
+}
+
+fn write_movie(movie: &Movie, path: &str) {
+    if let Err(err) = fs::write(path, movie.to_string()) {
+        eprintln!("failed to write {}: {}", path, err);
+    }
+}
+
+/// Serve one Debug Adapter Protocol client, on stdio or the first connection to the port.
+fn serve_dap(options: &Options, dap: &Dap) -> io::Result<()> {
+    let mut server = DapServer::new();
+    server.profile = options.profile;
+    server.quirks = options.quirks;
+    server.cycles_per_frame = options.cycles_per_frame;
+    match dap {
+        Dap::Stdio => server.serve(io::stdin(), io::stdout()),
+        Dap::Tcp(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", *port))?;
+            let (stream, _) = listener.accept()?;
+            server.serve(stream.try_clone()?, stream)
+        }
+    }
+}
+
 fn main() {
     setup_logger();
 
-    let args: Vec<String> = env::args().collect();
-    assert_eq!(args.len(), 2);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Some(dap) = &options.dap {
+        if let Err(err) = serve_dap(&options, dap) {
+            eprintln!("debug adapter failed: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
 
-    let rom_location = &args[1];
+    let rom_location = &options.rom_location;
+
+    let rom = match fs::read(rom_location) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("failed to load {}: {}", rom_location, err);
+            process::exit(1);
+        }
+    };
+    let mut machine = Machine::with_profile(options.profile);
+    machine.quirks = options.quirks;
+    machine.cycles_per_frame = options.cycles_per_frame;
+    if let Err(err) = machine.memory.load_bytes(&rom) {
+        eprintln!("failed to load {}: {}", rom_location, err);
+        process::exit(1);
+    }
+    let mut replay = None;
+    if let Some(path) = &options.movie {
+        match read_movie(path).and_then(|movie| Ok((movie.machine(&rom)?, movie))) {
+            Ok((movie_machine, movie)) => {
+                machine = movie_machine;
+                replay = Some(movie);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+    let recording = options
+        .record_movie
+        .as_ref()
+        .map(|_| Movie::new(&rom, options.profile, &machine));
+    if let Some(path) = &options.trace {
+        let tracer =
+            File::create(path).and_then(|file| Tracer::new(Box::new(BufWriter::new(file))));
+        match tracer {
+            Ok(tracer) => machine.trace = Some(tracer),
+            Err(err) => {
+                eprintln!("failed to create {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+    if options.rewind_budget > 0 {
+        machine.rewind_buffer = Some(RewindBuffer::new(
+            options.rewind_interval,
+            options.rewind_budget,
+        ));
+    }
+    if options.profile_report.is_some() {
+        machine.profiler = Some(Profiler::new());
+    }
+    if options.coverage.is_some() || options.lcov.is_some() {
+        machine.memory.coverage = Some(Coverage::new(machine.memory.all.len()));
+    }
+    if options.debug {
+        let machine = debug(machine);
+        if let Some(path) = &options.profile_report {
+            write_profile_report(&machine, path);
+        }
+        write_coverage(&machine, &options);
+        if let Some(path) = &options.final_screenshot {
+            write_screenshot(&machine, &options, path);
+        }
+        return;
+    }
 
     let terminated = Arc::new(AtomicBool::new(false));
 
     // graphic setup
     let (graphic_sender, graphic_receiver) = mpsc::channel();
     let graphic_receiver = Arc::new(Mutex::new(graphic_receiver));
-    let graphic = Graphic::new(graphic_sender);
 
-    let (key_event_sender, key_event_receiver) = mpsc::channel();
-    let key_event_sender = Arc::new(Mutex::new(key_event_sender));
-    let keypad = Keypad::new(key_event_sender);
+    let (event_sender, event_receiver) = mpsc::channel();
+    let event_sender = Arc::new(Mutex::new(event_sender));
+    let keypad = Keypad::new(event_sender);
 
-    let mut memory = Memory::new();
-    memory.load(rom_location);
-    let register = Register::new();
-    let mut emulator = Cpu::new(memory, register, graphic, key_event_receiver);
+    let mut emulator = Cpu::new(machine, graphic_sender, event_receiver);
+    emulator.set_state_file(rom_location);
+    emulator.set_screenshot(options.screenshot);
+    if let Some(path) = &options.gif {
+        let palette = &options.screenshot.palette;
+        match GifRecorder::create(path, options.screenshot.scale, palette) {
+            Ok(recorder) => emulator.start_recording(recorder),
+            Err(err) => {
+                eprintln!("failed to create {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(movie) = recording {
+        emulator.record_movie(movie);
+    }
+    if let Some(movie) = replay {
+        emulator.replay_movie(movie);
+    }
     let mut console =
         Console::new(graphic_receiver, Box::new(keypad), Arc::clone(&terminated)).unwrap();
     console.run();
     emulator.execute(Arc::clone(&terminated));
     console.join();
+    if let Some(path) = &options.profile_report {
+        write_profile_report(emulator.machine(), path);
+    }
+    write_coverage(emulator.machine(), &options);
+    if let Some(path) = &options.final_screenshot {
+        write_screenshot(emulator.machine(), &options, path);
+    }
+    if let (Some(path), Some(movie)) = (&options.record_movie, emulator.take_recorded_movie()) {
+        write_movie(&movie, path);
+    }
 }
@@ -0,0 +1,188 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Number of timer ticks, and frames, per second.
+pub const FRAME_RATE: u64 = 60;
+
+/// Source of elapsed time.
+pub trait Clock {
+    /// Time elapsed since the clock was started.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// Wall clock time.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Clock which only moves when it is advanced, for tests and deterministic runs.
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            now: Cell::new(Duration::from_secs(0)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+/// Counts the 60 Hz frames which have elapsed on a clock.
+/// Frames are derived from the total elapsed time rather than accumulated, so rounding never
+/// drifts the frame rate.
+pub struct FrameCounter<C: Clock> {
+    clock: C,
+    frames: u64,
+}
+
+impl<C: Clock> FrameCounter<C> {
+    pub fn new(clock: C) -> Self {
+        let frames = frames_at(clock.now());
+        FrameCounter { clock, frames }
+    }
+
+    /// Number of frames elapsed since the previous call.
+    pub fn due(&mut self) -> u64 {
+        let frames = frames_at(self.clock.now());
+        let due = frames - self.frames;
+        self.frames = frames;
+        due
+    }
+}
+
+/// Paces frames at a fixed interval against a clock.
+/// Each deadline is scheduled from the previous deadline rather than from the time a frame
+/// finished, so time spent running frames and oversleeping is compensated instead of accumulating
+/// as drift. After falling more than `MAX_LAG` frames behind, the pacer resynchronises with the
+/// clock instead of trying to catch up.
+pub struct FramePacer<C: Clock> {
+    clock: C,
+    deadline: Duration,
+}
+
+/// Number of frames the pacer may fall behind before it resynchronises.
+pub const MAX_LAG: u32 = 5;
+
+impl<C: Clock> FramePacer<C> {
+    pub fn new(clock: C) -> Self {
+        let deadline = clock.now();
+        FramePacer { clock, deadline }
+    }
+
+    /// Time left until the next frame is due.
+    pub fn wait(&self) -> Duration {
+        self.deadline
+            .checked_sub(self.clock.now())
+            .unwrap_or_default()
+    }
+
+    /// Schedule the next frame `interval` after the current deadline.
+    pub fn advance(&mut self, interval: Duration) {
+        self.deadline += interval;
+        let now = self.clock.now();
+        if now > self.deadline + interval * MAX_LAG {
+            self.deadline = now;
+        }
+    }
+
+    /// Make the next frame due immediately.
+    pub fn reset(&mut self) {
+        self.deadline = self.clock.now();
+    }
+}
+
+/// Duration of one 60 Hz frame.
+pub fn frame_duration() -> Duration {
+    Duration::from_nanos(1_000_000_000 / FRAME_RATE)
+}
+
+fn frames_at(time: Duration) -> u64 {
+    (time.as_nanos() * FRAME_RATE as u128 / 1_000_000_000) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        let clock = ManualClock::new();
+        let mut counter = FrameCounter::new(&clock);
+        assert_eq!(counter.due(), 0);
+        clock.advance(Duration::from_millis(16));
+        assert_eq!(counter.due(), 0);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(counter.due(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(counter.due(), 60);
+    }
+
+    #[test]
+    fn test_pacer_compensates_drift() {
+        let clock = ManualClock::new();
+        let mut pacer = FramePacer::new(&clock);
+        let interval = Duration::from_millis(10);
+        assert_eq!(pacer.wait(), Duration::from_millis(0));
+        pacer.advance(interval);
+        assert_eq!(pacer.wait(), interval);
+        // the frame ran 3ms late, so the next one is due 3ms sooner
+        clock.advance(Duration::from_millis(13));
+        pacer.advance(interval);
+        assert_eq!(pacer.wait(), Duration::from_millis(7));
+    }
+
+    #[test]
+    fn test_pacer_resynchronises() {
+        let clock = ManualClock::new();
+        let mut pacer = FramePacer::new(&clock);
+        let interval = Duration::from_millis(10);
+        clock.advance(Duration::from_secs(1));
+        pacer.advance(interval);
+        assert_eq!(pacer.wait(), Duration::from_millis(0));
+        pacer.advance(interval);
+        assert_eq!(pacer.wait(), interval);
+    }
+}
@@ -0,0 +1,247 @@
+use super::assembler::SourceMap;
+use super::disassembler::{Disassembly, Line, Syntax};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Way in which a byte of memory was used by a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Fetched as (part of) an instruction.
+    Executed,
+    /// Read as sprite data by `Dxyn`.
+    Sprite,
+    /// Read into registers by `Fx65`, `5xy3` or `F002`.
+    Read,
+    /// Written by `Fx55`, `Fx33` or `5xy2`.
+    Written,
+}
+
+impl Access {
+    const ALL: [Access; 4] = [
+        Access::Executed,
+        Access::Sprite,
+        Access::Read,
+        Access::Written,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Letter shown in annotated listings.
+    fn mark(self) -> char {
+        match self {
+            Access::Executed => 'X',
+            Access::Sprite => 'S',
+            Access::Read => 'R',
+            Access::Written => 'W',
+        }
+    }
+}
+
+/// How every byte of memory was used during a run, for finding the parts of a ROM its tests
+/// never reach. Executed bytes also count how often the instruction starting there ran.
+pub struct Coverage {
+    flags: Vec<u8>,
+    executions: Vec<u64>,
+}
+
+impl Coverage {
+    /// Coverage of a memory of `size` bytes.
+    pub fn new(size: usize) -> Self {
+        Coverage {
+            flags: vec![0; size],
+            executions: vec![0; size],
+        }
+    }
+
+    /// Record an access to `length` bytes starting at `address`. Bytes outside of memory are
+    /// ignored.
+    pub fn mark(&mut self, address: usize, length: usize, access: Access) {
+        let end = (address + length).min(self.flags.len());
+        for flags in self.flags.get_mut(address..end).unwrap_or_default() {
+            *flags |= access.bit();
+        }
+        if access == Access::Executed && address < self.executions.len() {
+            self.executions[address] += 1;
+        }
+    }
+
+    pub fn has(&self, address: usize, access: Access) -> bool {
+        self.flags
+            .get(address)
+            .is_some_and(|flags| flags & access.bit() != 0)
+    }
+
+    /// Number of times the instruction at `address` was executed.
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executions.get(address).copied().unwrap_or(0)
+    }
+
+    /// Whether any of `length` bytes at `address` were used in any way.
+    fn used(&self, address: usize, length: usize) -> bool {
+        (address..address + length).any(|address| self.flags.get(address).is_some_and(|f| *f != 0))
+    }
+
+    /// One letter per kind of access to any of `length` bytes at `address`, `-` if there was
+    /// none, e.g. `X--W`.
+    fn marks(&self, address: usize, length: usize) -> String {
+        Access::ALL
+            .iter()
+            .map(|&access| {
+                let any = (address..address + length).any(|address| self.has(address, access));
+                if any {
+                    access.mark()
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    }
+
+    /// Hits of bytes at `address` for lcov: executions of the instruction starting there, or 1
+    /// if they were only read or written.
+    fn hits(&self, address: usize, length: usize) -> u64 {
+        match self.executions(address) {
+            0 => self.used(address, length) as u64,
+            executions => executions,
+        }
+    }
+
+    /// Disassembly listing with the execution count and the accesses of every line in front,
+    /// e.g. `      12 X---    LD V0, 0x07`. The accesses are marked `X` for executed, `S` for
+    /// sprite data, `R` for read and `W` for written. Lines stay in the place they have in the
+    /// plain listing.
+    pub fn annotated_listing(&self, disassembly: &Disassembly, syntax: Syntax) -> String {
+        disassembly.annotated_listing(syntax, &|line: &Line| {
+            let address = line.address as usize;
+            let executions = match self.executions(address) {
+                0 => "-".to_string(),
+                count => count.to_string(),
+            };
+            format!(
+                "{:>8} {}",
+                executions,
+                self.marks(address, line.bytes.len())
+            )
+        })
+    }
+
+    /// Report in lcov format with one record for the listing of `disassembly` named `name`, as
+    /// written by `chip8-disasm` or `annotated_listing`.
+    pub fn lcov(&self, disassembly: &Disassembly, name: &str) -> String {
+        let mut lines = BTreeMap::new();
+        let mut number = 0;
+        for line in &disassembly.lines {
+            if disassembly.labels.contains_key(&line.address) {
+                number += 1;
+            }
+            number += 1;
+            let hits = self.hits(line.address as usize, line.bytes.len());
+            lines.insert(number, hits);
+        }
+        let mut files = BTreeMap::new();
+        files.insert(name.to_string(), lines);
+        lcov(&files)
+    }
+
+    /// Report in lcov format with one record per source file of `map`, as written by
+    /// `chip8-asm --map`. Source lines assembled to several places count the most hits.
+    pub fn lcov_with_map(&self, map: &SourceMap) -> String {
+        let mut files: BTreeMap<String, BTreeMap<usize, u64>> = BTreeMap::new();
+        for entry in &map.entries {
+            let hits = self.hits(entry.address as usize, entry.length as usize);
+            let line = files
+                .entry(entry.file.clone())
+                .or_default()
+                .entry(entry.line)
+                .or_insert(0);
+            *line = (*line).max(hits);
+        }
+        lcov(&files)
+    }
+}
+
+/// Write line hits per file as lcov records.
+fn lcov(files: &BTreeMap<String, BTreeMap<usize, u64>>) -> String {
+    let mut out = String::new();
+    for (file, lines) in files {
+        writeln!(out, "TN:\nSF:{}", file).unwrap();
+        for (line, hits) in lines {
+            writeln!(out, "DA:{},{}", line, hits).unwrap();
+        }
+        let hit = lines.values().filter(|&&hits| hits > 0).count();
+        writeln!(out, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_with_map;
+    use crate::machine::Machine;
+
+    #[test]
+    fn test_coverage() {
+        let source = "
+            LD I, sprite
+            DRW V0, V0, 2
+            LD I, buffer
+            LD [I], V0
+            LD V0, [I]
+            JP end
+            LD V2, 1
+        end:
+            JP end
+        sprite:
+            db 0xF0, 0x90
+            db 0xFF
+        buffer:
+            db 0, 0
+        ";
+        let (rom, map) = assemble_with_map(source, Syntax::Cowgod).unwrap();
+        let mut machine = Machine::new();
+        machine.memory.all[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        machine.memory.coverage = Some(Coverage::new(machine.memory.all.len()));
+        machine.run_cycles(9).unwrap();
+
+        let coverage = machine.memory.coverage.as_ref().unwrap();
+        assert!(coverage.has(0x200, Access::Executed));
+        assert!(coverage.has(0x201, Access::Executed));
+        assert!(!coverage.has(0x20c, Access::Executed));
+        assert_eq!(coverage.executions(0x20e), 3);
+        assert!(coverage.has(0x211, Access::Sprite));
+        assert!(!coverage.has(0x212, Access::Sprite));
+        assert!(coverage.has(0x213, Access::Written));
+        assert!(coverage.has(0x214, Access::Read));
+        assert!(!coverage.has(0x213, Access::Sprite));
+
+        let listing = coverage.annotated_listing(&Disassembly::new(&rom), Syntax::Cowgod);
+        assert!(listing.contains("       1 X---    LD I, data_210 "));
+        assert!(listing.contains("       - ----    db 0x62, 0x01 "));
+        assert!(listing.contains("       - -S--    db 0xF0, 0x90, 0xFF "));
+        assert!(listing.contains("\n             label_20E:\n"));
+
+        let report = coverage.lcov_with_map(&map);
+        assert!(report.starts_with("TN:\nSF:<input>\nDA:2,1\n"));
+        assert!(report.contains("DA:8,0\nDA:10,3\nDA:12,1\nDA:13,0\nDA:15,1\n"));
+        assert!(report.ends_with("LF:11\nLH:9\nend_of_record\n"));
+    }
+
+    #[test]
+    fn test_lcov_without_map() {
+        // 200: JP 0x202, 202: JP 0x202
+        let rom = [0x12, 0x02, 0x12, 0x02];
+        let mut coverage = Coverage::new(0x1000);
+        coverage.mark(0x200, 2, Access::Executed);
+        coverage.mark(0x202, 2, Access::Executed);
+        coverage.mark(0x202, 2, Access::Executed);
+        coverage.mark(0xfff, 2, Access::Written);
+        let report = coverage.lcov(&Disassembly::new(&rom), "game.asm");
+        assert_eq!(
+            report,
+            "TN:\nSF:game.asm\nDA:1,1\nDA:3,2\nLF:2\nLH:2\nend_of_record\n"
+        );
+    }
+}
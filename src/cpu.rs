@@ -1,19 +1,41 @@
-use super::graphic::Graphic;
-use super::instructions::Instruction;
-use super::memory::Memory;
+use super::clock::{frame_duration, Clock, FramePacer, SystemClock};
+use super::error::EmulationError;
+use super::gif::GifRecorder;
+use super::machine::Machine;
+use super::movie::Movie;
+use super::random::Random;
+use super::screenshot::{ImageFormat, Screenshot};
+use super::watch::Watches;
+use console::Graphic as ConsoleGraphic;
+use log::{error, info};
+use std::fs;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::thread;
-use std::time;
+use std::time::Duration;
 
 pub struct Register {
     pub pc: u16,
     pub i: u16,
     pub v: [u8; 16],
-    pub delay_timer: Arc<Mutex<u8>>,
-    pub sound_timer: Arc<Mutex<u8>>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// SUPER-CHIP RPL user flags saved and restored by Fx75 and Fx85.
+    pub rpl: [u8; 16],
+    /// XO-CHIP audio pattern buffer loaded by F002.
+    pub audio_pattern: [u8; 16],
+    /// XO-CHIP audio pitch register set by Fx3A.
+    pub pitch: u8,
+    /// Set once the program exits with 00FD.
+    pub halted: bool,
+    /// Register receiving the key while Fx0A waits for a key to be released.
+    pub waiting_for_key: Option<usize>,
+    /// Generator for Cxkk.
+    pub random: Random,
+    /// Register and index watchpoints checked by `set_v` and `set_i`.
+    pub watches: Watches,
 }
 
 impl Register {
@@ -22,71 +44,452 @@ impl Register {
             pc: 0x200,
             i: 0,
             v: [0; 16],
-            delay_timer: Arc::new(Mutex::new(0)),
-            sound_timer: Arc::new(Mutex::new(0)),
-        }
-    }
-
-    pub fn run_timer(&self, terminated: Arc<AtomicBool>) {
-        let delay_timer = Arc::clone(&self.delay_timer);
-        let sound_timer = Arc::clone(&self.sound_timer);
-        let interval = time::Duration::from_millis(15);
-        thread::spawn(move || {
-            while !terminated.load(Ordering::Relaxed) {
-                thread::sleep(interval);
-                let mut dt = delay_timer.lock().unwrap();
-                let mut st = sound_timer.lock().unwrap();
-                if *dt > 0 {
-                    *dt -= 1;
-                }
-                if *st > 0 {
-                    // TODO beeping here
-                    *st -= 1;
-                }
-            }
-        });
+            delay_timer: 0,
+            sound_timer: 0,
+            rpl: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: 64,
+            halted: false,
+            waiting_for_key: None,
+            random: Random::new(),
+            watches: Watches::new(),
+        }
+    }
+
+    /// Store `value` in Vx, checked against the register watchpoints.
+    pub fn set_v(&mut self, vx: usize, value: u8) {
+        let old = self.v[vx];
+        self.v[vx] = value;
+        self.watches.register(vx, old, value);
+    }
+
+    /// Store `address` in I, checked against the index watchpoints.
+    pub fn set_i(&mut self, address: u16) {
+        self.i = address;
+        self.watches.index(address);
+    }
+
+    /// Decrement the delay and sound timers by one if they are above zero.
+    /// Called once per 60 Hz frame.
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            // TODO beeping here
+            self.sound_timer -= 1;
+        }
+    }
+}
+
+impl Default for Register {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// Input sent from the console to the running `Cpu`.
+pub enum Event {
+    /// A CHIP-8 key went down.
+    Press(u8),
+    /// A CHIP-8 key went up.
+    Release(u8),
+    /// A CHIP-8 key was pressed by a frontend which cannot report releases.
+    /// The key is released after `KEY_TAP_FRAMES` frames.
+    Tap(u8),
+    ToggleTurbo,
+    ToggleSlowMotion,
+    TogglePause,
+    /// Choose the save state slot used by `SaveState` and `LoadState`.
+    SelectSlot(u8),
+    SaveState,
+    LoadState,
+    /// Run backwards for `KEY_TAP_FRAMES` frames, renewed by every repeat of a held key.
+    Rewind,
+    /// Write the current frame as an image.
+    Screenshot,
+    /// Start recording the presented frames as a GIF, or stop the recording.
+    ToggleRecording,
+}
+
+/// How fast frames are run relative to real time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    /// 60 frames per second.
+    Normal,
+    /// As many frames as possible, presenting one of them every 60th of a second.
+    Turbo,
+    /// 60 / `SLOW_MOTION_FACTOR` frames per second.
+    SlowMotion,
+}
+
+pub const SLOW_MOTION_FACTOR: u32 = 4;
+
+/// Most frames run for each presented frame in turbo mode, so that presenting, input and
+/// termination are still handled when frames take no measurable time.
+pub const TURBO_FRAME_LIMIT: u32 = 1000;
+
+/// Frames a tapped key stays held, long enough to bridge the delay before terminal key repeat.
+pub const KEY_TAP_FRAMES: u32 = 30;
+
+/// Movie a `Cpu` is recording or replaying.
+enum MovieMode {
+    Record(Movie),
+    /// Replaying, with the number of frames replayed so far.
+    Replay(Movie, u64),
+}
+
+/// Threaded frontend driving a `Machine` for the console.
+/// Input arrives over `event_bus` and every frame is sent to the console over `graphic_sender`.
+/// Frames are paced at 60 Hz against the system clock, subject to the current `Speed`.
+/// Save states are written to `<state_file>.<slot>.state`, and screenshots and recordings to
+/// `<state_file>-<n>.<extension>`, numbered from 1. Recordings capture every presented frame.
+/// Key events are applied between frames, so that a `Movie` of the session can be recorded or
+/// replayed; save states cannot be loaded and rewinding is disabled meanwhile.
 pub struct Cpu {
-    memory: Memory,
-    register: Register,
-    graphic: Graphic,
-    keyboard_bus: mpsc::Receiver<u8>,
+    machine: Machine,
+    graphic_sender: mpsc::Sender<ConsoleGraphic>,
+    event_bus: mpsc::Receiver<Event>,
+    speed: Speed,
+    paused: bool,
+    state_file: Option<PathBuf>,
+    state_slot: u8,
+    /// Frames left to play backwards.
+    rewinding: u32,
+    screenshot: Screenshot,
+    recording: Option<GifRecorder>,
+    movie: Option<MovieMode>,
 }
 
 impl Cpu {
     pub fn new(
-        memory: Memory,
-        register: Register,
-        graphic: Graphic,
-        keyboard_bus: mpsc::Receiver<u8>,
+        machine: Machine,
+        graphic_sender: mpsc::Sender<ConsoleGraphic>,
+        event_bus: mpsc::Receiver<Event>,
     ) -> Self {
         Cpu {
-            memory,
-            register,
-            graphic,
-            keyboard_bus,
+            machine,
+            graphic_sender,
+            event_bus,
+            speed: Speed::Normal,
+            paused: false,
+            state_file: None,
+            state_slot: 0,
+            rewinding: 0,
+            screenshot: Screenshot::new(ImageFormat::Png),
+            recording: None,
+            movie: None,
         }
     }
 
+    /// Enable save states, stored in files named after `path`.
+    pub fn set_state_file<P: Into<PathBuf>>(&mut self, path: P) {
+        self.state_file = Some(path.into());
+    }
+
+    /// Set the format, scale and palette of screenshots.
+    pub fn set_screenshot(&mut self, screenshot: Screenshot) {
+        self.screenshot = screenshot;
+    }
+
+    /// Record the presented frames with `recorder` until recording is toggled off or `execute`
+    /// returns.
+    pub fn start_recording(&mut self, recorder: GifRecorder) {
+        self.stop_recording();
+        self.recording = Some(recorder);
+    }
+
+    /// Finish the recording in progress, if any.
+    pub fn stop_recording(&mut self) {
+        let recorder = match self.recording.take() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let frames = recorder.frames();
+        match recorder.finish() {
+            Ok(()) => info!("recorded {} frames", frames),
+            Err(err) => error!("failed to finish recording: {}", err),
+        }
+    }
+
+    /// Record the keys of every frame into `movie`, which was started on the machine before it
+    /// ran.
+    pub fn record_movie(&mut self, movie: Movie) {
+        self.movie = Some(MovieMode::Record(movie));
+    }
+
+    /// Replay `movie`, which was recorded from the state the machine is in. Key events are
+    /// ignored until the replay ends, after which the player takes over.
+    pub fn replay_movie(&mut self, movie: Movie) {
+        self.movie = Some(MovieMode::Replay(movie, 0));
+    }
+
+    /// Take the movie being recorded, ending with the current screen.
+    pub fn take_recorded_movie(&mut self) -> Option<Movie> {
+        match self.movie.take() {
+            Some(MovieMode::Record(mut movie)) => {
+                movie.set_screen(&self.machine.graphic);
+                Some(movie)
+            }
+            other => {
+                self.movie = other;
+                None
+            }
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Run the machine until `terminated` is set.
+    /// If the program fails, the error is logged and the last frame stays on screen until the
+    /// console is closed.
     pub fn execute(&mut self, terminated: Arc<AtomicBool>) {
-        self.register.run_timer(Arc::clone(&terminated));
+        self.execute_with(SystemClock::new(), thread::sleep, &terminated);
+    }
+
+    /// Run the machine paced by `clock`, waiting with `sleep`, until `terminated` is set.
+    fn execute_with<C: Clock, S: FnMut(Duration)>(
+        &mut self,
+        clock: C,
+        mut sleep: S,
+        terminated: &AtomicBool,
+    ) {
+        let mut pacer = FramePacer::new(clock);
+        let mut halted = false;
         while !terminated.load(Ordering::Relaxed) {
-            // fetch using pc?
-            let opcode = self.memory.read(self.register.pc);
-            // recog instruction
-            let instruction = Box::<dyn Instruction>::from(opcode);
-            // execute
-            thread::sleep(time::Duration::from_millis(2));
-            instruction.execute(
-                &mut self.memory,
-                &mut self.register,
-                &mut self.graphic,
-                &mut self.keyboard_bus,
-            );
+            self.handle_events();
+            if halted || self.paused {
+                sleep(frame_duration());
+                pacer.reset();
+                continue;
+            }
+
+            sleep(pacer.wait());
+            let interval = match self.speed {
+                Speed::SlowMotion => frame_duration() * SLOW_MOTION_FACTOR,
+                _ => frame_duration(),
+            };
+            pacer.advance(interval);
+            let result = if self.rewinding > 0 {
+                self.rewinding -= 1;
+                self.machine.rewind(1).map(|_| ())
+            } else {
+                match self.speed {
+                    Speed::Turbo => self.run_turbo(&pacer),
+                    _ => self.run_frame(),
+                }
+            };
+            if let Err(err) = result {
+                error!("{}", err);
+                halted = true;
+            }
+            if self.machine.is_halted() {
+                terminated.store(true, Ordering::Relaxed);
+            }
             // draw
-            self.graphic.draw();
+            self.draw();
         }
+        self.stop_recording();
+    }
+
+    /// Run frames back to back until the next frame is due to be presented, at most
+    /// `TURBO_FRAME_LIMIT` of them.
+    fn run_turbo<C: Clock>(&mut self, pacer: &FramePacer<C>) -> Result<(), EmulationError> {
+        self.run_frame()?;
+        for _ in 1..TURBO_FRAME_LIMIT {
+            if pacer.wait() == Duration::from_secs(0) || self.machine.is_halted() {
+                break;
+            }
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Run one frame, recording the keys held during it or holding the recorded ones.
+    fn run_frame(&mut self) -> Result<(), EmulationError> {
+        match &mut self.movie {
+            Some(MovieMode::Record(movie)) => movie.record(&self.machine),
+            Some(MovieMode::Replay(movie, frame)) => {
+                movie.apply(*frame, &mut self.machine);
+                *frame += 1;
+            }
+            None => {}
+        }
+        self.machine.run_frame()?;
+        self.end_replay();
+        Ok(())
+    }
+
+    /// Stop replaying once every recorded frame has run, reporting whether the screen is the
+    /// recorded one.
+    fn end_replay(&mut self) {
+        if let Some(MovieMode::Replay(movie, frame)) = &self.movie {
+            if *frame < movie.frames() {
+                return;
+            }
+            if movie.matches_screen(&self.machine.graphic) {
+                info!("replayed {} frames", frame);
+            } else {
+                error!(
+                    "replay of {} frames ended on another screen than recorded",
+                    frame
+                );
+            }
+            self.movie = None;
+        }
+    }
+
+    fn handle_events(&mut self) {
+        while let Ok(event) = self.event_bus.try_recv() {
+            let replaying = matches!(self.movie, Some(MovieMode::Replay(..)));
+            match event {
+                Event::Press(_) | Event::Release(_) | Event::Tap(_) if replaying => {}
+                Event::LoadState | Event::Rewind if self.movie.is_some() => {
+                    info!("loading states and rewinding are disabled during movies");
+                }
+                Event::Press(key) => self.machine.press_key(key),
+                Event::Release(key) => self.machine.release_key(key),
+                Event::Tap(key) => self.machine.tap_key(key, KEY_TAP_FRAMES),
+                Event::ToggleTurbo => self.toggle_speed(Speed::Turbo),
+                Event::ToggleSlowMotion => self.toggle_speed(Speed::SlowMotion),
+                Event::TogglePause => self.paused = !self.paused,
+                Event::SelectSlot(slot) => self.state_slot = slot,
+                Event::SaveState => self.save_state(),
+                Event::LoadState => self.load_state(),
+                Event::Rewind => self.rewinding = KEY_TAP_FRAMES,
+                Event::Screenshot => self.save_screenshot(),
+                Event::ToggleRecording => self.toggle_recording(),
+            }
+        }
+    }
+
+    fn slot_path(&self) -> Option<PathBuf> {
+        let file = self.state_file.as_ref()?;
+        let mut name = file.clone().into_os_string();
+        name.push(format!(".{}.state", self.state_slot));
+        Some(PathBuf::from(name))
+    }
+
+    fn save_state(&self) {
+        if let Some(path) = self.slot_path() {
+            match fs::write(&path, self.machine.save_state()) {
+                Ok(()) => info!("saved state to {}", path.display()),
+                Err(err) => error!("failed to save {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    fn load_state(&mut self) {
+        if let Some(path) = self.slot_path() {
+            let result = fs::read(&path)
+                .map_err(EmulationError::from)
+                .and_then(|data| self.machine.load_state(&data));
+            match result {
+                Ok(()) => info!("loaded state from {}", path.display()),
+                Err(err) => error!("failed to load {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    /// First unused file name for a screenshot or recording with `extension`.
+    fn numbered_path(&self, extension: &str) -> Option<PathBuf> {
+        let file = self.state_file.as_ref()?;
+        (1..)
+            .map(|n| {
+                let mut name = file.clone().into_os_string();
+                name.push(format!("-{}.{}", n, extension));
+                PathBuf::from(name)
+            })
+            .find(|path| !path.exists())
+    }
+
+    /// Write the current frame to the first unused screenshot file.
+    fn save_screenshot(&self) {
+        let path = match self.numbered_path(self.screenshot.format.extension()) {
+            Some(path) => path,
+            None => return,
+        };
+        match self.screenshot.save(&self.machine.graphic, &path) {
+            Ok(()) => info!("saved screenshot to {}", path.display()),
+            Err(err) => error!("failed to save {}: {}", path.display(), err),
+        }
+    }
+
+    /// Start recording to the first unused GIF file with the scale and palette of screenshots,
+    /// or stop the recording in progress.
+    fn toggle_recording(&mut self) {
+        if self.recording.is_some() {
+            self.stop_recording();
+            return;
+        }
+        let path = match self.numbered_path("gif") {
+            Some(path) => path,
+            None => return,
+        };
+        match GifRecorder::create(&path, self.screenshot.scale, &self.screenshot.palette) {
+            Ok(recorder) => {
+                info!("recording to {}", path.display());
+                self.recording = Some(recorder);
+            }
+            Err(err) => error!("failed to create {}: {}", path.display(), err),
+        }
+    }
+
+    fn toggle_speed(&mut self, speed: Speed) {
+        if self.speed == speed {
+            self.speed = Speed::Normal;
+        } else {
+            self.speed = speed;
+        }
+    }
+
+    fn draw(&mut self) {
+        let graphic = &self.machine.graphic;
+        let gfx = graphic.gfx.to_vec();
+        self.graphic_sender
+            .send(ConsoleGraphic::new(gfx, graphic.width()))
+            .unwrap();
+        if let Some(recorder) = &mut self.recording {
+            if let Err(err) = recorder.capture(graphic) {
+                error!("recording failed: {}", err);
+                self.recording = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_turbo() {
+        let mut machine = Machine::new();
+        // 1200: JP 0x200
+        machine.memory.all[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+        machine.register.delay_timer = 255;
+        let (graphic_sender, graphics) = mpsc::channel();
+        let (events, event_bus) = mpsc::channel();
+        let mut cpu = Cpu::new(machine, graphic_sender, event_bus);
+        events.send(Event::ToggleTurbo).unwrap();
+
+        let clock = ManualClock::new();
+        let terminated = AtomicBool::new(false);
+        let mut sleeps = 0;
+        let sleep = |duration| {
+            clock.advance(duration);
+            sleeps += 1;
+            if sleeps == 3 {
+                terminated.store(true, Ordering::Relaxed);
+            }
+        };
+        cpu.execute_with(&clock, sleep, &terminated);
+        assert_eq!(cpu.speed, Speed::Turbo);
+        assert_eq!(graphics.try_iter().count(), 3);
+        // far more than 3 frames ran
+        assert_eq!(cpu.machine.register.delay_timer, 0);
     }
 }
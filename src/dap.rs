@@ -0,0 +1,769 @@
+use super::clock::{frame_duration, FramePacer, SystemClock};
+use super::debugger::{Debugger, Stop};
+use super::disassembler::{mnemonic, Disassembly, Syntax};
+use super::machine::{Machine, CYCLES_PER_FRAME};
+use super::quirks::{Profile, Quirks};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// The only thread reported to the client.
+const THREAD_ID: u64 = 1;
+
+/// Reference of the disassembly listing served by the `source` request.
+const SOURCE_REFERENCE: u64 = 1;
+
+const REGISTERS_REFERENCE: u64 = 1;
+const TIMERS_REFERENCE: u64 = 2;
+const MEMORY_REFERENCE: u64 = 3;
+/// Memory pages are expanded through references starting here, one per 256 bytes.
+const PAGE_REFERENCE_BASE: u64 = 0x100;
+
+const PAGE_SIZE: usize = 0x100;
+const ROW_SIZE: usize = 0x10;
+
+/// Read one Content-Length framed Debug Adapter Protocol message.
+/// Returns None once the input is closed.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| invalid_data(&err.to_string()))
+}
+
+/// Write one Content-Length framed Debug Adapter Protocol message.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Debug Adapter Protocol server driving a `Debugger`.
+/// The ROM is given by the launch request and shown to the client as a disassembly listing, so
+/// breakpoints are set on its lines. `handle` answers one request and `run_frame` advances a
+/// running program, both returning the responses and events to send in order.
+pub struct DapServer {
+    /// Profile used when the launch request does not name one.
+    pub profile: Profile,
+    /// Quirks used when the launch request does not name a profile.
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    debugger: Option<Debugger>,
+    name: String,
+    disassembly: Option<Disassembly>,
+    /// Address of each line of the listing.
+    addresses: Vec<u16>,
+    source_breakpoints: BTreeSet<u16>,
+    instruction_breakpoints: BTreeSet<u16>,
+    stop_on_entry: bool,
+    seq: u64,
+    terminated: bool,
+}
+
+impl DapServer {
+    pub fn new() -> Self {
+        DapServer {
+            profile: Profile::default(),
+            quirks: Profile::default().quirks(),
+            cycles_per_frame: CYCLES_PER_FRAME,
+            debugger: None,
+            name: String::new(),
+            disassembly: None,
+            addresses: Vec::new(),
+            source_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            seq: 0,
+            terminated: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.debugger.as_ref().is_some_and(Debugger::is_running)
+    }
+
+    /// Whether the session ended with a disconnect or the program exiting.
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Answer `request`, returning the response followed by any events it caused.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let was_running = self.is_running();
+        let pc = self
+            .debugger
+            .as_ref()
+            .map(|debugger| debugger.machine.register.pc);
+        let mut reversed = None;
+        let body = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsStepBack": true,
+                "supportsEvaluateForHovers": false,
+            })),
+            "launch" => self.launch(arguments).map(|()| Value::Null),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                let stop_on_entry = self.stop_on_entry;
+                self.debugger().map(|debugger| {
+                    if !stop_on_entry {
+                        debugger.resume();
+                    }
+                    Value::Null
+                })
+            }
+            "threads" => Ok(json!({
+                "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }]
+            })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Timers", "variablesReference": TIMERS_REFERENCE, "expensive": false },
+                    { "name": "Memory", "variablesReference": MEMORY_REFERENCE, "expensive": true },
+                ]
+            })),
+            "variables" => self.variables(arguments),
+            "source" => self
+                .disassembly
+                .as_ref()
+                .map(|disassembly| json!({ "content": disassembly.listing(Syntax::Cowgod) }))
+                .ok_or_else(|| "no program launched".to_string()),
+            "continue" => self.debugger().map(|debugger| {
+                debugger.resume();
+                json!({ "allThreadsContinued": true })
+            }),
+            "next" => self.step(Debugger::step_over),
+            "stepIn" => self.step(Debugger::step),
+            "stepBack" => self.debugger().map(|debugger| {
+                debugger.step_back();
+                Value::Null
+            }),
+            "reverseContinue" => self.debugger().map(|debugger| {
+                reversed = Some(debugger.reverse_continue());
+                Value::Null
+            }),
+            "stepOut" => self.debugger().map(|debugger| {
+                debugger.step_out();
+                Value::Null
+            }),
+            "pause" => self.debugger().map(|debugger| {
+                if debugger.is_running() {
+                    debugger.pause();
+                }
+                Value::Null
+            }),
+            "evaluate" => self.evaluate(arguments),
+            "disconnect" | "terminate" => {
+                self.terminated = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request {}", command)),
+        };
+
+        let success = body.is_ok();
+        let mut messages = vec![self.response(request, body)];
+        match command {
+            "launch" if success => messages.push(self.event("initialized", json!({}))),
+            "configurationDone" if success && self.stop_on_entry => {
+                messages.push(self.stopped("entry", None))
+            }
+            _ => {}
+        }
+        if command == "pause" && was_running {
+            messages.push(self.stopped("pause", None));
+        }
+        let stepped = match command {
+            "next" | "stepIn" | "stepBack" => true,
+            "evaluate" => {
+                self.debugger
+                    .as_ref()
+                    .map(|debugger| debugger.machine.register.pc)
+                    != pc
+            }
+            _ => false,
+        };
+        if stepped && self.debugger.is_some() && !self.is_running() {
+            messages.append(&mut self.step_stopped());
+        }
+        match reversed {
+            Some(Stop::Breakpoint(_)) => messages.push(self.stopped("breakpoint", None)),
+            Some(stop) => messages.push(self.stopped("step", Some(stop.to_string()))),
+            None => {}
+        }
+        messages
+    }
+
+    /// Run one frame of a running program, returning the events reporting why it stopped.
+    pub fn run_frame(&mut self) -> Vec<Value> {
+        let stop = match self.debugger.as_mut().and_then(Debugger::run_frame) {
+            Some(stop) => stop,
+            None => return Vec::new(),
+        };
+        match stop {
+            Stop::Breakpoint(_) => vec![self.stopped("breakpoint", None)],
+            Stop::Watchpoint(hit) => vec![self.stopped("data breakpoint", Some(hit.to_string()))],
+            Stop::Reached(_) => vec![self.stopped("step", None)],
+            Stop::Halted => self.exited(),
+            Stop::JournalStart(_) => vec![self.stopped("step", Some(stop.to_string()))],
+            Stop::Error(err) => vec![self.stopped("exception", Some(err.to_string()))],
+        }
+    }
+
+    /// Handle requests from `input` and send the replies to `output` until the session ends,
+    /// running the program at 60 frames per second in between.
+    pub fn serve<R, W>(&mut self, input: R, mut output: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut pacer = FramePacer::new(SystemClock::new());
+        while !self.terminated {
+            let messages = if self.is_running() {
+                match requests.try_recv() {
+                    Ok(request) => self.handle(&request),
+                    Err(TryRecvError::Empty) => {
+                        thread::sleep(pacer.wait());
+                        pacer.advance(frame_duration());
+                        self.run_frame()
+                    }
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                let request = match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                };
+                pacer.reset();
+                self.handle(&request)
+            };
+            for message in &messages {
+                write_message(&mut output, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("launch needs the path of the ROM in program")?;
+        let (profile, quirks) = match arguments["profile"].as_str() {
+            Some(name) => {
+                let profile: Profile = name.parse()?;
+                (profile, profile.quirks())
+            }
+            None => (self.profile, self.quirks),
+        };
+        let rom =
+            fs::read(program).map_err(|err| format!("failed to read {}: {}", program, err))?;
+        let mut machine = Machine::with_profile(profile);
+        machine.quirks = quirks;
+        machine.cycles_per_frame = arguments["cycles"]
+            .as_u64()
+            .map_or(self.cycles_per_frame, |cycles| cycles as usize);
+        machine
+            .memory
+            .load_bytes(&rom)
+            .map_err(|err| format!("failed to load {}: {}", program, err))?;
+
+        let disassembly = Disassembly::new(&rom);
+        self.addresses = disassembly.listing_addresses();
+        self.disassembly = Some(disassembly);
+        self.name = Path::new(program)
+            .file_name()
+            .map_or(program.to_string(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.debugger = Some(Debugger::new(machine));
+        self.update_breakpoints();
+        Ok(())
+    }
+
+    fn debugger(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger
+            .as_mut()
+            .ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn step(
+        &mut self,
+        step: fn(&mut Debugger) -> Result<(), super::error::EmulationError>,
+    ) -> Result<Value, String> {
+        let debugger = self.debugger()?;
+        step(debugger).map_err(|err| err.to_string())?;
+        Ok(Value::Null)
+    }
+
+    /// Events after a step which finished without running: exited, or stopped for a new look.
+    fn step_stopped(&mut self) -> Vec<Value> {
+        let debugger = match &self.debugger {
+            Some(debugger) => debugger,
+            None => return Vec::new(),
+        };
+        if debugger.machine.is_halted() {
+            return self.exited();
+        }
+        let description = debugger.hits.first().map(ToString::to_string);
+        match description {
+            Some(text) => vec![self.stopped("data breakpoint", Some(text))],
+            None => vec![self.stopped("step", None)],
+        }
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let lines: Vec<u64> = match arguments["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints
+                .iter()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .collect(),
+            None => arguments["lines"]
+                .as_array()
+                .map(|lines| lines.iter().filter_map(Value::as_u64).collect())
+                .unwrap_or_default(),
+        };
+        self.source_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            let code = self.line_code_address(line);
+            if let Some(address) = code {
+                self.source_breakpoints.insert(address);
+            }
+            breakpoints.push(json!({
+                "verified": code.is_some(),
+                "line": line,
+                "message": if code.is_some() { Value::Null } else { json!("no instruction on this line") },
+            }));
+        }
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let reference = breakpoint["instructionReference"]
+                .as_str()
+                .unwrap_or_default();
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let address = parse_reference(reference).map(|address| address as i64 + offset);
+            let verified = match address {
+                Some(address) if (0..=0xFFFF).contains(&address) => {
+                    self.instruction_breakpoints.insert(address as u16);
+                    true
+                }
+                _ => false,
+            };
+            breakpoints.push(json!({ "verified": verified }));
+        }
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn update_breakpoints(&mut self) {
+        let breakpoints = self
+            .source_breakpoints
+            .union(&self.instruction_breakpoints)
+            .copied()
+            .collect();
+        if let Some(debugger) = &mut self.debugger {
+            debugger.breakpoints = breakpoints;
+        }
+    }
+
+    /// Address of the instruction on listing `line`, counted from 1.
+    fn line_code_address(&self, line: u64) -> Option<u16> {
+        let address = *self.addresses.get((line as usize).checked_sub(1)?)?;
+        let disassembly = self.disassembly.as_ref()?;
+        disassembly
+            .lines
+            .iter()
+            .find(|candidate| candidate.address == address)
+            .filter(|candidate| candidate.code)
+            .map(|_| address)
+    }
+
+    /// Listing line showing `address`, counted from 1. Label lines are skipped.
+    fn address_line(&self, address: u16) -> Option<usize> {
+        self.addresses
+            .iter()
+            .rposition(|&line_address| line_address == address)
+            .map(|index| index + 1)
+    }
+
+    /// One frame for PC and one for every CALL waiting on the stack, innermost first.
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let debugger = self.debugger()?;
+        let mut addresses = vec![debugger.machine.register.pc];
+        let stack = &debugger.machine.memory.stack;
+        addresses.extend(stack.iter().rev().map(|address| address.wrapping_sub(2)));
+        let frames: Vec<Value> = addresses
+            .iter()
+            .enumerate()
+            .map(|(id, &address)| self.frame(id, address))
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn frame(&self, id: usize, address: u16) -> Value {
+        let memory = &self.debugger.as_ref().unwrap().machine.memory;
+        let text = memory
+            .all
+            .get(address as usize..)
+            .and_then(|bytes| mnemonic(bytes, Syntax::Cowgod))
+            .unwrap_or_else(|| "???".to_string());
+        let mut frame = json!({
+            "id": id,
+            "name": format!("{:03X}: {}", address, text),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{:03X}", address),
+        });
+        if let Some(line) = self.address_line(address) {
+            frame["source"] = self.source();
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+
+    fn source(&self) -> Value {
+        json!({
+            "name": format!("{} (disassembly)", self.name),
+            "sourceReference": SOURCE_REFERENCE,
+        })
+    }
+
+    fn variables(&mut self, arguments: &Value) -> Result<Value, String> {
+        let reference = arguments["variablesReference"].as_u64().unwrap_or(0);
+        let machine = &self.debugger()?.machine;
+        let register = &machine.register;
+        let variables = match reference {
+            REGISTERS_REFERENCE => {
+                let mut variables = vec![
+                    variable("PC", format!("0x{:03X}", register.pc), 0),
+                    variable("I", format!("0x{:03X}", register.i), 0),
+                ];
+                for (vx, value) in register.v.iter().enumerate() {
+                    variables.push(variable(
+                        &format!("V{:X}", vx),
+                        format!("0x{:02X}", value),
+                        0,
+                    ));
+                }
+                variables
+            }
+            TIMERS_REFERENCE => vec![
+                variable("DT", register.delay_timer.to_string(), 0),
+                variable("ST", register.sound_timer.to_string(), 0),
+            ],
+            MEMORY_REFERENCE => (0..machine.memory.all.len() / PAGE_SIZE)
+                .map(|page| {
+                    let start = page * PAGE_SIZE;
+                    variable(
+                        &format!("0x{:04X}", start),
+                        format!("0x{:04X}-0x{:04X}", start, start + PAGE_SIZE - 1),
+                        PAGE_REFERENCE_BASE + page as u64,
+                    )
+                })
+                .collect(),
+            _ => {
+                let page = reference
+                    .checked_sub(PAGE_REFERENCE_BASE)
+                    .ok_or("unknown variables reference")? as usize;
+                let start = page * PAGE_SIZE;
+                let bytes = machine
+                    .memory
+                    .all
+                    .get(start..start + PAGE_SIZE)
+                    .ok_or("unknown variables reference")?;
+                bytes
+                    .chunks(ROW_SIZE)
+                    .enumerate()
+                    .map(|(row, bytes)| {
+                        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                        variable(
+                            &format!("0x{:04X}", start + row * ROW_SIZE),
+                            hex.join(" "),
+                            0,
+                        )
+                    })
+                    .collect()
+            }
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Run a debugger command typed into the debug console, see `DEBUGGER_HELP`.
+    fn evaluate(&mut self, arguments: &Value) -> Result<Value, String> {
+        let expression = arguments["expression"].as_str().unwrap_or_default();
+        let output = self.debugger()?.command(expression)?;
+        Ok(json!({ "result": output, "variablesReference": 0 }))
+    }
+
+    fn response(&mut self, request: &Value, body: Result<Value, String>) -> Value {
+        self.seq += 1;
+        let mut response = json!({
+            "seq": self.seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        response
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        self.seq += 1;
+        json!({ "seq": self.seq, "type": "event", "event": event, "body": body })
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> Value {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["description"] = json!(text.clone());
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    fn exited(&mut self) -> Vec<Value> {
+        self.terminated = true;
+        vec![
+            self.event("exited", json!({ "exitCode": 0 })),
+            self.event("terminated", json!({})),
+        ]
+    }
+}
+
+impl Default for DapServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn variable(name: &str, value: String, reference: u64) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": reference })
+}
+
+fn parse_reference(reference: &str) -> Option<u16> {
+    let digits = reference.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    /// Scripted client talking to a server over a local TCP connection.
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+        seq: u64,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            write_message(&mut self.writer, &request).unwrap();
+            let response = self.receive(|message| message["type"] == "response");
+            assert_eq!(response["request_seq"], self.seq);
+            assert_eq!(response["command"], command);
+            response
+        }
+
+        /// Skip messages until one matches `expected`.
+        fn receive(&mut self, expected: impl Fn(&Value) -> bool) -> Value {
+            loop {
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                if expected(&message) {
+                    return message;
+                }
+            }
+        }
+
+        fn event(&mut self, event: &str) -> Value {
+            self.receive(|message| message["event"] == event)
+        }
+    }
+
+    fn connect() -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let input = stream.try_clone().unwrap();
+            DapServer::new().serve(input, stream).unwrap();
+        });
+        let writer = TcpStream::connect(address).unwrap();
+        let reader = BufReader::new(writer.try_clone().unwrap());
+        Client {
+            reader,
+            writer,
+            seq: 0,
+        }
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(out, b"Content-Length: 9\r\n\r\n{\"seq\":1}");
+        let mut reader = &out[..];
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_session() {
+        // 200: CALL 206, 202: LD V1, 1, 204: JP 204, 206: LD V2, 2, 208: RET
+        let program = [0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x62, 0x02, 0x00, 0xee];
+        let rom = std::env::temp_dir().join(format!("chip-8-dap-{}.ch8", std::process::id()));
+        fs::write(&rom, program).unwrap();
+
+        let mut client = connect();
+        let response = client.request("initialize", json!({ "adapterID": "chip-8" }));
+        assert_eq!(response["body"]["supportsConfigurationDoneRequest"], true);
+        let response = client.request("launch", json!({ "program": "missing.ch8" }));
+        assert_eq!(response["success"], false);
+        let response = client.request(
+            "launch",
+            json!({ "program": rom.to_str().unwrap(), "stopOnEntry": true }),
+        );
+        assert_eq!(response["success"], true);
+        client.event("initialized");
+
+        // listing line 6 is "LD V2, 0x02" below the sub_206 label
+        let response = client.request(
+            "setBreakpoints",
+            json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 6 }] }),
+        );
+        assert_eq!(response["body"]["breakpoints"][0]["verified"], true);
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.event("stopped")["body"]["reason"], "entry");
+        let response = client.request("source", json!({ "sourceReference": 1 }));
+        let listing = response["body"]["content"].as_str().unwrap().to_string();
+        assert_eq!(
+            listing.lines().nth(5).unwrap().trim_start().get(..11),
+            Some("LD V2, 0x02")
+        );
+
+        client.request("continue", json!({ "threadId": 1 }));
+        let stopped = client.event("stopped");
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+        let response = client.request("stackTrace", json!({ "threadId": 1 }));
+        let frames = response["body"]["stackFrames"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["instructionPointerReference"], "0x206");
+        assert_eq!(frames[0]["line"], 6);
+        assert_eq!(frames[1]["name"], "200: CALL 0x206");
+
+        client.request("stepIn", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "step");
+        let response = client.request("variables", json!({ "variablesReference": 1 }));
+        let registers = &response["body"]["variables"];
+        assert_eq!(registers[0]["value"], "0x208");
+        assert_eq!(registers[4]["name"], "V2");
+        assert_eq!(registers[4]["value"], "0x02");
+
+        client.request("stepBack", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "step");
+        client.request("stepIn", json!({ "threadId": 1 }));
+        client.event("stopped");
+        client.request("reverseContinue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "breakpoint");
+        let response = client.request("variables", json!({ "variablesReference": 1 }));
+        assert_eq!(response["body"]["variables"][4]["value"], "0x00");
+        client.request("stepIn", json!({ "threadId": 1 }));
+        client.event("stopped");
+
+        client.request("stepOut", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "step");
+        let response = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(
+            response["body"]["stackFrames"][0]["instructionPointerReference"],
+            "0x202"
+        );
+        client.request("next", json!({ "threadId": 1 }));
+        client.event("stopped");
+
+        let response = client.request("variables", json!({ "variablesReference": 3 }));
+        assert_eq!(
+            response["body"]["variables"][2]["variablesReference"],
+            0x102
+        );
+        let response = client.request("variables", json!({ "variablesReference": 0x102 }));
+        assert_eq!(
+            response["body"]["variables"][0]["value"],
+            "22 06 61 01 12 04 62 02 00 EE 00 00 00 00 00 00"
+        );
+
+        client.request("continue", json!({ "threadId": 1 }));
+        client.request("pause", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "pause");
+        let response = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(response["body"]["stackFrames"][0]["name"], "204: JP 0x204");
+        client.request("disconnect", json!({}));
+        fs::remove_file(rom).unwrap();
+    }
+}
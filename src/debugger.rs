@@ -0,0 +1,625 @@
+use super::cpu::KEY_TAP_FRAMES;
+use super::disassembler::{instruction_length, mnemonic, Syntax};
+use super::error::EmulationError;
+use super::journal::Journal;
+use super::machine::Machine;
+use super::watch::{WatchHit, Watchpoint};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Number of instructions shown around PC.
+const DISASSEMBLY_LINES: usize = 9;
+
+/// Number of 8 byte rows in the memory hexdump.
+const DUMP_ROWS: usize = 6;
+
+pub const DEBUGGER_HELP: &str = "commands:
+  s              step one instruction
+  n              step over a subroutine call
+  o              step out of the current subroutine
+  p              step back one instruction
+  c              continue until a breakpoint, enter pauses
+  r              run backwards until a breakpoint
+  u ADDR         run until PC reaches ADDR
+  b ADDR         toggle a breakpoint at ADDR
+  w r ADDR[-END] toggle a watchpoint on reads of ADDR to END
+  w w ADDR[-END] toggle a watchpoint on writes to ADDR to END
+  w v X          toggle a watchpoint on changes of VX
+  w i ADDR[-END] toggle a watchpoint on I being set to ADDR to END
+  x [ADDR]       show memory from ADDR, or from I without ADDR
+  k KEY          tap a keypad key
+  q              quit";
+
+/// Why a running debugger stopped.
+#[derive(Debug)]
+pub enum Stop {
+    Breakpoint(u16),
+    /// The last instruction triggered a watchpoint.
+    Watchpoint(WatchHit),
+    /// A step over, step out or run to cursor reached its target.
+    Reached(u16),
+    Halted,
+    /// Running backwards reached the oldest instruction in the journal.
+    JournalStart(u16),
+    Error(EmulationError),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(address) => write!(f, "breakpoint at {:03X}", address),
+            Stop::Watchpoint(hit) => write!(f, "watchpoint: {}", hit),
+            Stop::Reached(address) => write!(f, "stopped at {:03X}", address),
+            Stop::Halted => write!(f, "program exited"),
+            Stop::JournalStart(address) => {
+                write!(
+                    f,
+                    "reached the oldest recorded instruction at {:03X}",
+                    address
+                )
+            }
+            Stop::Error(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+/// Condition ending a run.
+#[derive(Clone, Copy)]
+enum Run {
+    Continue,
+    To(u16),
+    /// Until the call stack is no deeper than the given depth.
+    Return(usize),
+}
+
+/// Execution control for a `Machine`.
+/// While paused, the machine only moves through `step`, `step_over` and `step_out`. Once
+/// resumed, the frontend calls `run_frame` every frame until it returns why the run stopped.
+/// Timers are ticked after every `cycles_per_frame` instructions, however they were executed.
+/// Executed instructions are recorded in the journal of the machine, which is enabled if it was
+/// not, so that `step_back` and `reverse_continue` can run the program backwards.
+pub struct Debugger {
+    pub machine: Machine,
+    pub breakpoints: BTreeSet<u16>,
+    /// Start of the memory hexdump, following I if unset.
+    pub dump_address: Option<u16>,
+    /// Watchpoints triggered by the last instruction.
+    pub hits: Vec<WatchHit>,
+    cycle: usize,
+    run: Option<Run>,
+    resumed: bool,
+}
+
+impl Debugger {
+    pub fn new(mut machine: Machine) -> Self {
+        if machine.journal.is_none() {
+            machine.journal = Some(Journal::default());
+        }
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            dump_address: None,
+            hits: Vec::new(),
+            cycle: 0,
+            run: None,
+            resumed: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        self.run = None;
+    }
+
+    /// Run until a breakpoint is hit. A breakpoint at PC itself is passed.
+    pub fn resume(&mut self) {
+        self.start(Run::Continue);
+    }
+
+    /// Run until PC reaches `address` or a breakpoint is hit.
+    pub fn run_to(&mut self, address: u16) {
+        self.start(Run::To(address));
+    }
+
+    fn start(&mut self, run: Run) {
+        self.run = Some(run);
+        self.resumed = true;
+    }
+
+    /// Execute one instruction, collecting the watchpoints it triggered in `hits`.
+    pub fn step(&mut self) -> Result<(), EmulationError> {
+        let result = self.machine.step();
+        self.hits = self.machine.take_watch_hits();
+        result?;
+        self.cycle += 1;
+        if self.cycle >= self.machine.cycles_per_frame {
+            self.cycle = 0;
+            self.machine.end_frame();
+        }
+        Ok(())
+    }
+
+    /// Undo the last instruction. Returns false if the journal holds nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        if !self.machine.step_back() {
+            return false;
+        }
+        self.hits.clear();
+        let cycles_per_frame = self.machine.cycles_per_frame.max(1);
+        self.cycle = (self.cycle + cycles_per_frame - 1) % cycles_per_frame;
+        true
+    }
+
+    /// Undo instructions until PC reaches a breakpoint or the journal runs out.
+    /// At least one instruction is undone, so a breakpoint at PC itself is passed.
+    pub fn reverse_continue(&mut self) -> Stop {
+        self.pause();
+        while self.step_back() {
+            let pc = self.machine.register.pc;
+            if self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+        }
+        Stop::JournalStart(self.machine.register.pc)
+    }
+
+    /// Execute one instruction, running a called subroutine until it returns.
+    pub fn step_over(&mut self) -> Result<(), EmulationError> {
+        let pc = self.machine.register.pc as usize;
+        let is_call = self.machine.memory.all.get(pc).map(|b| b >> 4) == Some(0x2);
+        let depth = self.machine.memory.stack.len();
+        self.step()?;
+        if is_call && !self.machine.is_waiting_for_key() {
+            self.run = Some(Run::Return(depth));
+            self.resumed = false;
+        }
+        Ok(())
+    }
+
+    /// Run until the current subroutine returns. Outside of a subroutine this runs like `resume`.
+    pub fn step_out(&mut self) {
+        match self.machine.memory.stack.len() {
+            0 => self.start(Run::Continue),
+            depth => self.start(Run::Return(depth - 1)),
+        }
+    }
+
+    /// Run the rest of the current frame, or less if the run stops.
+    /// Returns why the run stopped, or None if it did not stop or the debugger is paused.
+    pub fn run_frame(&mut self) -> Option<Stop> {
+        let run = self.run?;
+        loop {
+            if self.machine.is_halted() {
+                return self.stop(Stop::Halted);
+            }
+            let pc = self.machine.register.pc;
+            if !self.resumed && !self.machine.is_waiting_for_key() {
+                if self.breakpoints.contains(&pc) {
+                    return self.stop(Stop::Breakpoint(pc));
+                }
+                let reached = match run {
+                    Run::Continue => false,
+                    Run::To(address) => address == pc,
+                    Run::Return(depth) => self.machine.memory.stack.len() <= depth,
+                };
+                if reached {
+                    return self.stop(Stop::Reached(pc));
+                }
+            }
+            self.resumed = false;
+            if let Err(err) = self.step() {
+                return self.stop(Stop::Error(err));
+            }
+            if let Some(&hit) = self.hits.first() {
+                return self.stop(Stop::Watchpoint(hit));
+            }
+            if self.cycle == 0 {
+                return None;
+            }
+        }
+    }
+
+    fn stop(&mut self, stop: Stop) -> Option<Stop> {
+        self.run = None;
+        Some(stop)
+    }
+
+    /// Execute a debugger command, see `DEBUGGER_HELP`. Returns the text to show the user, or why
+    /// the command failed.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(String::new()),
+        };
+        let argument = words.next();
+        let address = || match argument.map(parse_hex) {
+            Some(Some(address)) => Ok(address),
+            _ => Err(format!("{} needs a hexadecimal address", name)),
+        };
+        let result = match name {
+            "s" | "step" => {
+                self.step().map_err(|err| err.to_string())?;
+                return Ok(self.report_hits());
+            }
+            "n" | "next" => {
+                self.step_over().map_err(|err| err.to_string())?;
+                return Ok(self.report_hits());
+            }
+            "o" | "out" => {
+                self.step_out();
+                Ok(())
+            }
+            "p" | "back" => match self.step_back() {
+                true => Ok(()),
+                false => Err("no earlier instruction recorded".to_string()),
+            },
+            "r" | "reverse" => return Ok(self.reverse_continue().to_string()),
+            "c" | "continue" => {
+                self.resume();
+                Ok(())
+            }
+            "u" | "until" => address().map(|address| self.run_to(address)),
+            "b" | "break" => address().map(|address| {
+                if !self.breakpoints.remove(&address) {
+                    self.breakpoints.insert(address);
+                }
+            }),
+            "w" | "watch" => match parse_watchpoint(argument, words.next()) {
+                Some(watchpoint) => {
+                    if !self.machine.remove_watchpoint(watchpoint) {
+                        self.machine.add_watchpoint(watchpoint);
+                    }
+                    Ok(())
+                }
+                None => Err("w needs r, w or i with an address range, or v with a register".into()),
+            },
+            "x" | "dump" => match argument {
+                None => {
+                    self.dump_address = None;
+                    Ok(())
+                }
+                Some(_) => address().map(|address| self.dump_address = Some(address)),
+            },
+            "k" | "key" => match argument.map(parse_hex) {
+                Some(Some(key)) if key < 16 => {
+                    self.machine.tap_key(key as u8, KEY_TAP_FRAMES);
+                    Ok(())
+                }
+                _ => Err("k needs a key from 0 to F".to_string()),
+            },
+            "h" | "help" => return Ok(DEBUGGER_HELP.to_string()),
+            _ => Err(format!("unknown command {}, h for help", name)),
+        };
+        result.map(|()| String::new())
+    }
+
+    /// Describe the watchpoints triggered by the last step, one per line.
+    fn report_hits(&self) -> String {
+        let hits: Vec<String> = self
+            .hits
+            .iter()
+            .map(|hit| format!("watchpoint: {}", hit))
+            .collect();
+        hits.join("\n")
+    }
+
+    /// The screen with panels for the registers, timers, call stack, disassembly around PC and a
+    /// memory hexdump next to it.
+    pub fn view(&self) -> String {
+        let screen = self.screen();
+        let panels = self.panels();
+        let width = screen.first().map_or(0, |row| row.chars().count());
+        let rows = screen.len().max(panels.len());
+        let mut out = String::new();
+        for row in 0..rows {
+            let left = screen.get(row).map_or("", String::as_str);
+            let right = panels.get(row).map_or("", String::as_str);
+            let padding = width - left.chars().count();
+            let line = format!("{}{}  {}", left, " ".repeat(padding), right);
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Framebuffer drawn with half blocks, two pixel rows per line.
+    fn screen(&self) -> Vec<String> {
+        let graphic = &self.machine.graphic;
+        let width = graphic.width();
+        let lit = |x: usize, y: usize| graphic.gfx[y * width + x] != 0;
+        let border = format!("+{}+", "-".repeat(width));
+        let mut lines = vec![border.clone()];
+        for y in (0..graphic.height()).step_by(2) {
+            let row: String = (0..width)
+                .map(|x| match (lit(x, y), lit(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect();
+            lines.push(format!("|{}|", row));
+        }
+        lines.push(border);
+        lines
+    }
+
+    fn panels(&self) -> Vec<String> {
+        let register = &self.machine.register;
+        let memory = &self.machine.memory;
+        let mut lines = vec![format!(
+            "PC {:03X}  I {:03X}  DT {:02X}  ST {:02X}",
+            register.pc, register.i, register.delay_timer, register.sound_timer
+        )];
+        for (row, values) in register.v.chunks(4).enumerate() {
+            let cells: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value))
+                .collect();
+            lines.push(cells.join("  "));
+        }
+        let stack: Vec<String> = memory
+            .stack
+            .iter()
+            .rev()
+            .map(|address| format!("{:03X}", address))
+            .collect();
+        lines.push(format!("stack  {}", stack.join(" ")));
+        let state = if self.machine.is_halted() {
+            "exited".to_string()
+        } else if let Some(vx) = register.waiting_for_key {
+            format!("waiting for a key into V{:X}", vx)
+        } else if self.is_running() {
+            "running".to_string()
+        } else {
+            "paused".to_string()
+        };
+        lines.push(format!("state  {}", state));
+        let watches: Vec<String> = self
+            .machine
+            .watchpoints()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        if !watches.is_empty() {
+            lines.push(format!("watch  {}", watches.join(", ")));
+        }
+
+        lines.push(String::new());
+        let mut address = register.pc.saturating_sub(6) as usize;
+        for _ in 0..DISASSEMBLY_LINES {
+            let bytes = match memory.all.get(address..) {
+                Some(bytes) if bytes.len() >= 2 => bytes,
+                _ => break,
+            };
+            let (length, text) = match mnemonic(bytes, Syntax::Cowgod) {
+                Some(text) => (instruction_length(bytes).unwrap_or(2), text),
+                None => (2, format!("db 0x{:02X}, 0x{:02X}", bytes[0], bytes[1])),
+            };
+            let raw: String = bytes[..length]
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect();
+            lines.push(format!(
+                "{}{} {:03X}  {:<8} {}",
+                if self.breakpoints.contains(&(address as u16)) {
+                    '*'
+                } else {
+                    ' '
+                },
+                if address == register.pc as usize {
+                    '>'
+                } else {
+                    ' '
+                },
+                address,
+                raw,
+                text
+            ));
+            address += length;
+        }
+
+        lines.push(String::new());
+        let start = self.dump_address.unwrap_or(register.i) as usize & !0x7;
+        for row in 0..DUMP_ROWS {
+            let address = start + row * 8;
+            let bytes = match memory.all.get(address..memory.all.len().min(address + 8)) {
+                Some(bytes) if !bytes.is_empty() => bytes,
+                _ => break,
+            };
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            lines.push(format!("{:04X}  {}", address, hex.join(" ")));
+        }
+        lines
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Parse the arguments of the `w` command, e.g. `w 300-30f` or `v 3`.
+fn parse_watchpoint(kind: Option<&str>, argument: Option<&str>) -> Option<Watchpoint> {
+    let argument = argument?;
+    if kind? == "v" {
+        return match parse_hex(argument)? {
+            vx if vx < 16 => Some(Watchpoint::Register(vx as usize)),
+            _ => None,
+        };
+    }
+    let (start, end) = match argument.find('-') {
+        Some(dash) => (
+            parse_hex(&argument[..dash])?,
+            parse_hex(&argument[dash + 1..])?,
+        ),
+        None => (parse_hex(argument)?, parse_hex(argument)?),
+    };
+    if end < start {
+        return None;
+    }
+    let (first, last) = (start as usize, end as usize);
+    match kind? {
+        "r" => Some(Watchpoint::Read {
+            start: first,
+            end: last,
+        }),
+        "w" => Some(Watchpoint::Write {
+            start: first,
+            end: last,
+        }),
+        "i" => Some(Watchpoint::Index { start, end }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 200: CALL 206, 202: LD V1, 1, 204: JP 204, 206: LD V2, 2, 208: RET
+    fn debugger() -> Debugger {
+        let mut machine = Machine::new();
+        let program = [0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x62, 0x02, 0x00, 0xee];
+        machine.memory.all[0x200..0x20a].copy_from_slice(&program);
+        Debugger::new(machine)
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut debugger = debugger();
+        debugger.breakpoints.insert(0x208);
+        debugger.resume();
+        assert!(matches!(
+            debugger.run_frame(),
+            Some(Stop::Breakpoint(0x208))
+        ));
+        assert!(!debugger.is_running());
+        assert_eq!(debugger.machine.register.v[2], 2);
+        debugger.resume();
+        assert!(debugger.run_frame().is_none());
+        assert_eq!(debugger.machine.register.pc, 0x204);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut debugger = debugger();
+        debugger.step_over().unwrap();
+        assert!(matches!(debugger.run_frame(), Some(Stop::Reached(0x202))));
+        assert_eq!(debugger.machine.register.v[2], 2);
+
+        let mut debugger = self::debugger();
+        debugger.step().unwrap();
+        assert_eq!(debugger.machine.register.pc, 0x206);
+        debugger.step_out();
+        assert!(matches!(debugger.run_frame(), Some(Stop::Reached(0x202))));
+        assert!(debugger.machine.memory.stack.is_empty());
+    }
+
+    #[test]
+    fn test_run_to_and_timers() {
+        let mut debugger = debugger();
+        debugger.machine.register.delay_timer = 3;
+        debugger.run_to(0x204);
+        assert!(matches!(debugger.run_frame(), Some(Stop::Reached(0x204))));
+        assert_eq!(debugger.machine.register.delay_timer, 3);
+        for _ in 0..8 {
+            debugger.step().unwrap();
+        }
+        assert_eq!(debugger.machine.register.delay_timer, 2);
+    }
+
+    #[test]
+    fn test_step_back_and_reverse_continue() {
+        let mut debugger = debugger();
+        debugger.breakpoints.insert(0x206);
+        debugger.machine.register.delay_timer = 5;
+        for _ in 0..10 {
+            debugger.step().unwrap();
+        }
+        assert_eq!(debugger.machine.register.delay_timer, 4);
+        assert!(matches!(
+            debugger.reverse_continue(),
+            Stop::Breakpoint(0x206)
+        ));
+        assert_eq!(debugger.machine.register.v[2], 0);
+        assert_eq!(debugger.machine.register.delay_timer, 5);
+        assert_eq!(debugger.machine.memory.stack, [0x202]);
+        assert!(debugger.step_back());
+        assert_eq!(debugger.machine.register.pc, 0x200);
+        assert!(matches!(
+            debugger.reverse_continue(),
+            Stop::JournalStart(0x200)
+        ));
+        assert_eq!(
+            debugger.command("r"),
+            Ok("reached the oldest recorded instruction at 200".to_string())
+        );
+        assert_eq!(
+            debugger.command("p"),
+            Err("no earlier instruction recorded".to_string())
+        );
+        debugger.step().unwrap();
+        assert_eq!(debugger.command("p"), Ok(String::new()));
+        assert_eq!(debugger.machine.register.pc, 0x200);
+    }
+
+    #[test]
+    fn test_command_and_view() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.command("b 206"), Ok(String::new()));
+        assert!(debugger.breakpoints.contains(&0x206));
+        assert_eq!(debugger.command("s"), Ok(String::new()));
+        assert_eq!(debugger.command("x 0x300"), Ok(String::new()));
+        assert_eq!(debugger.dump_address, Some(0x300));
+        assert_eq!(debugger.command("h"), Ok(DEBUGGER_HELP.to_string()));
+        assert_eq!(
+            debugger.command("u"),
+            Err("u needs a hexadecimal address".to_string())
+        );
+        assert_eq!(
+            debugger.command("k 1f"),
+            Err("k needs a key from 0 to F".to_string())
+        );
+        let view = debugger.view();
+        assert!(view.contains("PC 206  I 000"));
+        assert!(view.contains("stack  202"));
+        assert!(view.contains("*> 206  6202     LD V2, 0x02"));
+        assert!(view.contains("0300  00 00 00 00 00 00 00 00"));
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.command("w v 2"), Ok(String::new()));
+        assert_eq!(
+            debugger.command("w i 300-2ff"),
+            Err("w needs r, w or i with an address range, or v with a register".to_string())
+        );
+        assert!(debugger.view().contains("watch  V2"));
+        debugger.resume();
+        assert!(matches!(
+            debugger.run_frame(),
+            Some(Stop::Watchpoint(WatchHit::Register {
+                vx: 0x2,
+                old: 0,
+                new: 2
+            }))
+        ));
+        assert_eq!(debugger.machine.register.pc, 0x208);
+        assert_eq!(debugger.command("w v 2"), Ok(String::new()));
+        assert!(debugger.machine.watchpoints().is_empty());
+        assert_eq!(debugger.command("w w 200-2ff"), Ok(String::new()));
+        assert_eq!(debugger.command("s"), Ok(String::new()));
+        assert_eq!(debugger.command("w v 1"), Ok(String::new()));
+        assert_eq!(
+            debugger.command("s"),
+            Ok("watchpoint: V1 changed from 00 to 01".to_string())
+        );
+    }
+}
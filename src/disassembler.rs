@@ -0,0 +1,517 @@
+use super::memory::PROGRAM_START;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Mnemonic syntax of a listing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// Upper case mnemonics of Cowgod's Chip-8 technical reference, e.g. `LD VA, 0x2B`.
+    Cowgod,
+    /// Octo assembly language, e.g. `va := 0x2B`.
+    Octo,
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Syntax::Cowgod => "cowgod",
+            Syntax::Octo => "octo",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("unknown syntax {}", name)),
+        }
+    }
+}
+
+/// Why an address is referenced, deciding the name of its label.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Reference {
+    Subroutine,
+    Jump,
+    Data,
+}
+
+/// One line of a listing, either a single instruction or a run of data bytes.
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Set if the bytes were reached as an instruction.
+    pub code: bool,
+}
+
+/// A ROM split into instructions and data.
+/// Code is found by following every path of execution from the start of the program, so bytes
+/// which are never executed, like sprites, are kept as data. Targets of `1nnn`, `2nnn`, `Bnnn`,
+/// `Annn` and `F000 nnnn` inside the ROM get labels.
+pub struct Disassembly {
+    pub lines: Vec<Line>,
+    pub labels: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    /// Disassemble a ROM loaded at `PROGRAM_START`.
+    pub fn new(rom: &[u8]) -> Self {
+        Self::with_origin(rom, PROGRAM_START as u16)
+    }
+
+    pub fn with_origin(rom: &[u8], origin: u16) -> Self {
+        let origin = origin as usize;
+        let end = origin + rom.len();
+        let at = |address: usize| &rom[address - origin..];
+
+        let mut code = BTreeSet::new();
+        let mut references = BTreeMap::new();
+        let mut refer = |address: usize, reference: Reference| {
+            let entry = references.entry(address).or_insert(reference);
+            *entry = reference.min(*entry);
+        };
+        let mut pending = vec![origin];
+        while let Some(address) = pending.pop() {
+            if address < origin || address >= end || code.contains(&address) {
+                continue;
+            }
+            let bytes = at(address);
+            let length = match instruction_length(bytes) {
+                Some(length) => length,
+                None => continue,
+            };
+            code.insert(address);
+            let op = opcode(bytes);
+            let nnn = (op & 0x0FFF) as usize;
+            let next = address + length;
+            match op >> 12 {
+                0x0 if op == 0x00EE || op == 0x00FD => {}
+                0x1 => {
+                    refer(nnn, Reference::Jump);
+                    pending.push(nnn);
+                }
+                0x2 => {
+                    refer(nnn, Reference::Subroutine);
+                    pending.push(nnn);
+                    pending.push(next);
+                }
+                0xA => {
+                    refer(nnn, Reference::Data);
+                    pending.push(next);
+                }
+                0xB => {
+                    refer(nnn, Reference::Jump);
+                    pending.push(nnn);
+                }
+                0xF if op == 0xF000 => {
+                    refer(long_address(bytes) as usize, Reference::Data);
+                    pending.push(next);
+                }
+                _ if is_skip(op) => {
+                    pending.push(next);
+                    let long = next + 2 <= end && opcode(at(next)) == 0xF000;
+                    pending.push(next + if long { 4 } else { 2 });
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        let mut lines = Vec::new();
+        let mut address = origin;
+        while address < end {
+            let length = if code.contains(&address) {
+                instruction_length(at(address))
+                    .filter(|length| (address + 1..address + length).all(|a| !code.contains(&a)))
+            } else {
+                None
+            };
+            let (length, is_code) = match length {
+                Some(length) => (length, true),
+                None => {
+                    let mut next = address + 1;
+                    while next < end
+                        && next - address < 8
+                        && !code.contains(&next)
+                        && !references.contains_key(&next)
+                    {
+                        next += 1;
+                    }
+                    (next - address, false)
+                }
+            };
+            lines.push(Line {
+                address: address as u16,
+                bytes: at(address)[..length].to_vec(),
+                code: is_code,
+            });
+            address += length;
+        }
+
+        let starts: BTreeSet<u16> = lines.iter().map(|line| line.address).collect();
+        let labels = references
+            .into_iter()
+            .filter(|(address, _)| starts.contains(&(*address as u16)))
+            .map(|(address, reference)| {
+                let prefix = match reference {
+                    Reference::Subroutine => "sub",
+                    Reference::Jump => "label",
+                    Reference::Data => "data",
+                };
+                (address as u16, format!("{}_{:03X}", prefix, address))
+            })
+            .collect();
+        Disassembly { lines, labels }
+    }
+
+    /// Mnemonic or data directive of `line`, referring to labelled addresses by name.
+    pub fn text(&self, line: &Line, syntax: Syntax) -> String {
+        let target = |address: u16| match self.labels.get(&address) {
+            Some(label) => label.clone(),
+            None => format!("0x{:03X}", address),
+        };
+        if line.code {
+            if let Some(text) = format(&line.bytes, syntax, &target) {
+                return text;
+            }
+        }
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+        match syntax {
+            Syntax::Cowgod => format!("db {}", bytes.join(", ")),
+            Syntax::Octo => bytes.join(" "),
+        }
+    }
+
+    /// Full listing with labels, mnemonics, and the address and raw bytes of every line in a
+    /// trailing comment. Listings in either syntax assemble back into the original ROM.
+    pub fn listing(&self, syntax: Syntax) -> String {
+        self.annotated_listing(syntax, &|_| String::new())
+    }
+
+    /// Listing with `annotate(line)` put in front of every line. Label lines are indented by the
+    /// width of the annotation of the line they label.
+    pub fn annotated_listing(&self, syntax: Syntax, annotate: &dyn Fn(&Line) -> String) -> String {
+        let comment = match syntax {
+            Syntax::Cowgod => ';',
+            Syntax::Octo => '#',
+        };
+        let mut out = String::new();
+        for line in &self.lines {
+            let annotation = annotate(line);
+            if let Some(label) = self.labels.get(&line.address) {
+                let indent = " ".repeat(annotation.chars().count());
+                match syntax {
+                    Syntax::Cowgod => out.push_str(&format!("{}{}:\n", indent, label)),
+                    Syntax::Octo => out.push_str(&format!("{}: {}\n", indent, label)),
+                }
+            }
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            out.push_str(&format!(
+                "{}    {:<31} {} {:03X}: {}\n",
+                annotation,
+                self.text(line, syntax),
+                comment,
+                line.address,
+                bytes.join(" ")
+            ));
+        }
+        out
+    }
+
+    /// Address shown on each line of `listing`, a label line taking the address it labels.
+    pub fn listing_addresses(&self) -> Vec<u16> {
+        let mut addresses = Vec::new();
+        for line in &self.lines {
+            if self.labels.contains_key(&line.address) {
+                addresses.push(line.address);
+            }
+            addresses.push(line.address);
+        }
+        addresses
+    }
+}
+
+/// Length in bytes of the instruction at the start of `bytes`, or None if it does not hold a
+/// known instruction.
+pub fn instruction_length(bytes: &[u8]) -> Option<usize> {
+    format(bytes, Syntax::Cowgod, &hex).map(|_| if opcode(bytes) == 0xF000 { 4 } else { 2 })
+}
+
+/// Mnemonic of the instruction at the start of `bytes` with numeric addresses.
+pub fn mnemonic(bytes: &[u8], syntax: Syntax) -> Option<String> {
+    format(bytes, syntax, &hex)
+}
+
+fn hex(address: u16) -> String {
+    format!("0x{:03X}", address)
+}
+
+fn opcode(bytes: &[u8]) -> u16 {
+    ((bytes[0] as u16) << 8) | bytes[1] as u16
+}
+
+fn long_address(bytes: &[u8]) -> u16 {
+    ((bytes[2] as u16) << 8) | bytes[3] as u16
+}
+
+fn is_skip(op: u16) -> bool {
+    match op >> 12 {
+        0x3 | 0x4 => true,
+        0x5 | 0x9 => op & 0xF == 0,
+        0xE => op & 0xFF == 0x9E || op & 0xFF == 0xA1,
+        _ => false,
+    }
+}
+
+fn format(bytes: &[u8], syntax: Syntax, target: &dyn Fn(u16) -> String) -> Option<String> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let op = opcode(bytes);
+    if op == 0xF000 {
+        if bytes.len() < 4 {
+            return None;
+        }
+        let address = target(long_address(bytes));
+        return Some(match syntax {
+            Syntax::Cowgod => format!("LD I, LONG {}", address),
+            Syntax::Octo => format!("i := long {}", address),
+        });
+    }
+    match syntax {
+        Syntax::Cowgod => cowgod(op, target),
+        Syntax::Octo => octo(op, target),
+    }
+}
+
+fn cowgod(op: u16, target: &dyn Fn(u16) -> String) -> Option<String> {
+    let x = (op >> 8) & 0xF;
+    let y = (op >> 4) & 0xF;
+    let n = op & 0xF;
+    let kk = op & 0xFF;
+    let nnn = op & 0xFFF;
+    let text = match (op >> 12, x, y, n) {
+        (0x0, 0x0, 0xC, _) => format!("SCD {}", n),
+        (0x0, 0x0, 0xD, _) => format!("SCU {}", n),
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_string(),
+        (0x0, 0x0, 0xF, 0xB) => "SCR".to_string(),
+        (0x0, 0x0, 0xF, 0xC) => "SCL".to_string(),
+        (0x0, 0x0, 0xF, 0xD) => "EXIT".to_string(),
+        (0x0, 0x0, 0xF, 0xE) => "LOW".to_string(),
+        (0x0, 0x0, 0xF, 0xF) => "HIGH".to_string(),
+        (0x0, _, _, _) => format!("SYS {}", target(nnn)),
+        (0x1, _, _, _) => format!("JP {}", target(nnn)),
+        (0x2, _, _, _) => format!("CALL {}", target(nnn)),
+        (0x3, _, _, _) => format!("SE V{:X}, 0x{:02X}", x, kk),
+        (0x4, _, _, _) => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x5, _, _, 0x2) => format!("SAVE V{:X}, V{:X}", x, y),
+        (0x5, _, _, 0x3) => format!("LOAD V{:X}, V{:X}", x, y),
+        (0x6, _, _, _) => format!("LD V{:X}, 0x{:02X}", x, kk),
+        (0x7, _, _, _) => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        (0x8, _, _, _) => {
+            let name = match n {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return None,
+            };
+            format!("{} V{:X}, V{:X}", name, x, y)
+        }
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _, _) => format!("LD I, {}", target(nnn)),
+        (0xB, _, _, _) => format!("JP V0, {}", target(nnn)),
+        (0xC, _, _, _) => format!("RND V{:X}, 0x{:02X}", x, kk),
+        (0xD, _, _, _) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", x),
+        (0xF, _, 0x0, 0x1) => format!("PLANE {}", x),
+        (0xF, 0x0, 0x0, 0x2) => "AUDIO".to_string(),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xF, _, 0x3, 0x0) => format!("LD HF, V{:X}", x),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xF, _, 0x3, 0xA) => format!("PITCH V{:X}", x),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        (0xF, _, 0x7, 0x5) => format!("LD R, V{:X}", x),
+        (0xF, _, 0x8, 0x5) => format!("LD V{:X}, R", x),
+        _ => return None,
+    };
+    Some(text)
+}
+
+fn octo(op: u16, target: &dyn Fn(u16) -> String) -> Option<String> {
+    // Decide what is an instruction in one place and only spell it differently here.
+    cowgod(op, &hex)?;
+    let x = (op >> 8) & 0xF;
+    let y = (op >> 4) & 0xF;
+    let n = op & 0xF;
+    let kk = op & 0xFF;
+    let nnn = op & 0xFFF;
+    let text = match (op >> 12, x, y, n) {
+        (0x0, 0x0, 0xC, _) => format!("scroll-down {}", n),
+        (0x0, 0x0, 0xD, _) => format!("scroll-up {}", n),
+        (0x0, 0x0, 0xE, 0x0) => "clear".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "return".to_string(),
+        (0x0, 0x0, 0xF, 0xB) => "scroll-right".to_string(),
+        (0x0, 0x0, 0xF, 0xC) => "scroll-left".to_string(),
+        (0x0, 0x0, 0xF, 0xD) => "exit".to_string(),
+        (0x0, 0x0, 0xF, 0xE) => "lores".to_string(),
+        (0x0, 0x0, 0xF, 0xF) => "hires".to_string(),
+        // Octo has no machine code calls, so they are written out as bytes.
+        (0x0, _, _, _) => format!("0x{:02X} 0x{:02X}", op >> 8, kk),
+        (0x1, _, _, _) => format!("jump {}", target(nnn)),
+        (0x2, _, _, _) => format!(":call {}", target(nnn)),
+        (0x3, _, _, _) => format!("if v{:x} != 0x{:02X} then", x, kk),
+        (0x4, _, _, _) => format!("if v{:x} == 0x{:02X} then", x, kk),
+        (0x5, _, _, 0x0) => format!("if v{:x} != v{:x} then", x, y),
+        (0x5, _, _, 0x2) => format!("save v{:x} - v{:x}", x, y),
+        (0x5, _, _, _) => format!("load v{:x} - v{:x}", x, y),
+        (0x6, _, _, _) => format!("v{:x} := 0x{:02X}", x, kk),
+        (0x7, _, _, _) => format!("v{:x} += 0x{:02X}", x, kk),
+        (0x8, _, _, _) => {
+            let operator = match n {
+                0x0 => ":=",
+                0x1 => "|=",
+                0x2 => "&=",
+                0x3 => "^=",
+                0x4 => "+=",
+                0x5 => "-=",
+                0x6 => ">>=",
+                0x7 => "=-",
+                _ => "<<=",
+            };
+            format!("v{:x} {} v{:x}", x, operator, y)
+        }
+        (0x9, _, _, _) => format!("if v{:x} == v{:x} then", x, y),
+        (0xA, _, _, _) => format!("i := {}", target(nnn)),
+        (0xB, _, _, _) => format!("jump0 {}", target(nnn)),
+        (0xC, _, _, _) => format!("v{:x} := random 0x{:02X}", x, kk),
+        (0xD, _, _, _) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        (0xE, _, 0x9, _) => format!("if v{:x} -key then", x),
+        (0xE, _, _, _) => format!("if v{:x} key then", x),
+        (0xF, _, 0x0, 0x1) => format!("plane {}", x),
+        (0xF, _, 0x0, 0x2) => "audio".to_string(),
+        (0xF, _, 0x0, 0x7) => format!("v{:x} := delay", x),
+        (0xF, _, 0x0, _) => format!("v{:x} := key", x),
+        (0xF, _, 0x1, 0x5) => format!("delay := v{:x}", x),
+        (0xF, _, 0x1, 0x8) => format!("buzzer := v{:x}", x),
+        (0xF, _, 0x1, _) => format!("i += v{:x}", x),
+        (0xF, _, 0x2, _) => format!("i := hex v{:x}", x),
+        (0xF, _, 0x3, 0x0) => format!("i := bighex v{:x}", x),
+        (0xF, _, 0x3, 0x3) => format!("bcd v{:x}", x),
+        (0xF, _, 0x3, _) => format!("pitch := v{:x}", x),
+        (0xF, _, 0x5, _) => format!("save v{:x}", x),
+        (0xF, _, 0x6, _) => format!("load v{:x}", x),
+        (0xF, _, 0x7, _) => format!("saveflags v{:x}", x),
+        _ => format!("loadflags v{:x}", x),
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: [u8; 14] = [
+        0x00, 0xe0, // CLS
+        0xa2, 0x0a, // LD I, data_20A
+        0x22, 0x08, // CALL sub_208
+        0x12, 0x06, // JP label_206
+        0x00, 0xee, // RET
+        0xf0, 0x90, 0x90, 0xf0, // sprite
+    ];
+
+    #[test]
+    fn test_cowgod_listing() {
+        let listing = Disassembly::new(&ROM).listing(Syntax::Cowgod);
+        let expected = [
+            "    CLS                             ; 200: 00 E0",
+            "    LD I, data_20A                  ; 202: A2 0A",
+            "    CALL sub_208                    ; 204: 22 08",
+            "label_206:",
+            "    JP label_206                    ; 206: 12 06",
+            "sub_208:",
+            "    RET                             ; 208: 00 EE",
+            "data_20A:",
+            "    db 0xF0, 0x90, 0x90, 0xF0       ; 20A: F0 90 90 F0",
+        ];
+        assert_eq!(listing.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_octo_listing() {
+        let disassembly = Disassembly::new(&ROM);
+        let texts: Vec<String> = disassembly
+            .lines
+            .iter()
+            .map(|line| disassembly.text(line, Syntax::Octo))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "clear",
+                "i := data_20A",
+                ":call sub_208",
+                "jump label_206",
+                "return",
+                "0xF0 0x90 0x90 0xF0"
+            ]
+        );
+        assert!(disassembly.listing(Syntax::Octo).contains(": sub_208\n"));
+    }
+
+    #[test]
+    fn test_skips_and_unknown_opcodes() {
+        let rom = [
+            0x30, 0x01, // SE V0, 0x01
+            0xf0, 0x00, 0x02, 0x0a, // LD I, LONG 0x20A
+            0x00, 0xfd, // EXIT
+            0x81, 0x28, // never reached, and no instruction either
+            0x00, 0xfd,
+        ];
+        let disassembly = Disassembly::new(&rom);
+        let code: Vec<(u16, bool)> = disassembly
+            .lines
+            .iter()
+            .map(|line| (line.address, line.code))
+            .collect();
+        assert_eq!(
+            code,
+            [
+                (0x200, true),
+                (0x202, true),
+                (0x206, true),
+                (0x208, false),
+                (0x20a, false)
+            ]
+        );
+        assert_eq!(
+            disassembly.text(&disassembly.lines[1], Syntax::Cowgod),
+            "LD I, LONG data_20A"
+        );
+        assert_eq!(mnemonic(&[0x81, 0x28], Syntax::Cowgod), None);
+        assert_eq!(mnemonic(&[0x91, 0x21], Syntax::Octo), None);
+        assert_eq!(
+            mnemonic(&[0x8a, 0xbe], Syntax::Octo),
+            Some("va <<= vb".to_string())
+        );
+    }
+}
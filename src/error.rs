@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors raised while loading a ROM or executing its instructions.
+#[derive(Debug)]
+pub enum EmulationError {
+    /// The opcode at `address` does not decode to any known instruction.
+    UnknownOpcode {
+        address: u16,
+        opcode: u16,
+    },
+    /// A subroutine call at `address` exceeded the depth of the call stack.
+    StackOverflow {
+        address: u16,
+    },
+    /// A return at `address` was executed with an empty call stack.
+    StackUnderflow {
+        address: u16,
+    },
+    /// An access of `length` bytes starting at `address` went past the end of memory.
+    MemoryOutOfRange {
+        address: usize,
+        length: usize,
+    },
+    /// The ROM does not fit into the memory available for programs.
+    RomTooLarge {
+        size: usize,
+        capacity: usize,
+    },
+    /// A save state was written in a `version` of the format this build cannot read.
+    IncompatibleState {
+        version: u16,
+        expected: u16,
+    },
+    /// A save state is not a save state, is truncated or holds impossible values.
+    CorruptState {
+        reason: &'static str,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulationError::UnknownOpcode { address, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, address)
+            }
+            EmulationError::StackOverflow { address } => {
+                write!(f, "stack overflow at {:03X}", address)
+            }
+            EmulationError::StackUnderflow { address } => {
+                write!(f, "stack underflow at {:03X}", address)
+            }
+            EmulationError::MemoryOutOfRange { address, length } => write!(
+                f,
+                "memory access of {} bytes at {:03X} is out of range",
+                length, address
+            ),
+            EmulationError::RomTooLarge { size, capacity } => write!(
+                f,
+                "rom of {} bytes exceeds the {} bytes available",
+                size, capacity
+            ),
+            EmulationError::IncompatibleState { version, expected } => write!(
+                f,
+                "save state version {} is not supported, expected version {}",
+                version, expected
+            ),
+            EmulationError::CorruptState { reason } => {
+                write!(f, "save state is corrupt: {}", reason)
+            }
+            EmulationError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for EmulationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmulationError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmulationError {
+    fn from(err: io::Error) -> Self {
+        EmulationError::Io(err)
+    }
+}
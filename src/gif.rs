@@ -0,0 +1,417 @@
+use super::graphic::{Graphic, HIRES_HEIGHT, HIRES_WIDTH, PLANE_MASK};
+use super::screenshot::Palette;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Bits of the smallest LZW codes, enough for the four pixel values.
+const MIN_CODE_SIZE: u8 = 2;
+/// Number of LZW codes, GIF codes being at most 12 bits long.
+const MAX_CODES: u16 = 4096;
+/// Largest number of bytes in a GIF data sub-block.
+const SUB_BLOCK: usize = 255;
+
+/// Records frames as a looping animated GIF, capturing one frame per call to `capture` and
+/// timing each of them as a 60th of a second. Frames equal to the one before are not stored
+/// again but lengthen the display time of the previous image, and only the area that changed is
+/// stored for the others. GIF delays are counted in hundredths of a second, so images are shown
+/// for 1 or 2 hundredths per frame in turn to keep the total time exact.
+///
+/// Images always have the size of a high resolution screen, scaled by `scale`, with low
+/// resolution pixels drawn twice as large, so that programs may switch modes while recording.
+pub struct GifRecorder {
+    writer: Box<dyn Write + Send>,
+    scale: usize,
+    /// Frames captured so far.
+    frames: u64,
+    /// Pixel values shown after the images written so far, empty before the first one.
+    written: Vec<u8>,
+    /// Pixel values of the last captured frame, which is written once its display time is known,
+    /// with the frame it was first captured in.
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl GifRecorder {
+    /// Start a recording on `writer`, writing the GIF header with the colours of `palette`.
+    pub fn new(
+        mut writer: Box<dyn Write + Send>,
+        scale: usize,
+        palette: &Palette,
+    ) -> io::Result<Self> {
+        let scale = scale.max(1);
+        let (width, height) = dimensions(scale)?;
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        // global colour table of 4 entries, background colour 0, square pixels
+        writer.write_all(&[0xF1, 0, 0])?;
+        for color in &palette.colors {
+            writer.write_all(color)?;
+        }
+        // loop forever
+        writer.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(GifRecorder {
+            writer,
+            scale,
+            frames: 0,
+            written: Vec::new(),
+            pending: None,
+        })
+    }
+
+    /// Start a recording into the file at `path`. Fails without creating the file if `scale` is
+    /// too large for a GIF.
+    pub fn create<P: AsRef<Path>>(path: P, scale: usize, palette: &Palette) -> io::Result<Self> {
+        dimensions(scale.max(1))?;
+        let file = File::create(path)?;
+        GifRecorder::new(Box::new(BufWriter::new(file)), scale, palette)
+    }
+
+    /// Number of frames captured so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Add the screen of `graphic` as the next frame.
+    pub fn capture(&mut self, graphic: &Graphic) -> io::Result<()> {
+        let pixels = self.pixels(graphic);
+        let frame = self.frames;
+        self.frames += 1;
+        if let Some((pending, start)) = self.pending.take() {
+            if pending == pixels {
+                self.pending = Some((pending, start));
+                return Ok(());
+            }
+            self.write_image(pending, start, frame)?;
+        }
+        self.pending = Some((pixels, frame));
+        Ok(())
+    }
+
+    /// Write the last image and the end of the GIF.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some((pending, start)) = self.pending.take() {
+            self.write_image(pending, start, self.frames)?;
+        }
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()
+    }
+
+    fn width(&self) -> usize {
+        HIRES_WIDTH * self.scale
+    }
+
+    /// Pixel values of the image showing `graphic`, row by row.
+    fn pixels(&self, graphic: &Graphic) -> Vec<u8> {
+        let factor = self.scale * HIRES_WIDTH / graphic.width();
+        let (width, height) = (self.width(), HIRES_HEIGHT * self.scale);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = (y / factor) * graphic.width();
+            pixels.extend((0..width).map(|x| graphic.gfx[row + x / factor] & PLANE_MASK));
+        }
+        pixels
+    }
+
+    /// Smallest rectangle holding every pixel of `pixels` which differs from the image shown,
+    /// as left, top, width and height.
+    fn changed_area(&self, pixels: &[u8]) -> (usize, usize, usize, usize) {
+        let width = self.width();
+        let height = pixels.len() / width;
+        if self.written.is_empty() {
+            return (0, 0, width, height);
+        }
+        let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+        for (index, (new, old)) in pixels.iter().zip(&self.written).enumerate() {
+            if new != old {
+                let (x, y) = (index % width, index / width);
+                left = left.min(x);
+                right = right.max(x + 1);
+                top = top.min(y);
+                bottom = bottom.max(y + 1);
+            }
+        }
+        if left >= right {
+            return (0, 0, 1, 1);
+        }
+        (left, top, right - left, bottom - top)
+    }
+
+    /// Write the part of `pixels` that changed, shown from frame `start` until frame `end`.
+    fn write_image(&mut self, pixels: Vec<u8>, start: u64, end: u64) -> io::Result<()> {
+        let delay = (centiseconds(end) - centiseconds(start)).min(u16::MAX as u64) as u16;
+        let (left, top, width, height) = self.changed_area(&pixels);
+
+        // graphic control extension: keep the image in place, no transparency
+        self.writer.write_all(&[0x21, 0xF9, 0x04, 0x04])?;
+        self.writer.write_all(&delay.to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+
+        self.writer.write_all(&[0x2C])?;
+        for value in &[left, top, width, height] {
+            let value = u16::try_from(*value).map_err(|_| too_large(self.scale))?;
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.writer.write_all(&[0])?;
+
+        let full_width = self.width();
+        let area: Vec<u8> = (top..top + height)
+            .flat_map(|y| &pixels[y * full_width + left..y * full_width + left + width])
+            .copied()
+            .collect();
+        self.writer.write_all(&[MIN_CODE_SIZE])?;
+        for block in lzw(&area).chunks(SUB_BLOCK) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])?;
+        self.written = pixels;
+        Ok(())
+    }
+}
+
+/// Width and height of the images at `scale`, which GIF stores in 16 bits.
+fn dimensions(scale: usize) -> io::Result<(u16, u16)> {
+    let scaled = |size: usize| {
+        size.checked_mul(scale)
+            .and_then(|size| u16::try_from(size).ok())
+            .ok_or_else(|| too_large(scale))
+    };
+    Ok((scaled(HIRES_WIDTH)?, scaled(HIRES_HEIGHT)?))
+}
+
+fn too_large(scale: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("scale {} is too large for a GIF", scale),
+    )
+}
+
+/// Time from the start of the recording until frame `frame`, rounded to hundredths of a second.
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + 30) / 60
+}
+
+/// Codes written least significant bit first, as GIF packs them.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Compress pixel values of 2 bits with the variable length LZW of GIF.
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = BitWriter::default();
+    let mut codes = HashMap::new();
+    let mut size = MIN_CODE_SIZE + 1;
+    let mut next = end + 1;
+    out.write(clear, size);
+
+    let mut pixels = pixels.iter();
+    let mut prefix = match pixels.next() {
+        Some(&pixel) => pixel as u16,
+        None => {
+            out.write(end, size);
+            return out.finish();
+        }
+    };
+    for &pixel in pixels {
+        if let Some(&code) = codes.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, size);
+        if next == MAX_CODES {
+            out.write(clear, size);
+            codes.clear();
+            size = MIN_CODE_SIZE + 1;
+            next = end + 1;
+        } else {
+            codes.insert((prefix, pixel), next);
+            next += 1;
+            // the decoder adds each code one step later, when it reads the next one
+            if next > 1 << size {
+                size += 1;
+            }
+        }
+        prefix = pixel as u16;
+    }
+    out.write(prefix, size);
+    // reading the last code makes the decoder add one more
+    if next == 1 << size && size < 12 {
+        size += 1;
+    }
+    out.write(end, size);
+    out.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer whose bytes stay readable after it was handed to a recorder.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reverse of `lzw`, as GIF decoders do it.
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let clear = 1 << MIN_CODE_SIZE;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = MIN_CODE_SIZE + 1;
+        let mut previous: Option<usize> = None;
+        let mut out = Vec::new();
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0, data.iter());
+        loop {
+            while bits < size {
+                buffer |= (*bytes.next().unwrap() as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << size) - 1)) as usize;
+            buffer >>= size;
+            bits -= size;
+            if code == clear {
+                table = (0..clear).map(|value| vec![value as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                size = MIN_CODE_SIZE + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (previous, table.get(code)) {
+                (_, Some(entry)) => entry.clone(),
+                (Some(previous), None) => {
+                    let mut entry = table[previous].clone();
+                    entry.push(table[previous][0]);
+                    entry
+                }
+                (None, None) => panic!("code {} before any other", code),
+            };
+            if let Some(previous) = previous {
+                if table.len() < MAX_CODES as usize {
+                    let mut new = table[previous].clone();
+                    new.push(entry[0]);
+                    table.push(new);
+                    if table.len() == 1 << size && size < 12 {
+                        size += 1;
+                    }
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    fn test_lzw() {
+        let mut random = 12345u32;
+        let noise: Vec<u8> = (0..20000)
+            .map(|_| {
+                random = random.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (random >> 16) as u8 & 3
+            })
+            .collect();
+        let runs: Vec<u8> = (0..20000).map(|i| (i / 700 % 4) as u8).collect();
+        for pixels in &[vec![], vec![1], vec![3; 7], noise, runs] {
+            for count in 0..pixels.len().min(40) {
+                assert_eq!(unlzw(&lzw(&pixels[..count])), &pixels[..count]);
+            }
+            assert_eq!(&unlzw(&lzw(pixels)), pixels);
+        }
+    }
+
+    #[test]
+    fn test_centiseconds() {
+        let delays: Vec<u64> = (0..6)
+            .map(|frame| centiseconds(frame + 1) - centiseconds(frame))
+            .collect();
+        assert_eq!(delays, vec![2, 1, 2, 2, 1, 2]);
+        assert_eq!(centiseconds(60), 100);
+    }
+
+    #[test]
+    fn test_capture() {
+        let output = Shared::default();
+        let mut recorder =
+            GifRecorder::new(Box::new(output.clone()), 1, &Palette::DEFAULT).unwrap();
+        let mut graphic = Graphic::new();
+        recorder.capture(&graphic).unwrap();
+        graphic.set_sprite(1, 2, &[0x80], false);
+        recorder.capture(&graphic).unwrap();
+        recorder.capture(&graphic).unwrap();
+        recorder.capture(&graphic).unwrap();
+        assert_eq!(recorder.frames(), 4);
+        recorder.finish().unwrap();
+
+        let gif = output.0.lock().unwrap().clone();
+        assert_eq!(&gif[..10], b"GIF89a\x80\x00\x40\x00");
+        assert_eq!(&gif[13..16], &[0, 0, 0]);
+        assert_eq!(gif.last(), Some(&0x3B));
+        let images: Vec<usize> = (0..gif.len())
+            .filter(|&index| gif[index..].starts_with(&[0x21, 0xF9, 0x04]))
+            .collect();
+        assert_eq!(images.len(), 2);
+        // the blank first frame, shown for one frame, then the 2x2 pixel at (2, 4) for three frames
+        let second = images[1];
+        assert_eq!(&gif[images[0] + 4..images[0] + 6], &[2, 0]);
+        assert_eq!(
+            &gif[images[0] + 9..images[0] + 18],
+            &[0, 0, 0, 0, 128, 0, 64, 0, 0]
+        );
+        assert_eq!(&gif[second + 4..second + 6], &[5, 0]);
+        assert_eq!(&gif[second + 9..second + 18], &[2, 0, 4, 0, 2, 0, 2, 0, 0]);
+        let data = &gif[second + 20..second + 20 + gif[second + 19] as usize];
+        assert_eq!(unlzw(data), vec![1; 4]);
+    }
+
+    #[test]
+    fn test_scale_too_large() {
+        let new = |scale| GifRecorder::new(Box::new(io::sink()), scale, &Palette::DEFAULT);
+        assert!(new(511).is_ok());
+        for &scale in &[512, usize::MAX] {
+            let err = new(scale).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        let path = std::env::temp_dir().join(format!("chip-8-gif-{}.gif", std::process::id()));
+        assert!(GifRecorder::create(&path, 512, &Palette::DEFAULT).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -1,47 +1,250 @@
-use console::Graphic as ConsoleGraphic;
-use std::sync::mpsc;
+use super::journal::PixelChanges;
 
+pub const LORES_WIDTH: usize = 64;
+
+pub const LORES_HEIGHT: usize = 32;
+
+pub const HIRES_WIDTH: usize = 128;
+
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Bit mask covering both XO-CHIP bitplanes.
+pub const PLANE_MASK: u8 = 0b11;
+
+/// Characters of the pixel values in `Graphic::to_text`.
+const PIXEL_CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+/// Framebuffer with one byte per pixel.
+/// The screen starts in the 64x32 low resolution mode and can be switched to the 128x64 high
+/// resolution mode of SUPER-CHIP at runtime. Switching clears the screen.
+/// Each pixel holds two XO-CHIP bitplanes, bit 0 for plane 1 and bit 1 for plane 2, so its value
+/// is one of four colours. Drawing, clearing and scrolling only affect the selected planes.
 pub struct Graphic {
-    pub gfx: [u8; 2048],
-    pub sender: mpsc::Sender<ConsoleGraphic>,
+    pub gfx: Vec<u8>,
+    width: usize,
+    height: usize,
+    planes: u8,
+    /// Pixels overwritten while a journal entry is open.
+    pub(crate) undo: Option<PixelChanges>,
 }
 
 impl Graphic {
-    pub fn new(sender: mpsc::Sender<ConsoleGraphic>) -> Self {
+    pub fn new() -> Self {
         Graphic {
-            gfx: [0; 2048],
-            sender,
+            gfx: vec![0; LORES_WIDTH * LORES_HEIGHT],
+            width: LORES_WIDTH,
+            height: LORES_HEIGHT,
+            planes: 0b01,
+            undo: None,
         }
     }
+
+    /// Clear the selected planes.
     pub fn clear(&mut self) {
-        self.gfx = [0; 2048];
-    }
-    pub fn set_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
-        assert!(x <= 64);
-        assert!(y <= 32);
-        let mut collision = false;
-        for (i, sprite_fragment) in sprite.iter().enumerate() {
-            for xi in 0..8 {
-                let pixel = (sprite_fragment & (0x80 >> xi)) as u8;
+        self.record_screen();
+        for pixel in self.gfx.iter_mut() {
+            *pixel &= !self.planes;
+        }
+    }
+
+    pub fn set_hires(&mut self, hires: bool) {
+        self.record_screen();
+        if hires {
+            self.width = HIRES_WIDTH;
+            self.height = HIRES_HEIGHT;
+        } else {
+            self.width = LORES_WIDTH;
+            self.height = LORES_HEIGHT;
+        }
+        self.gfx = vec![0; self.width * self.height];
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The screen as one line of text per row, with `.` for unlit pixels, `#` for pixels lit in
+    /// plane 1 only, `+` for plane 2 only and `@` for both planes.
+    pub fn to_text(&self) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in self.gfx.chunks(self.width) {
+            out.extend(
+                row.iter()
+                    .map(|&pixel| PIXEL_CHARS[(pixel & PLANE_MASK) as usize]),
+            );
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Select the planes affected by drawing, clearing and scrolling as a bit mask.
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & PLANE_MASK;
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Number of currently selected planes.
+    pub fn plane_count(&self) -> usize {
+        self.planes.count_ones() as usize
+    }
+
+    /// XOR an 8 pixel wide `sprite` onto the screen at (x, y).
+    /// The sprite holds one block of rows for each selected plane, in plane order.
+    /// Returns the number of sprite rows which erased at least one pixel.
+    /// The position itself always wraps around the screen. Pixels falling past the right or bottom
+    /// edge wrap around as well if `wrap` is set, and are clipped otherwise.
+    pub fn set_sprite(&mut self, x: usize, y: usize, sprite: &[u8], wrap: bool) -> usize {
+        let mut collided_rows = 0;
+        let length = sprite.len() / self.plane_count().max(1);
+        for (plane, data) in self.plane_bits().zip(sprite.chunks(length.max(1))) {
+            let rows = data.iter().map(|&row| (row as u16) << 8);
+            collided_rows |= self.xor_rows(plane, x, y, rows, wrap);
+        }
+        collided_rows.count_ones() as usize
+    }
+
+    /// XOR a 16x16 `sprite`, stored as two bytes per row, onto the screen at (x, y).
+    /// Behaves like `set_sprite` otherwise.
+    pub fn set_large_sprite(&mut self, x: usize, y: usize, sprite: &[u8], wrap: bool) -> usize {
+        let mut collided_rows = 0;
+        for (plane, data) in self.plane_bits().zip(sprite.chunks(32)) {
+            let rows = data
+                .chunks(2)
+                .map(|row| ((row[0] as u16) << 8) | row[1] as u16);
+            collided_rows |= self.xor_rows(plane, x, y, rows, wrap);
+        }
+        collided_rows.count_ones() as usize
+    }
+
+    fn plane_bits(&self) -> impl Iterator<Item = u8> {
+        let planes = self.planes;
+        [0b01, 0b10]
+            .iter()
+            .cloned()
+            .filter(move |bit| planes & bit != 0)
+    }
+
+    /// XOR `rows` onto `plane` and return a mask of the rows which erased a pixel.
+    fn xor_rows<I: Iterator<Item = u16>>(
+        &mut self,
+        plane: u8,
+        x: usize,
+        y: usize,
+        rows: I,
+        wrap: bool,
+    ) -> u32 {
+        let x = x % self.width;
+        let y = y % self.height;
+        let mut collided_rows = 0;
+        for (i, row) in rows.enumerate() {
+            for xi in 0..16 {
+                let pixel = row & (0x8000 >> xi);
                 let mut coord_x = x + xi;
                 let mut coord_y = y + i;
-                coord_x %= 64;
-                coord_y %= 32;
-                let index = coord_y * 64 + coord_x;
+                if !wrap && (coord_x >= self.width || coord_y >= self.height) {
+                    continue;
+                }
+                coord_x %= self.width;
+                coord_y %= self.height;
+                let index = coord_y * self.width + coord_x;
                 if pixel != 0 {
                     let screen_pixel = self.gfx[index];
-                    if screen_pixel == 1 {
-                        collision = true;
+                    if let Some(undo) = &mut self.undo {
+                        undo.pixels.push((index, screen_pixel));
+                    }
+                    if screen_pixel & plane != 0 {
+                        collided_rows |= 1 << i;
                     }
-                    self.gfx[index] ^= 1;
+                    self.gfx[index] ^= plane;
                 }
             }
         }
-        collision
+        collided_rows
     }
 
-    pub fn draw(&self) {
-        let gfx = self.gfx.to_vec();
-        self.sender.send(ConsoleGraphic::new(gfx, 64)).unwrap();
+    /// Scroll the screen up by `n` pixels.
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    /// Scroll the screen down by `n` pixels.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    /// Scroll the screen right by `n` pixels.
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    /// Scroll the screen left by `n` pixels.
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    /// Move the selected planes by (dx, dy), clearing what is scrolled in.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        self.record_screen();
+        let source = self.gfx.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let from_x = x as isize - dx;
+                let from_y = y as isize - dy;
+                let inside = from_x >= 0
+                    && from_y >= 0
+                    && (from_x as usize) < self.width
+                    && (from_y as usize) < self.height;
+                let moved = if inside {
+                    source[from_y as usize * self.width + from_x as usize] & self.planes
+                } else {
+                    0
+                };
+                let index = y * self.width + x;
+                self.gfx[index] = (self.gfx[index] & !self.planes) | moved;
+            }
+        }
+    }
+
+    /// Keep the whole framebuffer for the open journal entry, unless it already has it.
+    fn record_screen(&mut self) {
+        if let Some(undo) = &mut self.undo {
+            if undo.screen.is_none() {
+                undo.screen = Some(self.gfx.clone());
+            }
+        }
+    }
+}
+
+impl Default for Graphic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_text() {
+        let mut graphic = Graphic::new();
+        graphic.set_sprite(0, 0, &[0b1010_0000], false);
+        graphic.select_planes(0b11);
+        graphic.set_sprite(8, 1, &[0x80, 0xC0], false);
+        let text = graphic.to_text();
+        assert_eq!(text.lines().count(), LORES_HEIGHT);
+        assert!(text.starts_with(&format!("#.#{}\n", ".".repeat(61))));
+        assert_eq!(&text.lines().nth(1).unwrap()[..10], "........@+");
     }
 }
@@ -1,14 +1,22 @@
-use super::emulator::{Graphic, Memory, Register};
-use std::sync::mpsc;
+use super::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 
+mod opcode_0x00cn;
+mod opcode_0x00dn;
 mod opcode_0x00e0;
 mod opcode_0x00ee;
+mod opcode_0x00fb;
+mod opcode_0x00fc;
+mod opcode_0x00fd;
+mod opcode_0x00fe;
+mod opcode_0x00ff;
 mod opcode_0x0nnn;
 mod opcode_0x1nnn;
 mod opcode_0x2nnn;
 mod opcode_0x3xkk;
 mod opcode_0x4xkk;
 mod opcode_0x5xy0;
+mod opcode_0x5xy2;
+mod opcode_0x5xy3;
 mod opcode_0x6xkk;
 mod opcode_0x7xkk;
 mod opcode_0x8xy0;
@@ -27,15 +35,22 @@ mod opcode_0xcxkk;
 mod opcode_0xdxyn;
 mod opcode_0xex9e;
 mod opcode_0xexa1;
+mod opcode_0xf000;
+mod opcode_0xf002;
+mod opcode_0xfn01;
 mod opcode_0xfx07;
 mod opcode_0xfx0a;
 mod opcode_0xfx15;
 mod opcode_0xfx18;
 mod opcode_0xfx1e;
 mod opcode_0xfx29;
+mod opcode_0xfx30;
 mod opcode_0xfx33;
+mod opcode_0xfx3a;
 mod opcode_0xfx55;
 mod opcode_0xfx65;
+mod opcode_0xfx75;
+mod opcode_0xfx85;
 
 pub trait Instruction {
     fn execute(
@@ -43,86 +58,149 @@ pub trait Instruction {
         memory: &mut Memory,
         register: &mut Register,
         graphic: &mut Graphic,
-        keyboard_bus: &mpsc::Receiver<u8>,
-    );
+        keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError>;
+
+    /// Name of the instruction type, e.g. `Opcode0x6xkk`.
+    fn name(&self) -> &'static str {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path)
+    }
 }
-impl From<[u8; 2]> for Box<dyn Instruction> {
-    fn from(opcode: [u8; 2]) -> Box<dyn Instruction> {
-        let operator = ((opcode[0] & 0xF0) >> 4) as u8;
-        let instruction = ((opcode[0] as u16) << 8) | opcode[1] as u16;
-        match operator {
-            0x0 => match instruction {
-                0x00EE => Box::new(opcode_0x00ee::Opcode0x00ee::new()),
-                0x00E0 => Box::new(opcode_0x00e0::Opcode0x00e0::new()),
-                _ => Box::new(opcode_0x0nnn::Opcode0x0nnn::new()),
-            },
-            0x1 => Box::new(opcode_0x1nnn::Opcode0x1nnn::new(instruction)),
-            0x2 => Box::new(opcode_0x2nnn::Opcode0x2nnn::new(instruction)),
-            0x3 => Box::new(opcode_0x3xkk::Opcode0x3xkk::new(instruction)),
-            0x4 => Box::new(opcode_0x4xkk::Opcode0x4xkk::new(instruction)),
-            0x5 => Box::new(opcode_0x5xy0::Opcode0x5xy0::new(instruction)),
-            0x6 => Box::new(opcode_0x6xkk::Opcode0x6xkk::new(instruction)),
-            0x7 => Box::new(opcode_0x7xkk::Opcode0x7xkk::new(instruction)),
-            0x8 => {
-                let suffix = (opcode[1] & 0x0F) as u8;
-                match suffix {
-                    0x0 => Box::new(opcode_0x8xy0::Opcode0x8xy0::new(instruction)),
-                    0x1 => Box::new(opcode_0x8xy1::Opcode0x8xy1::new(instruction)),
-                    0x2 => Box::new(opcode_0x8xy2::Opcode0x8xy2::new(instruction)),
-                    0x3 => Box::new(opcode_0x8xy3::Opcode0x8xy3::new(instruction)),
-                    0x4 => Box::new(opcode_0x8xy4::Opcode0x8xy4::new(instruction)),
-                    0x5 => Box::new(opcode_0x8xy5::Opcode0x8xy5::new(instruction)),
-                    0x6 => Box::new(opcode_0x8xy6::Opcode0x8xy6::new(instruction)),
-                    0x7 => Box::new(opcode_0x8xy7::Opcode0x8xy7::new(instruction)),
-                    0xe => Box::new(opcode_0x8xye::Opcode0x8xye::new(instruction)),
-                    _ => panic!(
-                        "unsupported operator {prefix:x?}{suffix:x?}",
-                        prefix = opcode[0],
-                        suffix = opcode[1],
-                    ),
-                }
+
+/// Advance PC past the current instruction and the one following it.
+/// The following instruction is four bytes long if it is the XO-CHIP F000 NNNN long load.
+fn skip_next_instruction(memory: &Memory, register: &mut Register) {
+    let next = register.pc as usize + 2;
+    match memory.bytes(next, 2) {
+        Ok([0xF0, 0x00]) => register.pc = register.pc.wrapping_add(6),
+        _ => register.pc = register.pc.wrapping_add(4),
+    }
+}
+
+/// Registers from Vx to Vy in that order, which is descending if x > y.
+fn register_range(vx: usize, vy: usize) -> Vec<usize> {
+    if vx <= vy {
+        (vx..=vy).collect()
+    } else {
+        (vy..=vx).rev().collect()
+    }
+}
+
+/// Decode the opcode fetched from `address` into its instruction.
+pub fn decode(address: u16, opcode: [u8; 2]) -> Result<Box<dyn Instruction>, EmulationError> {
+    let operator = ((opcode[0] & 0xF0) >> 4) as u8;
+    let instruction = ((opcode[0] as u16) << 8) | opcode[1] as u16;
+    let unknown = EmulationError::UnknownOpcode {
+        address,
+        opcode: instruction,
+    };
+    let decoded: Box<dyn Instruction> = match operator {
+        0x0 => match instruction {
+            0x00C0..=0x00CF => Box::new(opcode_0x00cn::Opcode0x00cn::new(instruction)),
+            0x00D0..=0x00DF => Box::new(opcode_0x00dn::Opcode0x00dn::new(instruction)),
+            0x00EE => Box::new(opcode_0x00ee::Opcode0x00ee::new()),
+            0x00E0 => Box::new(opcode_0x00e0::Opcode0x00e0::new()),
+            0x00FB => Box::new(opcode_0x00fb::Opcode0x00fb::new()),
+            0x00FC => Box::new(opcode_0x00fc::Opcode0x00fc::new()),
+            0x00FD => Box::new(opcode_0x00fd::Opcode0x00fd::new()),
+            0x00FE => Box::new(opcode_0x00fe::Opcode0x00fe::new()),
+            0x00FF => Box::new(opcode_0x00ff::Opcode0x00ff::new()),
+            _ => Box::new(opcode_0x0nnn::Opcode0x0nnn::new()),
+        },
+        0x1 => Box::new(opcode_0x1nnn::Opcode0x1nnn::new(instruction)),
+        0x2 => Box::new(opcode_0x2nnn::Opcode0x2nnn::new(instruction)),
+        0x3 => Box::new(opcode_0x3xkk::Opcode0x3xkk::new(instruction)),
+        0x4 => Box::new(opcode_0x4xkk::Opcode0x4xkk::new(instruction)),
+        0x5 => {
+            let suffix = (opcode[1] & 0x0F) as u8;
+            match suffix {
+                0x0 => Box::new(opcode_0x5xy0::Opcode0x5xy0::new(instruction)),
+                0x2 => Box::new(opcode_0x5xy2::Opcode0x5xy2::new(instruction)),
+                0x3 => Box::new(opcode_0x5xy3::Opcode0x5xy3::new(instruction)),
+                _ => return Err(unknown),
             }
-            0x9 => Box::new(opcode_0x9xy0::Opcode0x9xy0::new(instruction)),
-            0xa => Box::new(opcode_0xannn::Opcode0xannn::new(instruction)),
-            0xb => Box::new(opcode_0xbnnn::Opcode0xbnnn::new(instruction)),
-            0xc => Box::new(opcode_0xcxkk::Opcode0xcxkk::new(instruction)),
-            0xd => Box::new(opcode_0xdxyn::Opcode0xdxyn::new(instruction)),
-            0xe => {
-                let suffix = opcode[1];
-                match suffix {
-                    0x9E => Box::new(opcode_0xex9e::Opcode0xex9e::new(instruction)),
-                    0xA1 => Box::new(opcode_0xexa1::Opcode0xexa1::new(instruction)),
-                    _ => panic!(
-                        "unsupported operator {prefix:x?}{suffix:x?}",
-                        prefix = opcode[0],
-                        suffix = opcode[1],
-                    ),
-                }
+        }
+        0x6 => Box::new(opcode_0x6xkk::Opcode0x6xkk::new(instruction)),
+        0x7 => Box::new(opcode_0x7xkk::Opcode0x7xkk::new(instruction)),
+        0x8 => {
+            let suffix = (opcode[1] & 0x0F) as u8;
+            match suffix {
+                0x0 => Box::new(opcode_0x8xy0::Opcode0x8xy0::new(instruction)),
+                0x1 => Box::new(opcode_0x8xy1::Opcode0x8xy1::new(instruction)),
+                0x2 => Box::new(opcode_0x8xy2::Opcode0x8xy2::new(instruction)),
+                0x3 => Box::new(opcode_0x8xy3::Opcode0x8xy3::new(instruction)),
+                0x4 => Box::new(opcode_0x8xy4::Opcode0x8xy4::new(instruction)),
+                0x5 => Box::new(opcode_0x8xy5::Opcode0x8xy5::new(instruction)),
+                0x6 => Box::new(opcode_0x8xy6::Opcode0x8xy6::new(instruction)),
+                0x7 => Box::new(opcode_0x8xy7::Opcode0x8xy7::new(instruction)),
+                0xe => Box::new(opcode_0x8xye::Opcode0x8xye::new(instruction)),
+                _ => return Err(unknown),
+            }
+        }
+        0x9 => Box::new(opcode_0x9xy0::Opcode0x9xy0::new(instruction)),
+        0xa => Box::new(opcode_0xannn::Opcode0xannn::new(instruction)),
+        0xb => Box::new(opcode_0xbnnn::Opcode0xbnnn::new(instruction)),
+        0xc => Box::new(opcode_0xcxkk::Opcode0xcxkk::new(instruction)),
+        0xd => Box::new(opcode_0xdxyn::Opcode0xdxyn::new(instruction)),
+        0xe => {
+            let suffix = opcode[1];
+            match suffix {
+                0x9E => Box::new(opcode_0xex9e::Opcode0xex9e::new(instruction)),
+                0xA1 => Box::new(opcode_0xexa1::Opcode0xexa1::new(instruction)),
+                _ => return Err(unknown),
             }
-            0xf => {
-                let suffix = opcode[1];
-                match suffix {
-                    0x07 => Box::new(opcode_0xfx07::Opcode0xfx07::new(instruction)),
-                    0x0a => Box::new(opcode_0xfx0a::Opcode0xfx0a::new(instruction)),
-                    0x15 => Box::new(opcode_0xfx15::Opcode0xfx15::new(instruction)),
-                    0x18 => Box::new(opcode_0xfx18::Opcode0xfx18::new(instruction)),
-                    0x1e => Box::new(opcode_0xfx1e::Opcode0xfx1e::new(instruction)),
-                    0x29 => Box::new(opcode_0xfx29::Opcode0xfx29::new(instruction)),
-                    0x33 => Box::new(opcode_0xfx33::Opcode0xfx33::new(instruction)),
-                    0x55 => Box::new(opcode_0xfx55::Opcode0xfx55::new(instruction)),
-                    0x65 => Box::new(opcode_0xfx65::Opcode0xfx65::new(instruction)),
-                    _ => panic!(
-                        "unsupported operator {prefix:x?}{suffix:x?}",
-                        prefix = opcode[0],
-                        suffix = opcode[1],
-                    ),
+        }
+        0xf => {
+            let suffix = opcode[1];
+            match suffix {
+                0x00 if instruction == 0xF000 => Box::new(opcode_0xf000::Opcode0xf000::new()),
+                0x01 => Box::new(opcode_0xfn01::Opcode0xfn01::new(instruction)),
+                0x02 if instruction == 0xF002 => Box::new(opcode_0xf002::Opcode0xf002::new()),
+                0x07 => Box::new(opcode_0xfx07::Opcode0xfx07::new(instruction)),
+                0x0a => Box::new(opcode_0xfx0a::Opcode0xfx0a::new(instruction)),
+                0x15 => Box::new(opcode_0xfx15::Opcode0xfx15::new(instruction)),
+                0x18 => Box::new(opcode_0xfx18::Opcode0xfx18::new(instruction)),
+                0x1e => Box::new(opcode_0xfx1e::Opcode0xfx1e::new(instruction)),
+                0x29 => Box::new(opcode_0xfx29::Opcode0xfx29::new(instruction)),
+                0x30 => Box::new(opcode_0xfx30::Opcode0xfx30::new(instruction)),
+                0x33 => Box::new(opcode_0xfx33::Opcode0xfx33::new(instruction)),
+                0x3a => Box::new(opcode_0xfx3a::Opcode0xfx3a::new(instruction)),
+                0x55 => Box::new(opcode_0xfx55::Opcode0xfx55::new(instruction)),
+                0x65 => Box::new(opcode_0xfx65::Opcode0xfx65::new(instruction)),
+                0x75 => Box::new(opcode_0xfx75::Opcode0xfx75::new(instruction)),
+                0x85 => Box::new(opcode_0xfx85::Opcode0xfx85::new(instruction)),
+                _ => return Err(unknown),
+            }
+        }
+        _ => return Err(unknown),
+    };
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_name() {
+        assert_eq!(decode(0x200, [0x6a, 0x2b]).unwrap().name(), "Opcode0x6xkk");
+        assert_eq!(decode(0x200, [0xf0, 0x00]).unwrap().name(), "Opcode0xf000");
+    }
+
+    #[test]
+    fn test_decode_unknown_opcode() {
+        for opcode in [[0x81, 0x28], [0xe0, 0x00], [0xf0, 0xff]].iter() {
+            let result = decode(0x234, *opcode);
+            let expected = ((opcode[0] as u16) << 8) | opcode[1] as u16;
+            match result {
+                Err(EmulationError::UnknownOpcode { address, opcode }) => {
+                    assert_eq!(address, 0x234);
+                    assert_eq!(opcode, expected);
                 }
+                _ => panic!("{:04X} should not decode", expected),
             }
-            _ => panic!(
-                "unsupported operator {prefix:x?}{suffix:x?}",
-                prefix = opcode[0],
-                suffix = opcode[1],
-            ),
         }
     }
 }
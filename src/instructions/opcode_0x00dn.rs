@@ -0,0 +1,61 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Scroll display n lines up.
+/// The whole screen moves up by n pixels, and the lines scrolled in at the bottom are cleared.
+pub struct Opcode0x00dn {
+    nibble: usize,
+}
+
+impl Opcode0x00dn {
+    pub fn new(instruction: u16) -> Self {
+        let nibble = (instruction & 0x000F) as usize;
+        Opcode0x00dn { nibble }
+    }
+}
+
+impl Instruction for Opcode0x00dn {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        graphic.scroll_up(self.nibble);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x00dn::new(0x00d2);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        graphic.gfx[2 * 64 + 3] = 1;
+        graphic.gfx[31 * 64] = 1;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(graphic.gfx[3], 1);
+        assert_eq!(graphic.gfx[2 * 64 + 3], 0);
+        assert_eq!(graphic.gfx[29 * 64], 1);
+        assert_eq!(graphic.gfx[31 * 64], 0);
+        assert_eq!(register.pc, 0x202);
+    }
+}
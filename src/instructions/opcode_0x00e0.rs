@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Clear the display.
 pub struct Opcode0x00e0;
@@ -17,10 +16,12 @@ impl Instruction for Opcode0x00e0 {
         _memory: &mut Memory,
         register: &mut Register,
         graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
         graphic.clear();
-        register.pc += 2;
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -33,11 +34,19 @@ mod test {
         let opcode = Opcode0x00e0::new();
         let mut memory = Memory::new();
         let mut register = Register::new();
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        graphic.gfx = [1; 2048];
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        graphic.gfx = vec![1; 2048];
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         for gfx in graphic.gfx.iter() {
             assert_eq!(*gfx, 0);
         }
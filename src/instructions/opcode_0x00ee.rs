@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Return from a subroutine.
 /// The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack.
@@ -18,9 +17,18 @@ impl Instruction for Opcode0x00ee {
         memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.pc = memory.stack.pop().unwrap();
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        match memory.stack.pop() {
+            Some(address) => register.pc = address,
+            None => {
+                return Err(EmulationError::StackUnderflow {
+                    address: register.pc,
+                })
+            }
+        }
+        Ok(())
     }
 }
 
@@ -37,13 +45,47 @@ mod test {
         memory.stack.push(3);
         let mut register = Register::new();
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(memory.stack, vec![1, 2]);
         assert_eq!(register.pc, 3);
     }
+
+    #[test]
+    fn test_execute_stack_underflow() {
+        let opcode = Opcode0x00ee::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+
+        let result = opcode.execute(
+            &mut memory,
+            &mut register,
+            &mut graphic,
+            &mut keypad,
+            &quirks,
+        );
+        assert!(matches!(
+            result,
+            Err(EmulationError::StackUnderflow { address: 0x200 })
+        ));
+    }
 }
@@ -0,0 +1,57 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Scroll display 4 pixels left.
+/// The columns scrolled in on the right are cleared.
+pub struct Opcode0x00fc;
+
+impl Opcode0x00fc {
+    pub fn new() -> Self {
+        Opcode0x00fc
+    }
+}
+
+impl Instruction for Opcode0x00fc {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        graphic.scroll_left(4);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x00fc::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        graphic.gfx[0] = 1;
+        graphic.gfx[4] = 1;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(graphic.gfx[0], 1);
+        assert_eq!(graphic.gfx[4], 0);
+        assert_eq!(graphic.gfx[63], 0);
+        assert_eq!(register.pc, 0x202);
+    }
+}
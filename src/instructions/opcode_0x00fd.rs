@@ -0,0 +1,52 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Exit the interpreter.
+/// The machine halts and does not execute any further instruction.
+pub struct Opcode0x00fd;
+
+impl Opcode0x00fd {
+    pub fn new() -> Self {
+        Opcode0x00fd
+    }
+}
+
+impl Instruction for Opcode0x00fd {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.halted = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x00fd::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert!(register.halted);
+        assert_eq!(register.pc, 0x200);
+    }
+}
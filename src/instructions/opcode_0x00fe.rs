@@ -0,0 +1,56 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Disable high resolution mode.
+/// The display switches to 64x32 pixels and is cleared.
+pub struct Opcode0x00fe;
+
+impl Opcode0x00fe {
+    pub fn new() -> Self {
+        Opcode0x00fe
+    }
+}
+
+impl Instruction for Opcode0x00fe {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        graphic.set_hires(false);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x00fe::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        graphic.set_hires(true);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert!(!graphic.is_hires());
+        assert_eq!(graphic.width(), 64);
+        assert_eq!(graphic.gfx.len(), 64 * 32);
+        assert_eq!(register.pc, 0x202);
+    }
+}
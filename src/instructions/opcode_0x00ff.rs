@@ -0,0 +1,55 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Enable high resolution mode.
+/// The display switches to 128x64 pixels and is cleared.
+pub struct Opcode0x00ff;
+
+impl Opcode0x00ff {
+    pub fn new() -> Self {
+        Opcode0x00ff
+    }
+}
+
+impl Instruction for Opcode0x00ff {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        graphic.set_hires(true);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x00ff::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert!(graphic.is_hires());
+        assert_eq!(graphic.width(), 128);
+        assert_eq!(graphic.gfx.len(), 128 * 64);
+        assert_eq!(register.pc, 0x202);
+    }
+}
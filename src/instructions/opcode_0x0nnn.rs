@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Jump to a machine code routine at nnn.
 /// This instruction is only used on the old computers on which Chip-8 was originally implemented.
@@ -19,9 +18,11 @@ impl Instruction for Opcode0x0nnn {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -35,11 +36,20 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
     }
 }
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Jump to location nnn.
 pub struct Opcode0x1nnn {
@@ -20,9 +19,11 @@ impl Instruction for Opcode0x1nnn {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
         register.pc = self.address;
+        Ok(())
     }
 }
 
@@ -37,10 +38,18 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.pc = 1;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x123);
     }
 }
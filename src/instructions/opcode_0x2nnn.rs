@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register, STACK_DEPTH};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Call subroutine at nnn.
 /// The interpreter increments the stack pointer, then puts the current PC on the top of the stack.
@@ -22,10 +21,17 @@ impl Instruction for Opcode0x2nnn {
         memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        memory.stack.push(register.pc + 2);
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        if memory.stack.len() >= STACK_DEPTH {
+            return Err(EmulationError::StackOverflow {
+                address: register.pc,
+            });
+        }
+        memory.stack.push(register.pc.wrapping_add(2));
         register.pc = self.address;
+        Ok(())
     }
 }
 
@@ -40,11 +46,43 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.pc = 1;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x123);
         assert_eq!(memory.stack[0], 3);
     }
+
+    #[test]
+    fn test_execute_stack_overflow() {
+        let instruction: u16 = 0x2123;
+        let opcode = Opcode0x2nnn::new(instruction);
+        let mut memory = Memory::new();
+        memory.stack = vec![0x200; STACK_DEPTH];
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        let result = opcode.execute(
+            &mut memory,
+            &mut register,
+            &mut graphic,
+            &mut keypad,
+            &quirks,
+        );
+        assert!(matches!(
+            result,
+            Err(EmulationError::StackOverflow { address: 0x200 })
+        ));
+        assert_eq!(memory.stack.len(), STACK_DEPTH);
+    }
 }
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
-use crate::instructions::Instruction;
-use std::sync::mpsc;
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::{skip_next_instruction, Instruction};
 
 /// Skip next instruction if Vx != kk.
 /// The interpreter compares register Vx to kk, and if they are not equal, increments the pragram
@@ -21,16 +20,18 @@ impl Opcode0x4xkk {
 impl Instruction for Opcode0x4xkk {
     fn execute(
         &self,
-        _memory: &mut Memory,
+        memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
         if register.v[self.vx] != self.byte {
-            register.pc += 4;
+            skip_next_instruction(memory, register);
         } else {
-            register.pc += 2;
+            register.pc = register.pc.wrapping_add(2);
         }
+        Ok(())
     }
 }
 
@@ -45,10 +46,18 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.v[1] = 0x23;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
     }
 
@@ -59,10 +68,18 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.v[1] = 0x24;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x204);
     }
 }
@@ -0,0 +1,90 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::{register_range, Instruction};
+
+/// Store registers Vx through Vy in memory starting at location I.
+/// The registers are stored in descending order if x > y. I is not changed.
+pub struct Opcode0x5xy2 {
+    vx: usize,
+    vy: usize,
+}
+
+impl Opcode0x5xy2 {
+    pub fn new(instruction: u16) -> Self {
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
+        let vy = ((instruction & 0x00F0) >> 4) as usize;
+        Opcode0x5xy2 { vx, vy }
+    }
+}
+
+impl Instruction for Opcode0x5xy2 {
+    fn execute(
+        &self,
+        memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let registers = register_range(self.vx, self.vy);
+        let values: Vec<u8> = registers.iter().map(|&index| register.v[index]).collect();
+        memory.write_bytes(register.i as usize, &values)?;
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x5xy2::new(0x5242);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.i = 0x300;
+        register.v[0x2] = 0x1;
+        register.v[0x3] = 0x2;
+        register.v[0x4] = 0x3;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(memory.all[0x300..0x304], [0x1, 0x2, 0x3, 0x0]);
+        assert_eq!(register.i, 0x300);
+        assert_eq!(register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_execute_descending() {
+        let opcode = Opcode0x5xy2::new(0x5422);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.i = 0x300;
+        register.v[0x2] = 0x1;
+        register.v[0x3] = 0x2;
+        register.v[0x4] = 0x3;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(memory.all[0x300..0x303], [0x3, 0x2, 0x1]);
+        assert_eq!(register.pc, 0x202);
+    }
+}
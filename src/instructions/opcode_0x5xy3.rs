@@ -0,0 +1,92 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::{register_range, Instruction};
+
+/// Read registers Vx through Vy from memory starting at location I.
+/// The registers are read in descending order if x > y. I is not changed.
+pub struct Opcode0x5xy3 {
+    vx: usize,
+    vy: usize,
+}
+
+impl Opcode0x5xy3 {
+    pub fn new(instruction: u16) -> Self {
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
+        let vy = ((instruction & 0x00F0) >> 4) as usize;
+        Opcode0x5xy3 { vx, vy }
+    }
+}
+
+impl Instruction for Opcode0x5xy3 {
+    fn execute(
+        &self,
+        memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let registers = register_range(self.vx, self.vy);
+        let source = memory.read_bytes(register.i as usize, registers.len())?;
+        for (&byte, &index) in source.iter().zip(registers.iter()) {
+            register.set_v(index, byte);
+        }
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0x5xy3::new(0x5243);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.i = 0x300;
+        memory.all[0x300] = 0x1;
+        memory.all[0x301] = 0x2;
+        memory.all[0x302] = 0x3;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.v[0x2..0x5], [0x1, 0x2, 0x3]);
+        assert_eq!(register.i, 0x300);
+        assert_eq!(register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_execute_descending() {
+        let opcode = Opcode0x5xy3::new(0x5423);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.i = 0x300;
+        memory.all[0x300] = 0x1;
+        memory.all[0x301] = 0x2;
+        memory.all[0x302] = 0x3;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.v[0x2..0x5], [0x3, 0x2, 0x1]);
+        assert_eq!(register.pc, 0x202);
+    }
+}
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set Vx = kk.
 /// The interpreter puts the value kk into register Vx.
@@ -23,10 +22,12 @@ impl Instruction for Opcode0x6xkk {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.v[self.vx] = self.byte;
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.set_v(self.vx, self.byte);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -40,10 +41,18 @@ mod test {
         let opcode = Opcode0x6xkk::new(instruction);
         let mut memory = Memory::new();
         let mut register = Register::new();
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
         assert_eq!(register.v[1], 0x23);
     }
@@ -1,11 +1,11 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set Vx = Vx AND Vy.
 /// Performs a bitwise ADN on the values of Vx and Vy, then stores the result in Vx.
 /// A bitwise AND compares the corresponding bits from two values, and if both bits are 1, then the
 /// same bit in the result is also 1. Otherwise, it is 0.
+/// With the `reset_vf` quirk, VF is set to 0 afterwards.
 pub struct Opcode0x8xy2 {
     vx: usize,
     vy: usize,
@@ -25,10 +25,15 @@ impl Instruction for Opcode0x8xy2 {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.v[self.vx] &= register.v[self.vy];
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.set_v(self.vx, register.v[self.vx] & register.v[self.vy]);
+        if quirks.reset_vf {
+            register.set_v(0xF, 0);
+        }
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -44,11 +49,42 @@ mod test {
         let mut register = Register::new();
         register.v[1] = 0b0101;
         register.v[2] = 0b1001;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
         assert_eq!(register.v[1], 0b0001);
     }
+
+    #[test]
+    fn test_execute_without_reset_vf() {
+        let instruction: u16 = 0x8122;
+        let opcode = Opcode0x8xy2::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        register.v[15] = 0x7;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::CHIP_48;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.pc, 0x202);
+        assert_eq!(register.v[15], 0x7);
+    }
 }
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set Vx = Vy -Vx, set VF = NOT borrow.
 /// If Vy > Vx, set VF is set to 1, otherwise 0.
@@ -24,16 +23,18 @@ impl Instruction for Opcode0x8xy7 {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
         let (result, borrowing) = register.v[self.vy].overflowing_sub(register.v[self.vx]);
-        register.v[self.vx] = result;
+        register.set_v(self.vx, result);
         if borrowing {
-            register.v[0xF] = 0;
+            register.set_v(0xF, 0);
         } else {
-            register.v[0xF] = 1;
+            register.set_v(0xF, 1);
         }
-        register.pc += 2;
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -49,10 +50,18 @@ mod test {
         let mut register = Register::new();
         register.v[1] = 5;
         register.v[2] = 10;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
         assert_eq!(register.v[1], 5);
         assert_eq!(register.v[15], 1);
@@ -66,10 +75,18 @@ mod test {
         let mut register = Register::new();
         register.v[1] = 15;
         register.v[2] = 10;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
         assert_eq!(register.v[1], 251);
         assert_eq!(register.v[15], 0);
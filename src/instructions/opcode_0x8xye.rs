@@ -1,18 +1,21 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set Vx = Vx SHR 1.
 /// If the most-significant bit of Vx is 1, the VF is set to 1, otherwise 0.
 /// Then Vx is multipled by 2.
+/// The COSMAC VIP shifts Vy and stores the result in Vx instead, unless the `shift_in_place`
+/// quirk is set.
 pub struct Opcode0x8xye {
     vx: usize,
+    vy: usize,
 }
 
 impl Opcode0x8xye {
     pub fn new(instruction: u16) -> Self {
         let vx = ((instruction & 0x0F00) >> 8) as usize;
-        Opcode0x8xye { vx }
+        let vy = ((instruction & 0x00F0) >> 4) as usize;
+        Opcode0x8xye { vx, vy }
     }
 }
 
@@ -22,16 +25,18 @@ impl Instruction for Opcode0x8xye {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        let most_significant_bit = ((register.v[self.vx] & 0x80) >> 7) as u8;
-        if most_significant_bit == 1 {
-            register.v[0xF] = 1;
+        _keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let source = if quirks.shift_in_place {
+            register.v[self.vx]
         } else {
-            register.v[0xF] = 0;
-        }
-        register.v[self.vx] <<= 1;
-        register.pc += 2;
+            register.v[self.vy]
+        };
+        register.set_v(self.vx, source << 1);
+        register.set_v(0xF, (source & 0x80) >> 7);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -46,10 +51,18 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.v[1] = 200;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::CHIP_48;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
         assert_eq!(register.v[1], 144);
         assert_eq!(register.v[15], 1);
@@ -62,12 +75,46 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.v[1] = 25;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::CHIP_48;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
         assert_eq!(register.v[1], 50);
         assert_eq!(register.v[15], 0);
     }
+
+    #[test]
+    fn test_execute_shift_vy() {
+        let instruction: u16 = 0x812e;
+        let opcode = Opcode0x8xye::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        register.v[1] = 1;
+        register.v[2] = 0x81;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::COSMAC_VIP;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.pc, 0x202);
+        assert_eq!(register.v[1], 2);
+        assert_eq!(register.v[2], 0x81);
+        assert_eq!(register.v[15], 1);
+    }
 }
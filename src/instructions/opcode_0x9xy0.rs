@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
-use crate::instructions::Instruction;
-use std::sync::mpsc;
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::{skip_next_instruction, Instruction};
 
 /// Skip next instruction if Vx != Vy.
 /// The values of Vx and Vy are compared, and if they are not equal, the program counter is
@@ -21,16 +20,18 @@ impl Opcode0x9xy0 {
 impl Instruction for Opcode0x9xy0 {
     fn execute(
         &self,
-        _memory: &mut Memory,
+        memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
         if register.v[self.vx] != register.v[self.vy] {
-            register.pc += 4;
+            skip_next_instruction(memory, register);
         } else {
-            register.pc += 2;
+            register.pc = register.pc.wrapping_add(2);
         }
+        Ok(())
     }
 }
 
@@ -46,10 +47,18 @@ mod test {
         let mut register = Register::new();
         register.v[1] = 0x23;
         register.v[10] = 0x23;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
     }
 
@@ -61,10 +70,18 @@ mod test {
         let mut register = Register::new();
         register.v[1] = 0x23;
         register.v[10] = 0x24;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x204);
     }
 }
@@ -1,9 +1,9 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Jump to location nnn + V0.
 /// The program counter is set to nnn plus the value of V0.
+/// With the `jump_with_vx` quirk, the instruction is read as Bxnn and jumps to xnn plus Vx.
 pub struct Opcode0xbnnn {
     address: u16,
 }
@@ -21,10 +21,17 @@ impl Instruction for Opcode0xbnnn {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        let address = self.address + register.v[0] as u16;
+        _keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let offset = if quirks.jump_with_vx {
+            register.v[((self.address & 0x0F00) >> 8) as usize]
+        } else {
+            register.v[0]
+        };
+        let address = self.address + offset as u16;
         register.pc = address;
+        Ok(())
     }
 }
 
@@ -39,10 +46,41 @@ mod test {
         let mut memory = Memory::new();
         let mut register = Register::new();
         register.v[0] = 0x1;
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x12f);
     }
+
+    #[test]
+    fn test_execute_jump_with_vx() {
+        let instruction: u16 = 0xb12e;
+        let opcode = Opcode0xbnnn::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        register.v[0] = 0x1;
+        register.v[1] = 0x3;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::CHIP_48;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.pc, 0x131);
+    }
 }
@@ -1,27 +1,21 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use rand::prelude::*;
-use std::sync::mpsc;
 
 /// Set Vx = random byte AND kk.
 /// The interpreter generates a random number from 0 to 255, which is the ANDed with the value kk.
 /// The results are stored in Vx.
+/// The random byte comes from the generator kept in the registers, so runs can be reproduced and
+/// saved.
 pub struct Opcode0xcxkk {
     vx: usize,
     byte: u8,
-    random_byte: u8,
 }
 
 impl Opcode0xcxkk {
     pub fn new(instruction: u16) -> Self {
-        let vx = (instruction & 0x0F00 >> 8) as usize;
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
         let byte = (instruction & 0x00FF) as u8;
-        let random_byte = rand::thread_rng().gen::<u8>();
-        Opcode0xcxkk {
-            vx,
-            byte,
-            random_byte,
-        }
+        Opcode0xcxkk { vx, byte }
     }
 }
 
@@ -31,16 +25,27 @@ impl Instruction for Opcode0xcxkk {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.v[self.vx] = self.random_byte & self.byte;
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let random_byte = register.random.next_byte();
+        register.set_v(self.vx, random_byte & self.byte);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::emulator::Random;
+
+    #[test]
+    fn test_new() {
+        let opcode = Opcode0xcxkk::new(0xc32e);
+        assert_eq!(opcode.vx, 3);
+        assert_eq!(opcode.byte, 0x2e);
+    }
 
     #[test]
     fn test_execute() {
@@ -48,15 +53,25 @@ mod test {
         let opcode = Opcode0xcxkk {
             vx: 3 as usize,
             byte: 0x2e,
-            random_byte: 0x13,
         };
         let mut memory = Memory::new();
         let mut register = Register::new();
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
-        let (_, receiver) = mpsc::channel();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        register.random = Random::with_seed(0x5eed);
+        let random_byte = Random::with_seed(0x5eed).next_byte();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
-        assert_eq!(register.v[3], 2);
+        assert_eq!(register.v[3], random_byte & 0x2e);
+        assert_ne!(register.random, Random::with_seed(0x5eed));
     }
 }
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
 /// The interpreter reads n bytes from memory, starting at the address stored in I.
@@ -8,7 +7,12 @@ use std::sync::mpsc;
 /// Sprites are XORed onto the existing screen.
 /// If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
 /// If the sprite is positioned so part of it is outside the coordinates of the display, it wraps
-/// around to the opposite side of the screen.
+/// around to the opposite side of the screen with the `wrap_sprites` quirk, and is clipped
+/// otherwise.
+/// With both XO-CHIP planes selected, the sprite data for plane 2 follows the data for plane 1.
+/// Dxy0 displays a 16x16 sprite stored as 32 bytes, two bytes per row (SUPER-CHIP).
+/// With the `count_collision_rows` quirk, VF is set to the number of rows which erased a pixel
+/// while in high resolution mode.
 pub struct Opcode0xdxyn {
     vx: usize,
     vy: usize,
@@ -30,22 +34,28 @@ impl Instruction for Opcode0xdxyn {
         memory: &mut Memory,
         register: &mut Register,
         graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        let start = register.i as usize;
-        let end = register.i as usize + self.nibble as usize;
-        let sprite = &memory.all[start..end];
-        let collision = graphic.set_sprite(
-            register.v[self.vx] as usize,
-            register.v[self.vy] as usize,
-            sprite,
-        );
-        if collision {
-            register.v[0xF] = 1;
+        _keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let x = register.v[self.vx] as usize;
+        let y = register.v[self.vy] as usize;
+        let planes = graphic.plane_count();
+        let collided_rows = if self.nibble == 0 {
+            let sprite = memory.read_sprite(register.i as usize, 32 * planes)?;
+            graphic.set_large_sprite(x, y, sprite, quirks.wrap_sprites)
+        } else {
+            let sprite = memory.read_sprite(register.i as usize, self.nibble as usize * planes)?;
+            graphic.set_sprite(x, y, sprite, quirks.wrap_sprites)
+        };
+        if quirks.count_collision_rows && graphic.is_hires() {
+            register.set_v(0xF, collided_rows as u8);
+        } else if collided_rows > 0 {
+            register.set_v(0xF, 1);
         } else {
-            register.v[0xF] = 0;
+            register.set_v(0xF, 0);
         }
-        register.pc += 2;
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -70,12 +80,21 @@ mod test {
         register.v[0x1] = x as u8;
         register.v[0x2] = y as u8;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
 
         assert_eq!(graphic.gfx[y * 64 + (x + 0)], 1);
         assert_eq!(graphic.gfx[y * 64 + (x + 1)], 1);
@@ -123,8 +142,7 @@ mod test {
         register.v[0x1] = x as u8;
         register.v[0x2] = y as u8;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
         graphic.gfx[0] = 1;
         graphic.gfx[1] = 0;
         graphic.gfx[2] = 0;
@@ -134,9 +152,19 @@ mod test {
         graphic.gfx[6] = 0;
         graphic.gfx[7] = 0;
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
 
         assert_eq!(graphic.gfx[0], 0);
         assert_eq!(graphic.gfx[1], 1);
@@ -150,4 +178,169 @@ mod test {
         assert_eq!(register.v[0xF], 1);
         assert_eq!(register.pc, 0x202);
     }
+
+    #[test]
+    fn test_execute_out_of_range() {
+        let instruction = 0xd12f;
+        let opcode = Opcode0xdxyn::new(instruction);
+
+        let mut memory = Memory::new();
+
+        let mut register = Register::new();
+        register.i = 0xffa;
+
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+
+        let result = opcode.execute(
+            &mut memory,
+            &mut register,
+            &mut graphic,
+            &mut keypad,
+            &quirks,
+        );
+
+        assert!(matches!(
+            result,
+            Err(EmulationError::MemoryOutOfRange {
+                address: 0xffa,
+                length: 0xf
+            })
+        ));
+    }
+
+    #[test]
+    fn test_execute_clip() {
+        let instruction: u16 = 0xd121;
+        let opcode = Opcode0xdxyn::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        memory.all[0x12] = 0xFF;
+        register.i = 0x12;
+        register.v[0x1] = 60;
+        register.v[0x2] = 0;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::COSMAC_VIP;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(graphic.gfx[63], 1);
+        assert_eq!(graphic.gfx[0], 0);
+        assert_eq!(graphic.gfx[3], 0);
+    }
+
+    #[test]
+    fn test_execute_wrap() {
+        let instruction: u16 = 0xd121;
+        let opcode = Opcode0xdxyn::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        memory.all[0x12] = 0xFF;
+        register.i = 0x12;
+        register.v[0x1] = 60;
+        register.v[0x2] = 0;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::XO_CHIP;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(graphic.gfx[63], 1);
+        assert_eq!(graphic.gfx[0], 1);
+        assert_eq!(graphic.gfx[3], 1);
+        assert_eq!(graphic.gfx[4], 0);
+    }
+
+    #[test]
+    fn test_execute_large_sprite() {
+        let instruction = 0xd120;
+        let opcode = Opcode0xdxyn::new(instruction);
+
+        let mut memory = Memory::new();
+        for row in 0..16 {
+            memory.all[0x300 + row * 2] = 0x80;
+            memory.all[0x300 + row * 2 + 1] = 0x01;
+        }
+
+        let mut register = Register::new();
+        register.i = 0x300;
+        register.v[0x1] = 2;
+        register.v[0x2] = 3;
+
+        let mut graphic = Graphic::new();
+        graphic.set_hires(true);
+        graphic.gfx[4 * 128 + 2] = 1;
+        graphic.gfx[5 * 128 + 17] = 1;
+
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::SUPER_CHIP;
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(graphic.gfx[3 * 128 + 2], 1);
+        assert_eq!(graphic.gfx[3 * 128 + 17], 1);
+        assert_eq!(graphic.gfx[18 * 128 + 2], 1);
+        assert_eq!(graphic.gfx[18 * 128 + 17], 1);
+        assert_eq!(graphic.gfx[4 * 128 + 2], 0);
+        assert_eq!(graphic.gfx[5 * 128 + 17], 0);
+        assert_eq!(register.v[0xF], 2);
+        assert_eq!(register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_execute_both_planes() {
+        let instruction = 0xd121;
+        let opcode = Opcode0xdxyn::new(instruction);
+
+        let mut memory = Memory::new();
+        memory.all[0x300] = 0xC0; // plane 1: 0b11000000
+        memory.all[0x301] = 0x60; // plane 2: 0b01100000
+
+        let mut register = Register::new();
+        register.i = 0x300;
+
+        let mut graphic = Graphic::new();
+        graphic.select_planes(0b11);
+
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::XO_CHIP;
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(graphic.gfx[0..4], [1, 3, 2, 0]);
+        assert_eq!(register.v[0xF], 0);
+        assert_eq!(register.pc, 0x202);
+    }
 }
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
-use crate::instructions::Instruction;
-use std::sync::mpsc;
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::{skip_next_instruction, Instruction};
 
 /// Skip next instruction if key with the value of Vx is not pressed.
 /// Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up
@@ -19,19 +18,18 @@ impl Opcode0xexa1 {
 impl Instruction for Opcode0xexa1 {
     fn execute(
         &self,
-        _memory: &mut Memory,
+        memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        match keyboard_bus.try_recv() {
-            Ok(value) if value == register.v[self.vx] => {
-                register.pc += 2;
-            }
-            _ => {
-                register.pc += 4;
-            }
-        };
+        keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        if keypad.is_pressed(register.v[self.vx]) {
+            register.pc = register.pc.wrapping_add(2);
+        } else {
+            skip_next_instruction(memory, register);
+        }
+        Ok(())
     }
 }
 
@@ -48,12 +46,21 @@ mod test {
         let mut register = Register::new();
         register.v[0xa] = 0x4;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (sender, receiver) = mpsc::channel();
-        sender.send(0x4).unwrap();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+        keypad.press(0x4);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x202);
     }
 
@@ -66,12 +73,21 @@ mod test {
         let mut register = Register::new();
         register.v[0xb] = 0x5;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
 
-        let (sender, receiver) = mpsc::channel();
-        sender.send(0x4).unwrap();
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
+        keypad.press(0x4);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
         assert_eq!(register.pc, 0x204);
     }
 }
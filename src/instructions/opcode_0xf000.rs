@@ -0,0 +1,57 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Set I = nnnn.
+/// The 16-bit address nnnn is stored in the two bytes following the instruction, which makes this
+/// XO-CHIP instruction four bytes long.
+pub struct Opcode0xf000;
+
+impl Opcode0xf000 {
+    pub fn new() -> Self {
+        Opcode0xf000
+    }
+}
+
+impl Instruction for Opcode0xf000 {
+    fn execute(
+        &self,
+        memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let address = memory.bytes(register.pc as usize + 2, 2)?;
+        register.set_i(((address[0] as u16) << 8) | address[1] as u16);
+        register.pc = register.pc.wrapping_add(4);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xf000::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        memory.all[0x202] = 0xab;
+        memory.all[0x203] = 0xcd;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.i, 0xabcd);
+        assert_eq!(register.pc, 0x204);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Load the audio pattern buffer from the 16 bytes starting at location I.
+pub struct Opcode0xf002;
+
+impl Opcode0xf002 {
+    pub fn new() -> Self {
+        Opcode0xf002
+    }
+}
+
+impl Instruction for Opcode0xf002 {
+    fn execute(
+        &self,
+        memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let pattern = memory.read_bytes(register.i as usize, 16)?;
+        register.audio_pattern.copy_from_slice(pattern);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xf002::new();
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.i = 0x300;
+        memory.all[0x300] = 0xff;
+        memory.all[0x30f] = 0x0f;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.audio_pattern[0], 0xff);
+        assert_eq!(register.audio_pattern[15], 0x0f);
+        assert_eq!(register.pc, 0x202);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Select drawing planes n.
+/// n is a bit mask of the XO-CHIP bitplanes affected by drawing, clearing and scrolling.
+pub struct Opcode0xfn01 {
+    planes: u8,
+}
+
+impl Opcode0xfn01 {
+    pub fn new(instruction: u16) -> Self {
+        let planes = ((instruction & 0x0F00) >> 8) as u8;
+        Opcode0xfn01 { planes }
+    }
+}
+
+impl Instruction for Opcode0xfn01 {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        graphic.select_planes(self.planes);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xfn01::new(0xf301);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(graphic.selected_planes(), 0b11);
+        assert_eq!(graphic.plane_count(), 2);
+        assert_eq!(register.pc, 0x202);
+    }
+}
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set Vx = delay timer value.
 /// The value of DT is placed into Vx.
@@ -21,19 +20,18 @@ impl Instruction for Opcode0xfx07 {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        let dt = register.delay_timer.lock().unwrap();
-        register.v[self.vx] = *dt;
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.set_v(self.vx, register.delay_timer);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::sync::Arc;
-    use std::sync::Mutex;
 
     #[test]
     fn test_execute() {
@@ -42,16 +40,26 @@ mod test {
         let mut memory = Memory::new();
 
         let mut register = Register::new();
-        register.delay_timer = Arc::new(Mutex::new(7));
+        register.delay_timer = 7;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
 
-        assert_eq!(*register.delay_timer.lock().unwrap(), 7);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(register.v[0x5], 7);
+        assert_eq!(register.delay_timer, 7);
         assert_eq!(register.pc, 0x202);
     }
 }
@@ -1,9 +1,11 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Wait for a key press, store the value of the key in Vx.
-/// All execution stops until a key is pressed, then the value of that key is stored in Vx
+/// All execution stops until a key is pressed and released, then the value of that key is stored
+/// in Vx. The machine is put into a waiting state which `Machine::step` polls, so the timers keep
+/// counting down meanwhile. Releases from before this instruction, and of keys held since before
+/// it, are ignored.
 pub struct Opcode0xfx0a {
     vx: usize,
 }
@@ -21,15 +23,13 @@ impl Instruction for Opcode0xfx0a {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        match keyboard_bus.recv() {
-            Ok(key) => {
-                register.v[self.vx] = key;
-            }
-            _ => { /* do nothing*/ }
-        }
-        register.pc += 2;
+        keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        keypad.forget_released();
+        register.waiting_for_key = Some(self.vx);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -45,15 +45,53 @@ mod test {
 
         let mut register = Register::new();
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (sender, receiver) = mpsc::channel();
-        sender.send(0x9).unwrap();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
 
-        assert_eq!(register.v[0x5], 0x9);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(register.waiting_for_key, Some(0x5));
         assert_eq!(register.pc, 0x202);
     }
+
+    #[test]
+    fn test_execute_ignores_earlier_release() {
+        let instruction = 0xf50a;
+        let opcode = Opcode0xfx0a::new(instruction);
+        let mut memory = Memory::new();
+
+        let mut register = Register::new();
+
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+        keypad.press(0x9);
+        keypad.release(0x9);
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(keypad.take_released(), None);
+        assert_eq!(register.waiting_for_key, Some(0x5));
+    }
 }
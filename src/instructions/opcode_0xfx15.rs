@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set delay time = Vx.
 /// DT is set equal to the value of Vx.
@@ -21,11 +20,12 @@ impl Instruction for Opcode0xfx15 {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        let mut dt = register.delay_timer.lock().unwrap();
-        *dt = register.v[self.vx];
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.delay_timer = register.v[self.vx];
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -42,14 +42,23 @@ mod test {
         let mut register = Register::new();
         register.v[0x5] = 0xa;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
 
-        assert_eq!(*register.delay_timer.lock().unwrap(), 0xa);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(register.delay_timer, 0xa);
         assert_eq!(register.pc, 0x202);
     }
 }
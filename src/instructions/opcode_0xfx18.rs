@@ -1,8 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
-use std::sync::Arc;
-use std::sync::Mutex;
 
 /// Set sound timer = Vx.
 /// ST is set equal to the value of Vx.
@@ -23,10 +20,12 @@ impl Instruction for Opcode0xfx18 {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.sound_timer = Arc::new(Mutex::new(register.v[self.vx]));
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.sound_timer = register.v[self.vx];
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -43,14 +42,23 @@ mod test {
         let mut register = Register::new();
         register.v[0x5] = 0xb;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
 
-        assert_eq!(*register.sound_timer.lock().unwrap(), 0xb);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(register.sound_timer, 0xb);
         assert_eq!(register.pc, 0x202);
     }
 }
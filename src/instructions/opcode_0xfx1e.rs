@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set I = I + Vx.
 /// The values of I and Vx are added, and the results are stored in I.
@@ -21,10 +20,12 @@ impl Instruction for Opcode0xfx1e {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        register.i += register.v[self.vx] as u16;
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.set_i(register.i.wrapping_add(register.v[self.vx] as u16));
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -42,14 +43,47 @@ mod test {
         register.i = 0xa;
         register.v[0x5] = 0x2;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
 
         assert_eq!(register.i, 0xc);
         assert_eq!(register.pc, 0x202);
     }
+
+    #[test]
+    fn test_execute_wraps_i() {
+        let instruction = 0xf51e;
+        let opcode = Opcode0xfx1e::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        register.i = 0xffff;
+        register.v[0x5] = 0x2;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.i, 0x1);
+        assert_eq!(register.pc, 0x202);
+    }
 }
@@ -1,6 +1,7 @@
-use crate::emulator::{Graphic, Memory, Register, FONT_BASE, FONT_LENGTH};
+use crate::emulator::{
+    EmulationError, Graphic, Keypad, Memory, Quirks, Register, FONT_BASE, FONT_LENGTH,
+};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Set I = location of sprite for digit Vx.
 /// The value of I is set to the location for the hexadecimal sprite corresponding to the value of
@@ -22,14 +23,16 @@ impl Instruction for Opcode0xfx29 {
         _memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
         let digit = register.v[self.vx] as u16;
         let font_base = FONT_BASE as u16;
         let font_length = FONT_LENGTH as u16;
 
-        register.i = font_base + digit * font_length;
-        register.pc += 2;
+        register.set_i(font_base + digit * font_length);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -47,12 +50,21 @@ mod test {
         register.i = 0xa;
         register.v[0x5] = 0xe;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
 
         assert_eq!(register.i, 0x46);
         assert_eq!(register.pc, 0x202);
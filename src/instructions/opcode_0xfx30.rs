@@ -0,0 +1,64 @@
+use crate::emulator::{
+    EmulationError, Graphic, Keypad, Memory, Quirks, Register, BIG_FONT_BASE, BIG_FONT_LENGTH,
+};
+use crate::instructions::Instruction;
+
+/// Set I = location of the large sprite for digit Vx.
+/// The value of I is set to the location of the 8x10 hexadecimal sprite corresponding to the
+/// value of Vx.
+pub struct Opcode0xfx30 {
+    vx: usize,
+}
+
+impl Opcode0xfx30 {
+    pub fn new(instruction: u16) -> Self {
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
+        Opcode0xfx30 { vx }
+    }
+}
+
+impl Instruction for Opcode0xfx30 {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let digit = register.v[self.vx] as u16;
+        let font_base = BIG_FONT_BASE as u16;
+        let font_length = BIG_FONT_LENGTH as u16;
+
+        register.set_i(font_base + digit * font_length);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xfx30::new(0xf530);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.v[0x5] = 0x3;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.i, 0x50 + 0x1e);
+        assert_eq!(register.pc, 0x202);
+    }
+}
@@ -1,6 +1,5 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Store BCD representaion of Vx in memory locations, I, I+1, and I+2.
 /// The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at
@@ -22,12 +21,14 @@ impl Instruction for Opcode0xfx33 {
         memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        memory.all[register.i as usize] = register.v[self.vx] / 100;
-        memory.all[register.i as usize + 1] = (register.v[self.vx] % 100) / 10;
-        memory.all[register.i as usize + 2] = register.v[self.vx] % 10;
-        register.pc += 2;
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let value = register.v[self.vx];
+        let bcd = [value / 100, (value % 100) / 10, value % 10];
+        memory.write_bytes(register.i as usize, &bcd)?;
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
@@ -45,12 +46,21 @@ mod test {
         register.i = 0xa;
         register.v[0x5] = 0x7b; // 123
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
 
         assert_eq!(memory.all[0xa], 0x1);
         assert_eq!(memory.all[0xb], 0x2);
@@ -0,0 +1,57 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Set pitch register = Vx.
+/// The audio pattern is played back at 4000 * 2 ^ ((Vx - 64) / 48) bits per second.
+pub struct Opcode0xfx3a {
+    vx: usize,
+}
+
+impl Opcode0xfx3a {
+    pub fn new(instruction: u16) -> Self {
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
+        Opcode0xfx3a { vx }
+    }
+}
+
+impl Instruction for Opcode0xfx3a {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.pitch = register.v[self.vx];
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xfx3a::new(0xf53a);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.v[0x5] = 0x70;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.pitch, 0x70);
+        assert_eq!(register.pc, 0x202);
+    }
+}
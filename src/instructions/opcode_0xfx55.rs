@@ -1,9 +1,9 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Store registers V0 through Vx in memory starting at location I.
 /// The interpreter copies of registers V0 through Vx into memory, starting at the address in I.
+/// With the `increment_i` quirk, I is left at I + x + 1.
 pub struct Opcode0xfx55 {
     vx: usize,
 }
@@ -21,20 +21,22 @@ impl Instruction for Opcode0xfx55 {
         memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        for index in 0..self.vx + 1 {
-            let value = register.v[index];
-            memory.all[register.i as usize + index as usize] = value;
+        _keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        memory.write_bytes(register.i as usize, &register.v[..self.vx + 1])?;
+        if quirks.increment_i {
+            register.set_i(register.i.wrapping_add(self.vx as u16 + 1));
         }
-        register.i += self.vx as u16 + 1;
-        register.pc += 2;
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::emulator::XO_MEMORY_SIZE;
 
     #[test]
     fn test_execute() {
@@ -43,7 +45,7 @@ mod test {
         let mut memory = Memory::new();
 
         let mut register = Register::new();
-        register.i = 0xa;
+        register.i = 0x30a;
         register.v[0x0] = 0x1;
         register.v[0x1] = 0x2;
         register.v[0x2] = 0x3;
@@ -51,21 +53,112 @@ mod test {
         register.v[0x4] = 0x5;
         register.v[0x5] = 0x6;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+
+        assert_eq!(memory.all[0x309], 0x0);
+        assert_eq!(memory.all[0x30a], 0x1);
+        assert_eq!(memory.all[0x30b], 0x2);
+        assert_eq!(memory.all[0x30c], 0x3);
+        assert_eq!(memory.all[0x30d], 0x4);
+        assert_eq!(memory.all[0x30e], 0x5);
+        assert_eq!(memory.all[0x30f], 0x6);
+        assert_eq!(memory.all[0x310], 0x0);
+        assert_eq!(register.i, 0x310);
+        assert_eq!(register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_execute_out_of_range() {
+        let instruction = 0xff55;
+        let opcode = Opcode0xfx55::new(instruction);
+        let mut memory = Memory::new();
+
+        let mut register = Register::new();
+        register.i = 0xff8;
+
+        let mut graphic = Graphic::new();
 
-        let (_, receiver) = mpsc::channel();
+        let mut keypad = Keypad::new();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        let quirks = Quirks::default();
 
-        assert_eq!(memory.all[0x9], 0x0);
-        assert_eq!(memory.all[0xa], 0x1);
-        assert_eq!(memory.all[0xb], 0x2);
-        assert_eq!(memory.all[0xc], 0x3);
-        assert_eq!(memory.all[0xd], 0x4);
-        assert_eq!(memory.all[0xe], 0x5);
-        assert_eq!(memory.all[0xf], 0x6);
-        assert_eq!(memory.all[0x10], 0x0);
+        let result = opcode.execute(
+            &mut memory,
+            &mut register,
+            &mut graphic,
+            &mut keypad,
+            &quirks,
+        );
+
+        assert!(matches!(
+            result,
+            Err(EmulationError::MemoryOutOfRange {
+                address: 0xff8,
+                length: 0x10
+            })
+        ));
+        assert_eq!(register.pc, 0x200);
+    }
+
+    #[test]
+    fn test_execute_without_increment_i() {
+        let instruction: u16 = 0xf255;
+        let opcode = Opcode0xfx55::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        register.i = 0xa;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::SUPER_CHIP;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.i, 0xa);
+        assert_eq!(register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_execute_wraps_i() {
+        let instruction: u16 = 0xff55;
+        let opcode = Opcode0xfx55::new(instruction);
+        let mut memory = Memory::with_size(XO_MEMORY_SIZE);
+        let mut register = Register::new();
+        register.i = 0xfff0;
+        register.v[0xf] = 0x10;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(memory.all[0xffff], 0x10);
+        assert_eq!(register.i, 0x0);
         assert_eq!(register.pc, 0x202);
     }
 }
@@ -1,9 +1,9 @@
-use crate::emulator::{Graphic, Memory, Register};
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
 use crate::instructions::Instruction;
-use std::sync::mpsc;
 
 /// Read registers V0 through Vx from memory starting at location I.
 /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
+/// With the `increment_i` quirk, I is left at I + x + 1.
 pub struct Opcode0xfx65 {
     vx: usize,
 }
@@ -21,20 +21,25 @@ impl Instruction for Opcode0xfx65 {
         memory: &mut Memory,
         register: &mut Register,
         _graphic: &mut Graphic,
-        _keyboard_bus: &mpsc::Receiver<u8>,
-    ) {
-        for index in 0..self.vx + 1 {
-            let value = memory.all[register.i as usize + index as usize];
-            register.v[index] = value;
+        _keypad: &mut Keypad,
+        quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        let source = memory.read_bytes(register.i as usize, self.vx + 1)?;
+        for (index, &byte) in source.iter().enumerate() {
+            register.set_v(index, byte);
         }
-        register.i += (self.vx + 1) as u16;
-        register.pc += 2;
+        if quirks.increment_i {
+            register.set_i(register.i.wrapping_add((self.vx + 1) as u16));
+        }
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::emulator::XO_MEMORY_SIZE;
 
     #[test]
     fn test_execute() {
@@ -52,12 +57,21 @@ mod test {
         memory.all[0xe] = 0x5;
         memory.all[0xf] = 0x6;
 
-        let (sender, _) = mpsc::channel();
-        let mut graphic = Graphic::new(sender);
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
 
-        let (_, receiver) = mpsc::channel();
+        let quirks = Quirks::default();
 
-        opcode.execute(&mut memory, &mut register, &mut graphic, &receiver);
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
 
         assert_eq!(register.v[0x0], 0x1);
         assert_eq!(register.v[0x1], 0x2);
@@ -67,4 +81,85 @@ mod test {
         assert_eq!(register.v[0x5], 0x6);
         assert_eq!(register.pc, 0x202);
     }
+
+    #[test]
+    fn test_execute_out_of_range() {
+        let instruction = 0xff65;
+        let opcode = Opcode0xfx65::new(instruction);
+        let mut memory = Memory::new();
+
+        let mut register = Register::new();
+        register.i = 0xff8;
+
+        let mut graphic = Graphic::new();
+
+        let mut keypad = Keypad::new();
+
+        let quirks = Quirks::default();
+
+        let result = opcode.execute(
+            &mut memory,
+            &mut register,
+            &mut graphic,
+            &mut keypad,
+            &quirks,
+        );
+
+        assert!(matches!(
+            result,
+            Err(EmulationError::MemoryOutOfRange {
+                address: 0xff8,
+                length: 0x10
+            })
+        ));
+        assert_eq!(register.pc, 0x200);
+    }
+
+    #[test]
+    fn test_execute_without_increment_i() {
+        let instruction: u16 = 0xf265;
+        let opcode = Opcode0xfx65::new(instruction);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        register.i = 0xa;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::SUPER_CHIP;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.i, 0xa);
+        assert_eq!(register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_execute_wraps_i() {
+        let instruction: u16 = 0xff65;
+        let opcode = Opcode0xfx65::new(instruction);
+        let mut memory = Memory::with_size(XO_MEMORY_SIZE);
+        memory.all[0xffff] = 0x10;
+        let mut register = Register::new();
+        register.i = 0xfff0;
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.v[0xf], 0x10);
+        assert_eq!(register.i, 0x0);
+        assert_eq!(register.pc, 0x202);
+    }
 }
@@ -0,0 +1,59 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Store registers V0 through Vx in the RPL user flags.
+pub struct Opcode0xfx75 {
+    vx: usize,
+}
+
+impl Opcode0xfx75 {
+    pub fn new(instruction: u16) -> Self {
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
+        Opcode0xfx75 { vx }
+    }
+}
+
+impl Instruction for Opcode0xfx75 {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        register.rpl[..self.vx + 1].copy_from_slice(&register.v[..self.vx + 1]);
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xfx75::new(0xf275);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.v[0x0] = 0x1;
+        register.v[0x1] = 0x2;
+        register.v[0x2] = 0x3;
+        register.v[0x3] = 0x4;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.rpl[..4], [0x1, 0x2, 0x3, 0x0]);
+        assert_eq!(register.pc, 0x202);
+    }
+}
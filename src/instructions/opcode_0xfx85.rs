@@ -0,0 +1,61 @@
+use crate::emulator::{EmulationError, Graphic, Keypad, Memory, Quirks, Register};
+use crate::instructions::Instruction;
+
+/// Read registers V0 through Vx from the RPL user flags.
+pub struct Opcode0xfx85 {
+    vx: usize,
+}
+
+impl Opcode0xfx85 {
+    pub fn new(instruction: u16) -> Self {
+        let vx = ((instruction & 0x0F00) >> 8) as usize;
+        Opcode0xfx85 { vx }
+    }
+}
+
+impl Instruction for Opcode0xfx85 {
+    fn execute(
+        &self,
+        _memory: &mut Memory,
+        register: &mut Register,
+        _graphic: &mut Graphic,
+        _keypad: &mut Keypad,
+        _quirks: &Quirks,
+    ) -> Result<(), EmulationError> {
+        for index in 0..=self.vx {
+            register.set_v(index, register.rpl[index]);
+        }
+        register.pc = register.pc.wrapping_add(2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let opcode = Opcode0xfx85::new(0xf285);
+        let mut memory = Memory::new();
+        let mut register = Register::new();
+        let mut graphic = Graphic::new();
+        let mut keypad = Keypad::new();
+        let quirks = Quirks::default();
+        register.rpl[0x0] = 0x1;
+        register.rpl[0x1] = 0x2;
+        register.rpl[0x2] = 0x3;
+        register.rpl[0x3] = 0x4;
+        opcode
+            .execute(
+                &mut memory,
+                &mut register,
+                &mut graphic,
+                &mut keypad,
+                &quirks,
+            )
+            .unwrap();
+        assert_eq!(register.v[..4], [0x1, 0x2, 0x3, 0x0]);
+        assert_eq!(register.pc, 0x202);
+    }
+}
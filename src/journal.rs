@@ -0,0 +1,197 @@
+use super::keypad::Keypad;
+use super::machine::Machine;
+use super::random::Random;
+use std::collections::VecDeque;
+
+/// Default number of instructions kept by a `Journal`.
+pub const JOURNAL_CAPACITY: usize = 100_000;
+
+/// Old pixels recorded by the framebuffer while a journal entry is open.
+#[derive(Default)]
+pub(crate) struct PixelChanges {
+    /// Index and previous value of every pixel changed, in the order of the changes.
+    pub pixels: Vec<(usize, u8)>,
+    /// The whole framebuffer, recorded instead of single pixels when an instruction clears,
+    /// scrolls or resizes the screen.
+    pub screen: Option<Vec<u8>>,
+}
+
+/// Everything one instruction may overwrite, as it was before the instruction ran.
+/// Registers, timers, the call stack and the keypad are small enough to be kept whole, while
+/// memory and the framebuffer only keep the bytes which were actually changed.
+struct Entry {
+    pc: u16,
+    i: u16,
+    v: [u8; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    rpl: [u8; 16],
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    halted: bool,
+    waiting_for_key: Option<usize>,
+    random: Random,
+    stack: Vec<u16>,
+    keypad: Keypad,
+    hires: bool,
+    planes: u8,
+    /// Address and previous value of every byte written, in the order of the writes.
+    memory: Vec<(usize, u8)>,
+    pixels: PixelChanges,
+}
+
+impl Entry {
+    fn capture(machine: &Machine) -> Self {
+        let register = &machine.register;
+        Entry {
+            pc: register.pc,
+            i: register.i,
+            v: register.v,
+            delay_timer: register.delay_timer,
+            sound_timer: register.sound_timer,
+            rpl: register.rpl,
+            audio_pattern: register.audio_pattern,
+            pitch: register.pitch,
+            halted: register.halted,
+            waiting_for_key: register.waiting_for_key,
+            random: register.random,
+            stack: machine.memory.stack.clone(),
+            keypad: machine.keypad.clone(),
+            hires: machine.graphic.is_hires(),
+            planes: machine.graphic.selected_planes(),
+            memory: Vec::new(),
+            pixels: PixelChanges::default(),
+        }
+    }
+
+    fn restore(self, machine: &mut Machine) {
+        let register = &mut machine.register;
+        register.pc = self.pc;
+        register.i = self.i;
+        register.v = self.v;
+        register.delay_timer = self.delay_timer;
+        register.sound_timer = self.sound_timer;
+        register.rpl = self.rpl;
+        register.audio_pattern = self.audio_pattern;
+        register.pitch = self.pitch;
+        register.halted = self.halted;
+        register.waiting_for_key = self.waiting_for_key;
+        register.random = self.random;
+        machine.memory.stack = self.stack;
+        machine.keypad = self.keypad;
+        for &(address, byte) in self.memory.iter().rev() {
+            machine.memory.all[address] = byte;
+        }
+
+        let graphic = &mut machine.graphic;
+        if graphic.is_hires() != self.hires {
+            graphic.set_hires(self.hires);
+        }
+        graphic.select_planes(self.planes);
+        for &(index, pixel) in self.pixels.pixels.iter().rev() {
+            graphic.gfx[index] = pixel;
+        }
+        if let Some(screen) = self.pixels.screen {
+            graphic.gfx = screen;
+        }
+    }
+}
+
+/// Undo log holding what each of the last `capacity` instructions overwrote, so a `Machine`
+/// can step backwards one instruction at a time.
+pub struct Journal {
+    pub capacity: usize,
+    entries: VecDeque<Entry>,
+    /// Entry of the instruction being executed.
+    open: Option<Entry>,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Journal {
+            capacity,
+            entries: VecDeque::new(),
+            open: None,
+        }
+    }
+
+    /// Number of instructions which can be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Record the state of `machine` before an instruction and start collecting the memory and
+    /// pixels it overwrites.
+    pub(crate) fn begin(&mut self, machine: &mut Machine) {
+        self.open = Some(Entry::capture(machine));
+        machine.memory.undo = Some(Vec::new());
+        machine.graphic.undo = Some(PixelChanges::default());
+    }
+
+    /// Finish the entry opened by `begin`. It is kept only if the instruction was executed.
+    pub(crate) fn end(&mut self, machine: &mut Machine, executed: bool) {
+        let memory = machine.memory.undo.take().unwrap_or_default();
+        let pixels = machine.graphic.undo.take().unwrap_or_default();
+        if let (Some(mut entry), true) = (self.open.take(), executed) {
+            entry.memory = memory;
+            entry.pixels = pixels;
+            if self.entries.len() >= self.capacity {
+                self.entries.pop_front();
+            }
+            if self.capacity > 0 {
+                self.entries.push_back(entry);
+            }
+        }
+    }
+
+    /// Undo the newest instruction on `machine`. Returns false if there is nothing to undo.
+    pub(crate) fn undo(&mut self, machine: &mut Machine) -> bool {
+        match self.entries.pop_back() {
+            Some(entry) => {
+                entry.restore(machine);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new(JOURNAL_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capacity() {
+        let mut machine = Machine::new();
+        // 7101: ADD V1, 0x01
+        machine.memory.all[0x200] = 0x71;
+        machine.memory.all[0x201] = 0x01;
+        // 1200: JP 0x200
+        machine.memory.all[0x202] = 0x12;
+        machine.memory.all[0x203] = 0x00;
+        machine.journal = Some(Journal::new(3));
+        machine.run_cycles(10).unwrap();
+        assert_eq!(machine.journal.as_ref().unwrap().len(), 3);
+        assert_eq!(machine.register.v[1], 5);
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert!(!machine.step_back());
+        assert_eq!(machine.register.v[1], 4);
+        assert_eq!(machine.register.pc, 0x202);
+    }
+}
@@ -0,0 +1,175 @@
+/// State of the 16-key hexadecimal keypad.
+/// Keys are held from the moment they are pressed until they are released. Frontends which cannot
+/// observe key releases, such as terminals, can `tap` a key instead, which releases it
+/// automatically after a number of frames.
+/// Releases are remembered until they are taken, which is how Fx0A learns that a key went up.
+/// Only releases of keys pressed since `forget_released` count, so that a key held before Fx0A
+/// started waiting does not end the wait.
+#[derive(Clone)]
+pub struct Keypad {
+    pub(crate) held: u16,
+    /// Keys pressed since `forget_released`.
+    pub(crate) pressed: u16,
+    pub(crate) released: u16,
+    pub(crate) timeouts: [u32; 16],
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Keypad {
+            held: 0,
+            pressed: 0,
+            released: 0,
+            timeouts: [0; 16],
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.held |= 1 << key;
+        self.pressed |= 1 << key;
+        self.timeouts[key as usize] = 0;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.released |= self.held & self.pressed & (1 << key);
+        self.held &= !(1 << key);
+        self.timeouts[key as usize] = 0;
+    }
+
+    /// Press `key` and release it after `frames` calls to `tick`.
+    /// Tapping a key which is still held extends the timeout.
+    pub fn tap(&mut self, key: u8, frames: u32) {
+        self.press(key);
+        self.timeouts[(key & 0xF) as usize] = frames;
+    }
+
+    /// Count down tapped keys, releasing those whose timeout has expired.
+    pub fn tick(&mut self) {
+        for key in 0..16 {
+            if self.timeouts[key] > 0 {
+                self.timeouts[key] -= 1;
+                if self.timeouts[key] == 0 {
+                    self.release(key as u8);
+                }
+            }
+        }
+    }
+
+    /// Hold exactly the keys of the bit mask `held`, pressing and releasing keys as needed.
+    pub fn set_held(&mut self, held: u16) {
+        for key in 0..16 {
+            let bit = 1 << key;
+            if held & bit != 0 && self.held & bit == 0 {
+                self.press(key);
+            } else if held & bit == 0 && self.held & bit != 0 {
+                self.release(key);
+            }
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key <= 0xF && self.held & (1 << key) != 0
+    }
+
+    /// Bit mask of the held keys, bit n for key n.
+    pub fn held(&self) -> u16 {
+        self.held
+    }
+
+    /// Forget the remembered releases and which keys were pressed, so that only keys pressed from
+    /// now on are remembered when they are released.
+    pub fn forget_released(&mut self) {
+        self.pressed = 0;
+        self.released = 0;
+    }
+
+    /// Return the lowest key released since the last call, and forget all remembered releases.
+    pub fn take_released(&mut self) -> Option<u8> {
+        let released = self.released;
+        self.released = 0;
+        if released == 0 {
+            None
+        } else {
+            Some(released.trailing_zeros() as u8)
+        }
+    }
+}
+
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_press_and_release() {
+        let mut keypad = Keypad::new();
+        keypad.press(0x3);
+        keypad.press(0xa);
+        assert!(keypad.is_pressed(0x3));
+        assert!(keypad.is_pressed(0xa));
+        assert_eq!(keypad.held(), 0b0000_0100_0000_1000);
+        keypad.release(0x3);
+        assert!(!keypad.is_pressed(0x3));
+        assert!(keypad.is_pressed(0xa));
+    }
+
+    #[test]
+    fn test_tap() {
+        let mut keypad = Keypad::new();
+        keypad.tap(0x5, 2);
+        keypad.tick();
+        assert!(keypad.is_pressed(0x5));
+        keypad.tap(0x5, 2);
+        keypad.tick();
+        assert!(keypad.is_pressed(0x5));
+        keypad.tick();
+        assert!(!keypad.is_pressed(0x5));
+        assert_eq!(keypad.take_released(), Some(0x5));
+    }
+
+    #[test]
+    fn test_take_released() {
+        let mut keypad = Keypad::new();
+        keypad.release(0x2);
+        assert_eq!(keypad.take_released(), None);
+        keypad.press(0xc);
+        keypad.press(0x9);
+        keypad.release(0xc);
+        keypad.release(0x9);
+        assert_eq!(keypad.take_released(), Some(0x9));
+        assert_eq!(keypad.take_released(), None);
+    }
+
+    #[test]
+    fn test_forget_released() {
+        let mut keypad = Keypad::new();
+        keypad.press(0x4);
+        keypad.press(0x6);
+        keypad.release(0x6);
+        keypad.forget_released();
+        keypad.release(0x4);
+        assert_eq!(keypad.take_released(), None);
+        keypad.press(0x4);
+        keypad.release(0x4);
+        assert_eq!(keypad.take_released(), Some(0x4));
+    }
+
+    #[test]
+    fn test_set_held() {
+        let mut keypad = Keypad::new();
+        keypad.press(0x1);
+        keypad.press(0x2);
+        keypad.set_held(0b1000_0000_0000_0100);
+        assert_eq!(keypad.held(), 0b1000_0000_0000_0100);
+        assert_eq!(keypad.take_released(), Some(0x1));
+        keypad.set_held(0b1000_0000_0000_0100);
+        assert_eq!(keypad.take_released(), None);
+    }
+}
@@ -1,10 +1,65 @@
+mod assembler;
+mod clock;
+mod coverage;
 mod cpu;
+mod dap;
+mod debugger;
+mod disassembler;
+mod error;
+mod gif;
 mod graphic;
 mod instructions;
+mod journal;
+mod keypad;
+mod machine;
 mod memory;
+mod movie;
+mod profiler;
+mod quirks;
+mod random;
+mod rewind;
+mod screenshot;
+mod script;
+mod state;
+mod trace;
+mod watch;
 
 pub mod emulator {
-    pub use super::cpu::{Cpu, Register};
-    pub use super::graphic::Graphic;
-    pub use super::memory::{Memory, FONT_BASE, FONT_LENGTH};
+    pub use super::assembler::{
+        assemble, assemble_file, assemble_file_with_map, assemble_with_map, AssemblyError,
+        SourceMap, SourceMapEntry,
+    };
+    pub use super::clock::{
+        frame_duration, Clock, FrameCounter, FramePacer, ManualClock, SystemClock, FRAME_RATE,
+        MAX_LAG,
+    };
+    pub use super::coverage::{Access, Coverage};
+    pub use super::cpu::{
+        Cpu, Event, Register, Speed, KEY_TAP_FRAMES, SLOW_MOTION_FACTOR, TURBO_FRAME_LIMIT,
+    };
+    pub use super::dap::{read_message, write_message, DapServer};
+    pub use super::debugger::{Debugger, Stop, DEBUGGER_HELP};
+    pub use super::disassembler::{instruction_length, mnemonic, Disassembly, Line, Syntax};
+    pub use super::error::EmulationError;
+    pub use super::gif::GifRecorder;
+    pub use super::graphic::{
+        Graphic, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH, PLANE_MASK,
+    };
+    pub use super::journal::{Journal, JOURNAL_CAPACITY};
+    pub use super::keypad::Keypad;
+    pub use super::machine::{Machine, CYCLES_PER_FRAME};
+    pub use super::memory::{
+        Memory, BIG_FONT_BASE, BIG_FONT_LENGTH, FONT_BASE, FONT_LENGTH, MEMORY_SIZE, PROGRAM_START,
+        STACK_DEPTH, XO_MEMORY_SIZE,
+    };
+    pub use super::movie::{Movie, MOVIE_VERSION};
+    pub use super::profiler::{Profiler, SubroutineCost};
+    pub use super::quirks::{Profile, Quirks};
+    pub use super::random::Random;
+    pub use super::rewind::{RewindBuffer, REWIND_BUDGET, REWIND_INTERVAL};
+    pub use super::screenshot::{parse_color, ImageFormat, Palette, Screenshot};
+    pub use super::script::{KeyAction, KeyEvent, KeyScript, SCRIPT_TAP_FRAMES};
+    pub use super::state::STATE_VERSION;
+    pub use super::trace::{TraceLine, Tracer, TRACE_HEADER};
+    pub use super::watch::{WatchHit, Watches, Watchpoint};
 }
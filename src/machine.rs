@@ -0,0 +1,623 @@
+use super::clock::{Clock, FrameCounter};
+use super::coverage::Access;
+use super::cpu::Register;
+use super::disassembler::instruction_length;
+use super::error::EmulationError;
+use super::graphic::Graphic;
+use super::instructions;
+use super::journal::Journal;
+use super::keypad::Keypad;
+use super::memory::{Memory, XO_MEMORY_SIZE};
+use super::profiler::Profiler;
+use super::quirks::{Profile, Quirks};
+use super::rewind::RewindBuffer;
+use super::state;
+use super::trace::Tracer;
+use super::watch::{WatchHit, Watchpoint};
+use std::path::Path;
+
+/// Default number of instructions executed by `Machine::run_frame` before the timers are ticked.
+pub const CYCLES_PER_FRAME: usize = 8;
+
+/// Single-threaded CHIP-8 core.
+/// The machine owns the whole emulated state and only advances when it is driven through `step`,
+/// `run_cycles` or `run_frame`. It never sleeps, spawns threads or talks to channels, so it can be
+/// embedded in tests, tools and frontends which decide for themselves how fast it should run.
+pub struct Machine {
+    pub memory: Memory,
+    pub register: Register,
+    pub graphic: Graphic,
+    pub keypad: Keypad,
+    pub quirks: Quirks,
+    /// Instructions executed per 60 Hz frame.
+    pub cycles_per_frame: usize,
+    /// Trace of every executed instruction, if enabled.
+    pub trace: Option<Tracer>,
+    /// Snapshots taken at the end of frames for `rewind`, if enabled.
+    pub rewind_buffer: Option<RewindBuffer>,
+    /// Undo log of the last instructions for `step_back`, if enabled.
+    pub journal: Option<Journal>,
+    /// Execution counts of the instructions, if enabled.
+    pub profiler: Option<Profiler>,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Machine {
+            memory: Memory::new(),
+            register: Register::new(),
+            graphic: Graphic::new(),
+            keypad: Keypad::new(),
+            quirks: Quirks::default(),
+            cycles_per_frame: CYCLES_PER_FRAME,
+            trace: None,
+            rewind_buffer: None,
+            journal: None,
+            profiler: None,
+        }
+    }
+
+    /// Create a machine running with the quirks of `profile`.
+    /// XO-CHIP programs get the full 64 KiB address space.
+    pub fn with_profile(profile: Profile) -> Self {
+        let memory = match profile {
+            Profile::XoChip => Memory::with_size(XO_MEMORY_SIZE),
+            _ => Memory::new(),
+        };
+        Machine {
+            memory,
+            quirks: profile.quirks(),
+            ..Machine::new()
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, rom: P) -> Result<(), EmulationError> {
+        self.memory.load(rom)
+    }
+
+    /// Fetch, decode and execute the instruction at PC.
+    /// On error the machine is left as it was before the failing instruction.
+    /// Does nothing once the program has exited.
+    /// While Fx0A is waiting, a step only polls the keypad and completes the wait once a key has
+    /// been released.
+    pub fn step(&mut self) -> Result<(), EmulationError> {
+        if self.register.halted {
+            return Ok(());
+        }
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return self.execute(),
+        };
+        journal.begin(self);
+        let result = self.execute();
+        journal.end(self, result.is_ok());
+        self.journal = Some(journal);
+        result
+    }
+
+    fn execute(&mut self) -> Result<(), EmulationError> {
+        if let Some(vx) = self.register.waiting_for_key {
+            if let Some(key) = self.keypad.take_released() {
+                self.register.set_v(vx, key);
+                self.register.waiting_for_key = None;
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record_wait();
+            }
+            return Ok(());
+        }
+        let pc = self.register.pc;
+        let opcode = self.memory.read(pc)?;
+        if let Some(mut trace) = self.trace.take() {
+            let recorded = trace.record(self);
+            self.trace = Some(trace);
+            recorded?;
+        }
+        let instruction = instructions::decode(pc, opcode)?;
+        if let Some(coverage) = &mut self.memory.coverage {
+            let length = instruction_length(&self.memory.all[pc as usize..]).unwrap_or(2);
+            coverage.mark(pc as usize, length, Access::Executed);
+        }
+        let depth = self.memory.stack.len();
+        instruction.execute(
+            &mut self.memory,
+            &mut self.register,
+            &mut self.graphic,
+            &mut self.keypad,
+            &self.quirks,
+        )?;
+        if let Some(profiler) = &mut self.profiler {
+            let new_depth = self.memory.stack.len();
+            profiler.record(pc, instruction.name(), depth, new_depth, self.register.pc);
+        }
+        Ok(())
+    }
+
+    /// Undo the last instruction recorded in the journal, including any frame ended after it.
+    /// Returns false if the journal is disabled or empty.
+    pub fn step_back(&mut self) -> bool {
+        match self.journal.take() {
+            Some(mut journal) => {
+                let undone = journal.undo(self);
+                self.journal = Some(journal);
+                undone
+            }
+            None => false,
+        }
+    }
+
+    pub fn run_cycles(&mut self, cycles: usize) -> Result<(), EmulationError> {
+        for _ in 0..cycles {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Run one 60 Hz frame worth of instructions, then end the frame.
+    pub fn run_frame(&mut self) -> Result<(), EmulationError> {
+        self.run_cycles(self.cycles_per_frame)?;
+        self.end_frame();
+        Ok(())
+    }
+
+    /// Tick the delay and sound timers once, count down tapped keys and take a rewind snapshot
+    /// when one is due.
+    /// Only needed by callers stepping through a frame instruction by instruction.
+    pub fn end_frame(&mut self) {
+        self.register.tick_timers();
+        self.keypad.tick();
+        let due = self.rewind_buffer.as_mut().is_some_and(RewindBuffer::tick);
+        if due {
+            let state = self.save_state();
+            if let Some(buffer) = &mut self.rewind_buffer {
+                buffer.push(state);
+            }
+        }
+    }
+
+    /// Go back about `frames` frames, to the nearest earlier snapshot of the rewind buffer.
+    /// Snapshots newer than the restored one are dropped, while the restored one is kept so
+    /// that it can be returned to again. Returns the number of frames gone back, 0 if the buffer
+    /// is disabled or holds nothing older than the current frame.
+    pub fn rewind(&mut self, frames: u32) -> Result<u32, EmulationError> {
+        let mut buffer = match self.rewind_buffer.take() {
+            Some(buffer) => buffer,
+            None => return Ok(0),
+        };
+        let since = buffer.frames_since_snapshot();
+        let mut snapshots = frames.div_ceil(buffer.interval).max(1) as usize;
+        if since > 0 {
+            // the newest snapshot already lies in the past
+            snapshots -= 1;
+        }
+        let dropped = buffer.discard(snapshots) as u32;
+        let result = match buffer.latest() {
+            Some(state) if since > 0 || dropped > 0 => self.load_state(state),
+            _ => Ok(()),
+        };
+        self.rewind_buffer = Some(buffer);
+        result?;
+        Ok(since
+            + dropped
+                * self
+                    .rewind_buffer
+                    .as_ref()
+                    .map_or(1, |buffer| buffer.interval))
+    }
+
+    /// Run every frame which has become due on the clock behind `frames`.
+    pub fn run_due_frames<C: Clock>(
+        &mut self,
+        frames: &mut FrameCounter<C>,
+    ) -> Result<(), EmulationError> {
+        for _ in 0..frames.due() {
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.register.halted
+    }
+
+    /// Whether the program has come to an end, either by halting with 00FD or by jumping to the
+    /// jump it is at, which is how most programs stop. Jumps only reach the first 4 KiB, so jumps
+    /// above them always go elsewhere.
+    pub fn is_in_halt_loop(&self) -> bool {
+        let pc = self.register.pc;
+        let jumps_to_itself = match self.memory.read(pc) {
+            Ok([high, low]) if pc <= 0xFFF => u16::from_be_bytes([high, low]) == 0x1000 | pc,
+            _ => false,
+        };
+        self.is_halted() || jumps_to_itself
+    }
+
+    /// Serialize the complete machine state into the versioned save state format.
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save(self)
+    }
+
+    /// Replace the machine state with a save state written by `save_state`.
+    /// The number of cycles per frame, the watchpoints, the coverage, the trace, the profiler and
+    /// the rewind buffer are kept.
+    /// The journal is kept but emptied, since it cannot undo the jump to another state. On error the
+    /// machine is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmulationError> {
+        let mut machine = state::restore(data)?;
+        machine.cycles_per_frame = self.cycles_per_frame;
+        machine.trace = self.trace.take();
+        machine.profiler = self.profiler.take();
+        machine.rewind_buffer = self.rewind_buffer.take();
+        machine.journal = self.journal.take();
+        if let Some(journal) = &mut machine.journal {
+            journal.clear();
+        }
+        machine.memory.watches = std::mem::take(&mut self.memory.watches);
+        machine.memory.coverage = self.memory.coverage.take();
+        machine.register.watches = std::mem::take(&mut self.register.watches);
+        *self = machine;
+        Ok(())
+    }
+
+    /// Add a watchpoint to the memory or the registers, depending on what it watches.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        match watchpoint {
+            Watchpoint::Read { .. } | Watchpoint::Write { .. } => {
+                self.memory.watches.points.push(watchpoint)
+            }
+            Watchpoint::Register(_) | Watchpoint::Index { .. } => {
+                self.register.watches.points.push(watchpoint)
+            }
+        }
+    }
+
+    /// All watchpoints, memory watchpoints first.
+    pub fn watchpoints(&self) -> Vec<Watchpoint> {
+        let memory = self.memory.watches.points.iter();
+        memory
+            .chain(&self.register.watches.points)
+            .copied()
+            .collect()
+    }
+
+    /// Remove `watchpoint`. Returns whether it was set.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let mut removed = false;
+        for points in [
+            &mut self.memory.watches.points,
+            &mut self.register.watches.points,
+        ] {
+            let before = points.len();
+            points.retain(|point| *point != watchpoint);
+            removed |= points.len() != before;
+        }
+        removed
+    }
+
+    /// Return the watchpoint hits recorded since the last call, memory accesses first.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        let mut hits = self.memory.watches.take_hits();
+        hits.extend(self.register.watches.take_hits());
+        hits
+    }
+
+    /// Whether Fx0A is waiting for a key.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.register.waiting_for_key.is_some()
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.keypad.press(key);
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.keypad.release(key);
+    }
+
+    /// Hold exactly the keys of the bit mask `held`, bit n for key n.
+    pub fn set_keys(&mut self, held: u16) {
+        self.keypad.set_held(held);
+    }
+
+    /// Press `key` and release it automatically after `frames` frames.
+    pub fn tap_key(&mut self, key: u8, frames: u32) {
+        self.keypad.tap(key, frames);
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::time::Duration;
+
+    #[test]
+    fn test_step() {
+        let mut machine = Machine::new();
+        // 6a2b: LD VA, 0x2b
+        machine.memory.all[0x200] = 0x6a;
+        machine.memory.all[0x201] = 0x2b;
+        machine.step().unwrap();
+        assert_eq!(machine.register.v[0xa], 0x2b);
+        assert_eq!(machine.register.pc, 0x202);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut machine = Machine::new();
+        // 1200: JP 0x200
+        machine.memory.all[0x200] = 0x12;
+        machine.memory.all[0x201] = 0x00;
+        machine.register.delay_timer = 2;
+        machine.register.sound_timer = 1;
+        machine.run_frame().unwrap();
+        assert_eq!(machine.register.delay_timer, 1);
+        assert_eq!(machine.register.sound_timer, 0);
+        assert_eq!(machine.register.pc, 0x200);
+    }
+
+    #[test]
+    fn test_wait_for_key() {
+        let mut machine = Machine::new();
+        // f30a: LD V3, K
+        machine.memory.all[0x200] = 0xf3;
+        machine.memory.all[0x201] = 0x0a;
+        // 6a2b: LD VA, 0x2b
+        machine.memory.all[0x202] = 0x6a;
+        machine.memory.all[0x203] = 0x2b;
+        machine.register.delay_timer = 5;
+        machine.run_frame().unwrap();
+        assert!(machine.is_waiting_for_key());
+        assert_eq!(machine.register.delay_timer, 4);
+        machine.press_key(0x7);
+        machine.run_frame().unwrap();
+        assert!(machine.is_waiting_for_key());
+        assert_eq!(machine.register.delay_timer, 3);
+        machine.release_key(0x7);
+        machine.step().unwrap();
+        assert!(!machine.is_waiting_for_key());
+        assert_eq!(machine.register.v[0x3], 0x7);
+        machine.step().unwrap();
+        assert_eq!(machine.register.v[0xa], 0x2b);
+        assert_eq!(machine.register.pc, 0x204);
+    }
+
+    #[test]
+    fn test_wait_for_key_held_before() {
+        let mut machine = Machine::new();
+        // f30a: LD V3, K
+        machine.memory.all[0x200] = 0xf3;
+        machine.memory.all[0x201] = 0x0a;
+        machine.press_key(0x7);
+        machine.step().unwrap();
+        machine.release_key(0x7);
+        machine.run_frame().unwrap();
+        assert!(machine.is_waiting_for_key());
+        machine.press_key(0x7);
+        machine.release_key(0x7);
+        machine.step().unwrap();
+        assert!(!machine.is_waiting_for_key());
+        assert_eq!(machine.register.v[0x3], 0x7);
+    }
+
+    #[test]
+    fn test_halt_loop() {
+        let mut machine = Machine::new();
+        // 200: JP 0x202, 202: JP 0x202
+        machine.memory.all[0x200..0x204].copy_from_slice(&[0x12, 0x02, 0x12, 0x02]);
+        assert!(!machine.is_in_halt_loop());
+        machine.step().unwrap();
+        assert!(machine.is_in_halt_loop());
+        machine.register.pc = 0x1000;
+        assert!(!machine.is_in_halt_loop());
+        machine.register.halted = true;
+        assert!(machine.is_in_halt_loop());
+
+        let mut machine = Machine::with_profile(Profile::XoChip);
+        // 1200: JP 0x200
+        machine.memory.all[0x1200..0x1202].copy_from_slice(&[0x12, 0x00]);
+        machine.register.pc = 0x1200;
+        assert!(!machine.is_in_halt_loop());
+    }
+
+    #[test]
+    fn test_tap_key() {
+        let mut machine = Machine::new();
+        // 1200: JP 0x200
+        machine.memory.all[0x200] = 0x12;
+        machine.memory.all[0x201] = 0x00;
+        machine.tap_key(0x7, 2);
+        machine.run_frame().unwrap();
+        assert!(machine.keypad.is_pressed(0x7));
+        machine.run_frame().unwrap();
+        assert!(!machine.keypad.is_pressed(0x7));
+    }
+
+    #[test]
+    fn test_load_state() {
+        let mut machine = Machine::new();
+        // 6a2b: LD VA, 0x2b
+        machine.memory.all[0x200] = 0x6a;
+        machine.memory.all[0x201] = 0x2b;
+        let state = machine.save_state();
+        machine.step().unwrap();
+        machine.cycles_per_frame = 20;
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.register.v[0xa], 0);
+        assert_eq!(machine.register.pc, 0x200);
+        assert_eq!(machine.cycles_per_frame, 20);
+        assert!(machine.load_state(&state[..10]).is_err());
+        assert_eq!(machine.register.pc, 0x200);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut machine = Machine::new();
+        // f233: LD B, V2
+        machine.memory.all[0x200] = 0xf2;
+        machine.memory.all[0x201] = 0x33;
+        // a300: LD I, 0x300
+        machine.memory.all[0x202] = 0xa3;
+        machine.memory.all[0x203] = 0x00;
+        // f265: LD V2, [I]
+        machine.memory.all[0x204] = 0xf2;
+        machine.memory.all[0x205] = 0x65;
+        machine.register.i = 0x300;
+        machine.register.v[0x2] = 123;
+        machine.add_watchpoint(Watchpoint::Write {
+            start: 0x302,
+            end: 0x302,
+        });
+        machine.add_watchpoint(Watchpoint::Register(0x1));
+        let state = machine.save_state();
+        machine.step().unwrap();
+        assert_eq!(
+            machine.take_watch_hits(),
+            vec![WatchHit::Write {
+                address: 0x300,
+                length: 3
+            }]
+        );
+        machine.step().unwrap();
+        assert!(machine.take_watch_hits().is_empty());
+        machine.step().unwrap();
+        assert_eq!(
+            machine.take_watch_hits(),
+            vec![WatchHit::Register {
+                vx: 0x1,
+                old: 0,
+                new: 2
+            }]
+        );
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.watchpoints().len(), 2);
+        assert!(machine.remove_watchpoint(Watchpoint::Register(0x1)));
+        assert!(!machine.remove_watchpoint(Watchpoint::Register(0x1)));
+        machine.run_cycles(3).unwrap();
+        assert_eq!(machine.take_watch_hits().len(), 1);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut machine = Machine::new();
+        // 7101: ADD V1, 0x01
+        machine.memory.all[0x200] = 0x71;
+        machine.memory.all[0x201] = 0x01;
+        // 1200: JP 0x200
+        machine.memory.all[0x202] = 0x12;
+        machine.memory.all[0x203] = 0x00;
+        assert_eq!(machine.rewind(1).unwrap(), 0);
+        machine.rewind_buffer = Some(RewindBuffer::new(2, 0x10000));
+        for _ in 0..10 {
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(machine.register.v[1], 40);
+        assert_eq!(machine.rewind(4).unwrap(), 4);
+        assert_eq!(machine.register.v[1], 24);
+        machine.run_frame().unwrap();
+        assert_eq!(machine.rewind(1).unwrap(), 1);
+        assert_eq!(machine.register.v[1], 24);
+        assert_eq!(machine.rewind(100).unwrap(), 4);
+        assert_eq!(machine.register.v[1], 8);
+        assert_eq!(machine.rewind(2).unwrap(), 0);
+        assert!(machine.rewind_buffer.is_some());
+    }
+
+    #[test]
+    fn test_rewind_keeps_profiler() {
+        let mut machine = Machine::new();
+        // 7101: ADD V1, 0x01, 1200: JP 0x200
+        machine.memory.all[0x200..0x204].copy_from_slice(&[0x71, 0x01, 0x12, 0x00]);
+        machine.rewind_buffer = Some(RewindBuffer::new(2, 0x10000));
+        machine.profiler = Some(Profiler::new());
+        for _ in 0..4 {
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(machine.rewind(2).unwrap(), 2);
+        machine.run_frame().unwrap();
+        let profiler = machine.profiler.as_ref().unwrap();
+        assert_eq!(profiler.total, 5 * CYCLES_PER_FRAME as u64);
+        assert_eq!(profiler.addresses[&0x200], 5 * CYCLES_PER_FRAME as u64 / 2);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut machine = Machine::new();
+        // 00e0: CLS, a300: LD I, 0x300, f233: LD B, V2, d015: DRW V0, V1, 5, 220c: CALL 0x20c,
+        // 00c2: SCD 2, 00ff: HIGH, 0000: SYS 0x000
+        let program = [
+            0x00, 0xe0, 0xa3, 0x00, 0xf2, 0x33, 0xd0, 0x15, 0x22, 0x0c, 0x00, 0x00, 0x00, 0xc2,
+            0x00, 0xff,
+        ];
+        machine.memory.all[0x200..0x210].copy_from_slice(&program);
+        machine.memory.all[0x300] = 0xff;
+        machine.register.v[0x2] = 123;
+        machine.graphic.gfx[100] = 1;
+        machine.register.delay_timer = 10;
+        machine.journal = Some(Journal::default());
+        let memory = machine.memory.all.clone();
+        let gfx = machine.graphic.gfx.clone();
+        assert!(!machine.step_back());
+
+        machine.run_frame().unwrap();
+        assert!(machine.graphic.is_hires());
+        assert_eq!(machine.register.delay_timer, 9);
+        assert_eq!(machine.journal.as_ref().unwrap().len(), 8);
+        for _ in 0..8 {
+            assert!(machine.step_back());
+        }
+        assert!(!machine.step_back());
+        assert_eq!(machine.register.pc, 0x200);
+        assert_eq!(machine.register.i, 0);
+        assert_eq!(machine.register.delay_timer, 10);
+        assert!(machine.memory.stack.is_empty());
+        assert!(machine.memory.all == memory);
+        assert!(!machine.graphic.is_hires());
+        assert_eq!(machine.graphic.gfx, gfx);
+    }
+
+    #[test]
+    fn test_step_unknown_opcode() {
+        let mut machine = Machine::new();
+        machine.memory.all[0x200] = 0xe0;
+        machine.memory.all[0x201] = 0x00;
+        let result = machine.step();
+        assert!(matches!(
+            result,
+            Err(EmulationError::UnknownOpcode {
+                address: 0x200,
+                opcode: 0xe000
+            })
+        ));
+        assert_eq!(machine.register.pc, 0x200);
+    }
+
+    #[test]
+    fn test_with_profile() {
+        let machine = Machine::with_profile(Profile::XoChip);
+        assert_eq!(machine.memory.all.len(), XO_MEMORY_SIZE);
+        assert_eq!(machine.quirks, Quirks::XO_CHIP);
+    }
+
+    #[test]
+    fn test_run_due_frames() {
+        let mut machine = Machine::new();
+        // 1200: JP 0x200
+        machine.memory.all[0x200] = 0x12;
+        machine.memory.all[0x201] = 0x00;
+        machine.register.delay_timer = 10;
+        let clock = ManualClock::new();
+        let mut frames = FrameCounter::new(&clock);
+        machine.run_due_frames(&mut frames).unwrap();
+        assert_eq!(machine.register.delay_timer, 10);
+        clock.advance(Duration::from_millis(50));
+        machine.run_due_frames(&mut frames).unwrap();
+        assert_eq!(machine.register.delay_timer, 7);
+    }
+}
@@ -1,3 +1,6 @@
+use super::coverage::{Access, Coverage};
+use super::error::EmulationError;
+use super::watch::Watches;
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::prelude::*;
@@ -7,6 +10,19 @@ pub const FONT_BASE: usize = 0;
 
 pub const FONT_LENGTH: usize = 5;
 
+pub const BIG_FONT_BASE: usize = FONT_BASE + FONTS.len();
+
+pub const BIG_FONT_LENGTH: usize = 10;
+
+pub const PROGRAM_START: usize = 0x200;
+
+pub const MEMORY_SIZE: usize = 0x1000;
+
+/// Size of the extended XO-CHIP address space.
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+
+pub const STACK_DEPTH: usize = 16;
+
 const FONTS: [u8; 80] = [
     0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -26,39 +42,160 @@ const FONTS: [u8; 80] = [
     0xf0, 0x80, 0xf0, 0x80, 0x80, // F
 ];
 
+const BIG_FONTS: [u8; 160] = [
+    0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff, // 1
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // 2
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 3
+    0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 5
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 6
+    0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18, // 7
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 8
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 9
+    0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // A
+    0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, // B
+    0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c, // C
+    0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, // D
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // E
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0, // F
+];
+
 // 0x000 - 0x1FF reserved by interpreter
 // 0xEA0 - 0xEFF reserved for call stack (16 layer)
 // 0xF00 - 0xFFF reserved for display refresh
 pub struct Memory {
-    pub all: [u8; 4096],
+    pub all: Vec<u8>,
     pub stack: Vec<u16>,
+    /// Read and write watchpoints checked by `read_bytes` and `write_bytes`.
+    pub watches: Watches,
+    /// Use of every byte by instructions, if enabled.
+    pub coverage: Option<Coverage>,
+    /// Address and previous value of every byte written by `write_bytes`, while a journal entry
+    /// is open.
+    pub(crate) undo: Option<Vec<(usize, u8)>>,
 }
 
 impl Memory {
     pub fn new() -> Self {
-        let mut all = [0; 4096];
-        let mut fonts_copy = FONTS;
-        fonts_copy.swap_with_slice(&mut all[0..80]);
+        Self::with_size(MEMORY_SIZE)
+    }
+
+    /// Create memory of `size` bytes, e.g. `XO_MEMORY_SIZE` for XO-CHIP programs.
+    pub fn with_size(size: usize) -> Self {
+        let mut all = vec![0; size];
+        all[FONT_BASE..FONT_BASE + FONTS.len()].copy_from_slice(&FONTS);
+        all[BIG_FONT_BASE..BIG_FONT_BASE + BIG_FONTS.len()].copy_from_slice(&BIG_FONTS);
         Memory {
-            all: [0; 4096],
+            all,
             stack: Vec::new(),
+            watches: Watches::new(),
+            coverage: None,
+            undo: None,
         }
     }
 
-    pub fn load<P: AsRef<Path>>(&mut self, rom: P) {
-        let mut fd = File::open(rom).unwrap();
+    pub fn load<P: AsRef<Path>>(&mut self, rom: P) -> Result<(), EmulationError> {
+        let mut fd = File::open(rom)?;
         let mut rom_data = Vec::new();
-        fd.read_to_end(&mut rom_data).unwrap();
+        fd.read_to_end(&mut rom_data)?;
+        self.load_bytes(&rom_data)
+    }
 
-        let start = 0x200;
+    /// Copy the ROM `rom_data` to the start of the program area.
+    pub fn load_bytes(&mut self, rom_data: &[u8]) -> Result<(), EmulationError> {
+        let start = PROGRAM_START;
+        let capacity = self.all.len() - start;
+        if rom_data.len() > capacity {
+            return Err(EmulationError::RomTooLarge {
+                size: rom_data.len(),
+                capacity,
+            });
+        }
         let end = start + rom_data.len();
 
-        rom_data.swap_with_slice(&mut self.all[start..end]);
+        self.all[start..end].copy_from_slice(rom_data);
+        Ok(())
     }
 
-    pub fn read(&self, program_counter: u16) -> [u8; 2] {
-        let s = program_counter as usize;
-        let e = s + 2;
-        <[u8; 2]>::try_from(&self.all[s..e]).unwrap()
+    pub fn read(&self, program_counter: u16) -> Result<[u8; 2], EmulationError> {
+        let bytes = self.bytes(program_counter as usize, 2)?;
+        Ok(<[u8; 2]>::try_from(bytes).unwrap())
+    }
+
+    /// Borrow `length` bytes of memory starting at `address`.
+    pub fn bytes(&self, address: usize, length: usize) -> Result<&[u8], EmulationError> {
+        match self.all.get(address..address + length) {
+            Some(bytes) => Ok(bytes),
+            None => Err(EmulationError::MemoryOutOfRange { address, length }),
+        }
+    }
+
+    /// Borrow `length` bytes of memory starting at `address` on behalf of an instruction.
+    /// Unlike `bytes` the access is checked against the read watchpoints.
+    pub fn read_bytes(&mut self, address: usize, length: usize) -> Result<&[u8], EmulationError> {
+        self.read_as(address, length, Access::Read)
+    }
+
+    /// Borrow `length` bytes of sprite data starting at `address`, like `read_bytes`.
+    pub fn read_sprite(&mut self, address: usize, length: usize) -> Result<&[u8], EmulationError> {
+        self.read_as(address, length, Access::Sprite)
+    }
+
+    fn read_as(
+        &mut self,
+        address: usize,
+        length: usize,
+        access: Access,
+    ) -> Result<&[u8], EmulationError> {
+        self.bytes(address, length)?;
+        self.watches.read(address, length);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.mark(address, length, access);
+        }
+        self.bytes(address, length)
+    }
+
+    /// Copy `data` into memory starting at `address`, checked against the write watchpoints.
+    pub fn write_bytes(&mut self, address: usize, data: &[u8]) -> Result<(), EmulationError> {
+        let length = data.len();
+        let bytes = match self.all.get_mut(address..address + length) {
+            Some(bytes) => bytes,
+            None => return Err(EmulationError::MemoryOutOfRange { address, length }),
+        };
+        if let Some(undo) = &mut self.undo {
+            undo.extend(
+                bytes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &byte)| (address + i, byte)),
+            );
+        }
+        bytes.copy_from_slice(data);
+        self.watches.write(address, length);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.mark(address, length, Access::Written);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fonts() {
+        for memory in &[Memory::new(), Memory::with_size(XO_MEMORY_SIZE)] {
+            let font = &memory.all[FONT_BASE..FONT_BASE + FONTS.len()];
+            assert_eq!(font, &FONTS[..]);
+            let big_font = &memory.all[BIG_FONT_BASE..BIG_FONT_BASE + BIG_FONTS.len()];
+            assert_eq!(big_font, &BIG_FONTS[..]);
+        }
+        // character A
+        assert_eq!(
+            &Memory::new().all[FONT_BASE + 0xA * FONT_LENGTH..][..FONT_LENGTH],
+            &[0xf0, 0x90, 0xf0, 0x90, 0x90]
+        );
     }
 }
@@ -0,0 +1,361 @@
+use super::graphic::{Graphic, PLANE_MASK};
+use super::machine::Machine;
+use super::quirks::{Profile, Quirks};
+use super::random::Random;
+use super::screenshot::crc32;
+use std::fmt;
+use std::iter;
+use std::str::FromStr;
+
+/// Version of the movie format written by this build.
+pub const MOVIE_VERSION: u32 = 1;
+/// Most frames a movie may have when read, a day at 60 frames per second.
+const MAX_FRAMES: usize = 60 * 60 * 60 * 24;
+
+/// Recording of a play session: everything needed to start the same machine again, and the keys
+/// held in every frame. Input is sampled once per frame, before the frame runs, and the timers
+/// tick once at its end, so replaying a movie frame by frame gives exactly the frames that were
+/// recorded. Keys pressed and released again between two frames are not seen.
+///
+/// The text form starts with the settings, checksums in hex, and lists the keys after `frames`
+/// as hex bit masks, bit n for key n, each followed by the number of frames they were held for:
+///
+/// ```text
+/// # chip-8 movie
+/// version 1
+/// rom 5D0C8E1F
+/// profile schip
+/// quirk wrap_sprites on
+/// cycles 8
+/// seed 2545F491
+/// screen 0BA7E2C4
+/// frames
+/// 0000 120
+/// 0020 30
+/// ```
+///
+/// `quirk` lines give the quirks that differ from the profile. `screen` is the checksum of the
+/// last frame, used to detect replays which went differently.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    /// CRC-32 of the ROM.
+    pub rom_crc: u32,
+    pub profile: Profile,
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    /// Initial state of the random number generator.
+    pub seed: u32,
+    /// Keys held in every frame.
+    pub keys: Vec<u16>,
+    /// CRC-32 of the screen after the last frame, if known.
+    pub screen_crc: Option<u32>,
+}
+
+impl Movie {
+    /// Start recording a session of `rom` on `machine`, which has not run yet. `profile` is the
+    /// one the quirks of the machine are based on.
+    pub fn new(rom: &[u8], profile: Profile, machine: &Machine) -> Self {
+        Movie {
+            rom_crc: crc32(rom),
+            profile,
+            quirks: machine.quirks,
+            cycles_per_frame: machine.cycles_per_frame,
+            seed: machine.register.random.state,
+            keys: Vec::new(),
+            screen_crc: None,
+        }
+    }
+
+    /// Number of recorded frames.
+    pub fn frames(&self) -> u64 {
+        self.keys.len() as u64
+    }
+
+    /// Machine in the state the recording started from, with `rom` loaded.
+    /// Fails if `rom` is not the recorded one.
+    pub fn machine(&self, rom: &[u8]) -> Result<Machine, String> {
+        let crc = crc32(rom);
+        if crc != self.rom_crc {
+            return Err(format!(
+                "ROM checksum {:08X} differs from the recorded {:08X}",
+                crc, self.rom_crc
+            ));
+        }
+        let mut machine = Machine::with_profile(self.profile);
+        machine.quirks = self.quirks;
+        machine.cycles_per_frame = self.cycles_per_frame;
+        machine.register.random = Random::with_seed(self.seed);
+        machine
+            .memory
+            .load_bytes(rom)
+            .map_err(|err| err.to_string())?;
+        Ok(machine)
+    }
+
+    /// Record the keys `machine` holds as those of the next frame, before it runs.
+    pub fn record(&mut self, machine: &Machine) {
+        self.keys.push(machine.keypad.held());
+    }
+
+    /// Hold the keys of `frame` on `machine`. Frames past the end leave the keys as they are.
+    pub fn apply(&self, frame: u64, machine: &mut Machine) {
+        if let Some(&held) = self.keys.get(frame as usize) {
+            machine.set_keys(held);
+        }
+    }
+
+    /// Remember `graphic` as the screen the recording ends with.
+    pub fn set_screen(&mut self, graphic: &Graphic) {
+        self.screen_crc = Some(screen_crc(graphic));
+    }
+
+    /// Whether `graphic` shows the screen the recording ended with. Movies without a screen
+    /// checksum match any screen.
+    pub fn matches_screen(&self, graphic: &Graphic) -> bool {
+        self.screen_crc.is_none_or(|crc| crc == screen_crc(graphic))
+    }
+}
+
+fn screen_crc(graphic: &Graphic) -> u32 {
+    let pixels: Vec<u8> = graphic.gfx.iter().map(|pixel| pixel & PLANE_MASK).collect();
+    crc32(&pixels)
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# chip-8 movie")?;
+        writeln!(f, "version {}", MOVIE_VERSION)?;
+        writeln!(f, "rom {:08X}", self.rom_crc)?;
+        writeln!(f, "profile {}", self.profile)?;
+        let defaults = self.profile.quirks();
+        for name in Quirks::NAMES.iter() {
+            let enabled = self.quirks.get(name);
+            if enabled != defaults.get(name) {
+                let value = if enabled == Some(true) { "on" } else { "off" };
+                writeln!(f, "quirk {} {}", name, value)?;
+            }
+        }
+        writeln!(f, "cycles {}", self.cycles_per_frame)?;
+        writeln!(f, "seed {:08X}", self.seed)?;
+        if let Some(crc) = self.screen_crc {
+            writeln!(f, "screen {:08X}", crc)?;
+        }
+        writeln!(f, "frames")?;
+        let mut index = 0;
+        while index < self.keys.len() {
+            let held = self.keys[index];
+            let count = self.keys[index..]
+                .iter()
+                .take_while(|&&keys| keys == held)
+                .count();
+            writeln!(f, "{:04X} {}", held, count)?;
+            index += count;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut version = None;
+        let mut rom_crc = None;
+        let mut profile = None;
+        let mut overrides = Vec::new();
+        let mut cycles_per_frame = None;
+        let mut seed = None;
+        let mut screen_crc = None;
+        let mut keys = Vec::new();
+        let mut in_frames = false;
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: &str| format!("line {}: {}", index + 1, what);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let hex = |field: usize| {
+                let value = fields
+                    .get(field)
+                    .map(|value| u32::from_str_radix(value, 16));
+                match value {
+                    Some(Ok(value)) if fields.len() == field + 1 => Ok(value),
+                    _ => Err(invalid("expected a hex number")),
+                }
+            };
+            if in_frames {
+                let held = match u16::from_str_radix(fields[0], 16) {
+                    Ok(held) if fields[0].len() <= 4 => held,
+                    _ => return Err(invalid("invalid key mask")),
+                };
+                let count: usize = match fields[1..] {
+                    [] => 1,
+                    [count] => count.parse().map_err(|_| invalid("invalid frame count"))?,
+                    _ => return Err(invalid("expected a key mask and a frame count")),
+                };
+                if count > MAX_FRAMES - keys.len() {
+                    return Err(invalid("movie is longer than a day"));
+                }
+                keys.extend(iter::repeat_n(held, count));
+                continue;
+            }
+            match fields[0] {
+                "version" => match fields[1..] {
+                    [number] => {
+                        let number: u32 = number.parse().map_err(|_| invalid("invalid version"))?;
+                        version = Some(number);
+                    }
+                    _ => return Err(invalid("expected a version number")),
+                },
+                "rom" => rom_crc = Some(hex(1)?),
+                "profile" => match fields[1..] {
+                    [name] => profile = Some(name.parse::<Profile>().map_err(|e| invalid(&e))?),
+                    _ => return Err(invalid("expected a profile name")),
+                },
+                "quirk" => match fields[1..] {
+                    [name, "on"] => overrides.push((name, true)),
+                    [name, "off"] => overrides.push((name, false)),
+                    _ => return Err(invalid("expected a quirk name and on or off")),
+                },
+                "cycles" => match fields[1..] {
+                    [cycles] => {
+                        let cycles = cycles.parse().map_err(|_| invalid("invalid cycle count"))?;
+                        cycles_per_frame = Some(cycles);
+                    }
+                    _ => return Err(invalid("expected a cycle count")),
+                },
+                "seed" => seed = Some(hex(1)?),
+                "screen" => screen_crc = Some(hex(1)?),
+                "frames" if fields.len() == 1 => in_frames = true,
+                _ => return Err(invalid("unknown setting")),
+            }
+        }
+
+        match version {
+            Some(version) if version == MOVIE_VERSION => {}
+            Some(version) => return Err(format!("unsupported movie version {}", version)),
+            None => return Err("missing version".to_string()),
+        }
+        let profile = profile.ok_or("missing profile")?;
+        let mut quirks = profile.quirks();
+        for (name, enabled) in overrides {
+            if !quirks.set(name, enabled) {
+                return Err(format!("unknown quirk {}", name));
+            }
+        }
+        if !in_frames {
+            return Err("missing frames".to_string());
+        }
+        Ok(Movie {
+            rom_crc: rom_crc.ok_or("missing ROM checksum")?,
+            profile,
+            quirks,
+            cycles_per_frame: cycles_per_frame.ok_or("missing cycle count")?,
+            seed: seed.ok_or("missing seed")?,
+            keys,
+            screen_crc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::script::KeyScript;
+
+    #[test]
+    fn test_parse() {
+        let mut movie = Movie::new(&[0x12, 0x00], Profile::SuperChip, &Machine::new());
+        movie.profile = Profile::XoChip;
+        movie.quirks = Quirks::XO_CHIP;
+        movie.quirks.reset_vf = true;
+        movie.seed = 0x1234;
+        movie.keys = vec![0, 0, 0, 0x20, 0x20, 0];
+        movie.screen_crc = Some(0xABCD);
+        let text = movie.to_string();
+        assert!(text.contains("\nprofile xochip\nquirk reset_vf on\ncycles 8\nseed 00001234\n"));
+        assert!(text.ends_with("\nscreen 0000ABCD\nframes\n0000 3\n0020 2\n0000 1\n"));
+        assert_eq!(text.parse::<Movie>().unwrap(), movie);
+
+        let without_screen = text.replace("screen 0000ABCD\n", "");
+        assert_eq!(without_screen.parse::<Movie>().unwrap().screen_crc, None);
+        assert!(text.contains("\nversion 1\n"));
+        assert_eq!(
+            text.replace("version 1", "version 10").parse::<Movie>(),
+            Err("unsupported movie version 10".to_string())
+        );
+        assert!(text
+            .replace("version 1", "version 0x1")
+            .parse::<Movie>()
+            .is_err());
+        assert!(text
+            .replace("seed 00001234\n", "")
+            .parse::<Movie>()
+            .is_err());
+        assert!(text.replace("reset_vf", "reset").parse::<Movie>().is_err());
+        assert!((text.clone() + "10000 1\n").parse::<Movie>().is_err());
+        assert_eq!(
+            (text.clone() + "0000 18446744073709551615\n").parse::<Movie>(),
+            Err("line 13: movie is longer than a day".to_string())
+        );
+        let day = format!("{}0000 {}\n", text, MAX_FRAMES - movie.keys.len());
+        assert_eq!(day.parse::<Movie>().unwrap().keys.len(), MAX_FRAMES);
+        assert!((day + "0000 1\n").parse::<Movie>().is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        // draws a random digit every 4 frames, clearing the screen while key 5 is held
+        let rom = assemble(
+            "
+        loop:
+            LD V2, DT
+            SE V2, 0
+            JP loop
+            LD V1, 5
+            SKNP V1
+            CLS
+            RND V0, 0x0F
+            LD F, V0
+            DRW V3, V3, 5
+            ADD V3, 3
+            LD V2, 4
+            LD DT, V2
+            JP loop
+            ",
+        )
+        .unwrap();
+        let script: KeyScript = "20 press 5\n30 release 5\n50 tap 5 3\n".parse().unwrap();
+
+        let mut machine = Machine::new();
+        machine.memory.load_bytes(&rom).unwrap();
+        let mut movie = Movie::new(&rom, Profile::default(), &machine);
+        let mut screens = Vec::new();
+        for frame in 0..80 {
+            script.apply(frame, &mut machine);
+            movie.record(&machine);
+            machine.run_frame().unwrap();
+            screens.push(machine.graphic.gfx.clone());
+        }
+        movie.set_screen(&machine.graphic);
+
+        let movie: Movie = movie.to_string().parse().unwrap();
+        assert_eq!(movie.frames(), 80);
+        assert!(movie.machine(&[0x12, 0x00]).is_err());
+        let mut replay = movie.machine(&rom).unwrap();
+        for frame in 0..movie.frames() {
+            movie.apply(frame, &mut replay);
+            replay.run_frame().unwrap();
+            assert_eq!(
+                replay.graphic.gfx, screens[frame as usize],
+                "frame {}",
+                frame
+            );
+        }
+        assert!(movie.matches_screen(&replay.graphic));
+        replay.graphic.clear();
+        assert!(!movie.matches_screen(&replay.graphic));
+    }
+}
@@ -0,0 +1,188 @@
+use super::disassembler::{mnemonic, Syntax};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Number of addresses listed in the hot spot section of the report.
+const HOT_SPOTS: usize = 20;
+
+/// Instructions executed in and below one subroutine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubroutineCost {
+    pub calls: u64,
+    /// Instructions executed until the subroutine returned, including nested calls.
+    pub inclusive: u64,
+    /// Instructions of the subroutine itself.
+    pub exclusive: u64,
+}
+
+/// Counts executed instructions per instruction type, per address and per subroutine.
+/// Subroutines are followed through the call stack: an instruction growing the stack enters a
+/// subroutine at the new PC and one shrinking it returns.
+#[derive(Default)]
+pub struct Profiler {
+    pub total: u64,
+    /// Steps spent waiting for a key in Fx0A.
+    pub waiting: u64,
+    pub classes: HashMap<&'static str, u64>,
+    pub addresses: HashMap<u16, u64>,
+    pub subroutines: HashMap<u16, SubroutineCost>,
+    /// Instructions executed outside of any subroutine.
+    pub top_level: u64,
+    /// Entry addresses of the subroutines being executed, innermost last.
+    calls: Vec<u16>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the instruction of type `class` executed at `pc`, which changed the depth of the
+    /// call stack from `depth` to `new_depth` and continued at `new_pc`.
+    pub fn record(
+        &mut self,
+        pc: u16,
+        class: &'static str,
+        depth: usize,
+        new_depth: usize,
+        new_pc: u16,
+    ) {
+        self.total += 1;
+        *self.classes.entry(class).or_insert(0) += 1;
+        *self.addresses.entry(pc).or_insert(0) += 1;
+
+        // a loaded save state may have a stack of its own
+        self.calls.truncate(depth);
+        match self.calls.last() {
+            Some(entry) => self.subroutines.entry(*entry).or_default().exclusive += 1,
+            None => self.top_level += 1,
+        }
+        for (i, entry) in self.calls.iter().enumerate() {
+            // recursive calls count once
+            if !self.calls[..i].contains(entry) {
+                self.subroutines.entry(*entry).or_default().inclusive += 1;
+            }
+        }
+
+        if new_depth > depth {
+            self.calls.push(new_pc);
+            self.subroutines.entry(new_pc).or_default().calls += 1;
+        } else if new_depth < depth {
+            self.calls.truncate(new_depth);
+        }
+    }
+
+    /// Count a step spent waiting for a key.
+    pub fn record_wait(&mut self) {
+        self.waiting += 1;
+    }
+
+    /// Report sorted by cost, with instructions disassembled from `memory`.
+    pub fn report(&self, memory: &[u8]) -> String {
+        let percent = |count: u64| count as f64 * 100.0 / self.total.max(1) as f64;
+        let mut out = String::new();
+        writeln!(out, "instructions executed: {}", self.total).unwrap();
+        writeln!(out, "steps waiting for a key: {}", self.waiting).unwrap();
+
+        writeln!(out, "\ninstruction types\n      count       %  type").unwrap();
+        let mut classes: Vec<_> = self.classes.iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (class, &count) in classes {
+            writeln!(out, "{:>11} {:6.2}%  {}", count, percent(count), class).unwrap();
+        }
+
+        writeln!(out, "\nhot spots\n      count       %  address").unwrap();
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (&address, &count) in addresses.into_iter().take(HOT_SPOTS) {
+            let text = memory
+                .get(address as usize..)
+                .and_then(|bytes| mnemonic(bytes, Syntax::Cowgod))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{:>11} {:6.2}%  {:03X}  {}",
+                count,
+                percent(count),
+                address,
+                text
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "\nsubroutines\n      calls   inclusive       %   exclusive       %  address"
+        )
+        .unwrap();
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+        writeln!(
+            out,
+            "{:>11} {:>11} {:6.2}% {:>11} {:6.2}%  top level",
+            "",
+            self.total,
+            percent(self.total),
+            self.top_level,
+            percent(self.top_level)
+        )
+        .unwrap();
+        for (&address, cost) in subroutines {
+            writeln!(
+                out,
+                "{:>11} {:>11} {:6.2}% {:>11} {:6.2}%  {:03X}",
+                cost.calls,
+                cost.inclusive,
+                percent(cost.inclusive),
+                cost.exclusive,
+                percent(cost.exclusive),
+                address
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::machine::Machine;
+
+    #[test]
+    fn test_profile() {
+        let mut machine = Machine::new();
+        // 200: CALL 206, 202: LD V1, 1, 204: JP 204, 206: CALL 20a, 208: RET, 20a: ADD V2, 1,
+        // 20c: RET
+        let program = [
+            0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x22, 0x0a, 0x00, 0xee, 0x72, 0x01, 0x00, 0xee,
+        ];
+        machine.memory.all[0x200..0x20e].copy_from_slice(&program);
+        machine.profiler = Some(Profiler::new());
+        machine.run_cycles(10).unwrap();
+
+        let profiler = machine.profiler.as_ref().unwrap();
+        assert_eq!(profiler.total, 10);
+        assert_eq!(profiler.classes["Opcode0x2nnn"], 2);
+        assert_eq!(profiler.classes["Opcode0x1nnn"], 4);
+        assert_eq!(profiler.addresses[&0x204], 4);
+        assert_eq!(profiler.top_level, 6);
+        let expected = SubroutineCost {
+            calls: 1,
+            inclusive: 4,
+            exclusive: 2,
+        };
+        assert_eq!(profiler.subroutines[&0x206], expected);
+        let expected = SubroutineCost {
+            calls: 1,
+            inclusive: 2,
+            exclusive: 2,
+        };
+        assert_eq!(profiler.subroutines[&0x20a], expected);
+
+        let report = profiler.report(&machine.memory.all);
+        assert!(report.contains("          4  40.00%  Opcode0x1nnn\n"));
+        assert!(report.contains("          4  40.00%  204  JP 0x204\n"));
+        assert!(report.contains("          1           4  40.00%           2  20.00%  206\n"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviour differences between CHIP-8 interpreters.
+/// Programs were written against one specific interpreter and may only run correctly when the
+/// emulator reproduces its behaviour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    /// 8xy6 and 8xyE shift Vx in place instead of storing the shifted Vy into Vx.
+    pub shift_in_place: bool,
+    /// Fx55 and Fx65 leave I pointing past the last register stored or loaded.
+    pub increment_i: bool,
+    /// Bnnn jumps to xnn + Vx instead of nnn + V0.
+    pub jump_with_vx: bool,
+    /// 8xy1, 8xy2 and 8xy3 reset VF to 0.
+    pub reset_vf: bool,
+    /// Dxyn wraps pixels drawn past the edge of the screen around to the opposite side instead of
+    /// clipping them.
+    pub wrap_sprites: bool,
+    /// Dxyn sets VF to the number of sprite rows which collided in high resolution mode instead
+    /// of just 1.
+    pub count_collision_rows: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_in_place: false,
+        increment_i: true,
+        jump_with_vx: false,
+        reset_vf: true,
+        wrap_sprites: false,
+        count_collision_rows: false,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift_in_place: true,
+        increment_i: false,
+        jump_with_vx: true,
+        reset_vf: false,
+        wrap_sprites: false,
+        count_collision_rows: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_in_place: true,
+        increment_i: false,
+        jump_with_vx: true,
+        reset_vf: false,
+        wrap_sprites: false,
+        count_collision_rows: true,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_in_place: false,
+        increment_i: true,
+        jump_with_vx: false,
+        reset_vf: false,
+        wrap_sprites: true,
+        count_collision_rows: false,
+    };
+
+    /// Names of all flags, as accepted by `set`.
+    pub const NAMES: [&'static str; 6] = [
+        "shift_in_place",
+        "increment_i",
+        "jump_with_vx",
+        "reset_vf",
+        "wrap_sprites",
+        "count_collision_rows",
+    ];
+
+    /// Override a single flag by name, e.g. `shift_in_place`.
+    /// Returns `false` if there is no flag with that name.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match self.flag(name) {
+            Some(flag) => {
+                *flag = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Value of the flag called `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        let mut quirks = *self;
+        quirks.flag(name).map(|flag| *flag)
+    }
+
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift_in_place" => Some(&mut self.shift_in_place),
+            "increment_i" => Some(&mut self.increment_i),
+            "jump_with_vx" => Some(&mut self.jump_with_vx),
+            "reset_vf" => Some(&mut self.reset_vf),
+            "wrap_sprites" => Some(&mut self.wrap_sprites),
+            "count_collision_rows" => Some(&mut self.count_collision_rows),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Profile::default().quirks()
+    }
+}
+
+/// Named interpreter whose quirks are reproduced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    #[default]
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Profile {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::CosmacVip => Quirks::COSMAC_VIP,
+            Profile::Chip48 => Quirks::CHIP_48,
+            Profile::SuperChip => Quirks::SUPER_CHIP,
+            Profile::XoChip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Profile::CosmacVip => "vip",
+            Profile::Chip48 => "chip48",
+            Profile::SuperChip => "schip",
+            Profile::XoChip => "xochip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(Profile::CosmacVip),
+            "chip48" => Ok(Profile::Chip48),
+            "schip" => Ok(Profile::SuperChip),
+            "xochip" => Ok(Profile::XoChip),
+            _ => Err(format!("unknown profile {}", s)),
+        }
+    }
+}
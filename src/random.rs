@@ -0,0 +1,50 @@
+/// Xorshift random number generator behind Cxkk.
+/// The whole generator is a single word of state, so it can be saved, restored and seeded for
+/// reproducible runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Random {
+    pub state: u32,
+}
+
+impl Random {
+    /// Seed the generator from the thread random number generator.
+    pub fn new() -> Self {
+        Self::with_seed(rand::random())
+    }
+
+    /// A zero seed would only ever produce zeros, so it is replaced with a fixed one.
+    pub fn with_seed(seed: u32) -> Self {
+        let state = if seed == 0 { 0x2545_f491 } else { seed };
+        Random { state }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        (x >> 24) as u8
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_with_seed() {
+        let mut a = Random::with_seed(0x1234);
+        let mut b = Random::with_seed(0x1234);
+        let bytes: Vec<u8> = (0..8).map(|_| a.next_byte()).collect();
+        assert_eq!(bytes, (0..8).map(|_| b.next_byte()).collect::<Vec<_>>());
+        assert_eq!(a, b);
+        assert_ne!(Random::with_seed(0).next_byte(), 0);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+
+/// Default number of frames between two rewind snapshots.
+pub const REWIND_INTERVAL: u32 = 1;
+
+/// Default memory budget of the rewind buffer in bytes.
+pub const REWIND_BUDGET: usize = 8 * 1024 * 1024;
+
+/// Older snapshot stored relative to the next newer one.
+enum Snapshot {
+    /// Run length encoded XOR with the next newer state, which has the same length.
+    Delta(Vec<u8>),
+    /// Run length encoded state, used when the length of the save state changed.
+    Full(Vec<u8>),
+}
+
+impl Snapshot {
+    fn size(&self) -> usize {
+        match self {
+            Snapshot::Delta(data) | Snapshot::Full(data) => data.len(),
+        }
+    }
+}
+
+/// Ring buffer of save states taken every `interval` frames, for running a machine backwards.
+/// The newest state is kept as it is while every older one is stored as the compressed
+/// difference to its successor. Consecutive frames differ in few bytes, so a snapshot usually
+/// takes a few dozen bytes. Once the buffer uses more than `budget` bytes, the oldest snapshots
+/// are dropped.
+pub struct RewindBuffer {
+    pub interval: u32,
+    pub budget: usize,
+    newest: Option<Vec<u8>>,
+    older: VecDeque<Snapshot>,
+    used: usize,
+    /// Frames ended since the newest snapshot.
+    frames: u32,
+}
+
+impl RewindBuffer {
+    pub fn new(interval: u32, budget: usize) -> Self {
+        RewindBuffer {
+            interval: interval.max(1),
+            budget,
+            newest: None,
+            older: VecDeque::new(),
+            used: 0,
+            frames: 0,
+        }
+    }
+
+    /// Number of snapshots held.
+    pub fn len(&self) -> usize {
+        self.older.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes used by the snapshots.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Frames ended since the newest snapshot was taken.
+    pub fn frames_since_snapshot(&self) -> u32 {
+        self.frames
+    }
+
+    /// Count an ended frame. Returns whether a snapshot is due.
+    pub fn tick(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add `state` as the newest snapshot, dropping the oldest ones beyond the budget.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let snapshot = if previous.len() == state.len() {
+                let delta: Vec<u8> = previous.iter().zip(&state).map(|(a, b)| a ^ b).collect();
+                Snapshot::Delta(compress(&delta))
+            } else {
+                Snapshot::Full(compress(&previous))
+            };
+            self.used -= previous.len();
+            self.used += snapshot.size();
+            self.older.push_back(snapshot);
+        }
+        self.used += state.len();
+        self.newest = Some(state);
+        while self.used > self.budget {
+            match self.older.pop_front() {
+                Some(snapshot) => self.used -= snapshot.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// The newest snapshot.
+    pub fn latest(&self) -> Option<&[u8]> {
+        self.newest.as_deref()
+    }
+
+    /// Drop up to `count` of the newest snapshots, always keeping the oldest one.
+    /// Returns the number of snapshots dropped.
+    pub fn discard(&mut self, count: usize) -> usize {
+        let mut dropped = 0;
+        while dropped < count {
+            let (newest, snapshot) = match (self.newest.take(), self.older.pop_back()) {
+                (Some(newest), Some(snapshot)) => (newest, snapshot),
+                (newest, _) => {
+                    self.newest = newest;
+                    break;
+                }
+            };
+            self.used -= newest.len() + snapshot.size();
+            let state = match snapshot {
+                Snapshot::Delta(delta) => decompress(&delta)
+                    .iter()
+                    .zip(&newest)
+                    .map(|(a, b)| a ^ b)
+                    .collect(),
+                Snapshot::Full(state) => decompress(&state),
+            };
+            self.used += state.len();
+            self.newest = Some(state);
+            dropped += 1;
+        }
+        self.frames = 0;
+        dropped
+    }
+}
+
+/// Encode `data` as runs of a zero count byte, a literal count byte and the literal bytes.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let zeros = data[position..]
+            .iter()
+            .take(u8::MAX as usize)
+            .take_while(|&&byte| byte == 0)
+            .count();
+        position += zeros;
+        let literals = data[position..]
+            .iter()
+            .take(u8::MAX as usize)
+            .take_while(|&&byte| byte != 0)
+            .count();
+        out.push(zeros as u8);
+        out.push(literals as u8);
+        out.extend_from_slice(&data[position..position + literals]);
+        position += literals;
+    }
+    out
+}
+
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut position = 0;
+    while position + 1 < data.len() {
+        let zeros = data[position] as usize;
+        let literals = data[position + 1] as usize;
+        position += 2;
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(&data[position..position + literals]);
+        position += literals;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        let mut data = vec![0; 600];
+        data[0] = 1;
+        data[300..305].copy_from_slice(&[1, 2, 0, 3, 4]);
+        data.extend(std::iter::repeat_n(7, 300));
+        let compressed = compress(&data);
+        assert!(compressed.len() < 320);
+        assert_eq!(decompress(&compressed), data);
+        assert!(compress(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_push_and_discard() {
+        let mut buffer = RewindBuffer::new(2, usize::MAX);
+        assert!(!buffer.tick());
+        assert!(buffer.tick());
+        for value in 0..5u8 {
+            buffer.push(vec![value; 100]);
+        }
+        buffer.push(vec![9; 50]);
+        assert_eq!(buffer.len(), 6);
+        assert_eq!(buffer.discard(1), 1);
+        assert_eq!(buffer.latest(), Some(&[4; 100][..]));
+        assert_eq!(buffer.discard(2), 2);
+        assert_eq!(buffer.latest(), Some(&[2; 100][..]));
+        assert_eq!(buffer.discard(10), 2);
+        assert_eq!(buffer.latest(), Some(&[0; 100][..]));
+        assert_eq!(buffer.used(), 100);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut buffer = RewindBuffer::new(1, 1000);
+        for value in 0..100u8 {
+            let mut state = vec![0; 500];
+            state[value as usize] = value;
+            buffer.push(state);
+        }
+        assert!(buffer.used() <= 1000);
+        assert!(buffer.len() > 10);
+        let dropped = buffer.discard(usize::MAX);
+        assert_eq!(buffer.latest().unwrap()[99 - dropped], 99 - dropped as u8);
+    }
+}
@@ -0,0 +1,331 @@
+use super::graphic::{Graphic, PLANE_MASK};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Largest number of zlib stored block bytes.
+const STORED_BLOCK: usize = 0xFFFF;
+
+/// File format of a screenshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// 24 bit colour PNG.
+    Png,
+    /// Black and white binary PBM, with every lit pixel black.
+    Pbm,
+}
+
+impl ImageFormat {
+    /// Format given by the extension of `path`, if it is a known one.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        extension.to_ascii_lowercase().parse().ok()
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Pbm => "pbm",
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "png" => Ok(ImageFormat::Png),
+            "pbm" => Ok(ImageFormat::Pbm),
+            _ => Err(format!("unknown image format {}", name)),
+        }
+    }
+}
+
+/// Colours of the four pixel values: unlit, plane 1, plane 2 and both planes.
+/// The text form lists them as comma separated hex colours, e.g. `000000,ffffff,aaaaaa,555555`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [[u8; 3]; 4],
+}
+
+impl Palette {
+    pub const DEFAULT: Palette = Palette {
+        colors: [
+            [0x00, 0x00, 0x00],
+            [0xff, 0xff, 0xff],
+            [0xaa, 0xaa, 0xaa],
+            [0x55, 0x55, 0x55],
+        ],
+    };
+
+    pub fn background(&self) -> [u8; 3] {
+        self.colors[0]
+    }
+
+    pub fn set_background(&mut self, color: [u8; 3]) {
+        self.colors[0] = color;
+    }
+
+    /// Set the colour of pixels lit in plane 1 only, which are all lit pixels outside of
+    /// XO-CHIP programs.
+    pub fn set_foreground(&mut self, color: [u8; 3]) {
+        self.colors[1] = color;
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::DEFAULT
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let colors: Vec<String> = self
+            .colors
+            .iter()
+            .map(|[r, g, b]| format!("{:02x}{:02x}{:02x}", r, g, b))
+            .collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let colors = text
+            .split(',')
+            .map(parse_color)
+            .collect::<Result<Vec<_>, _>>()?;
+        match colors[..] {
+            [background, plane1, plane2, both] => Ok(Palette {
+                colors: [background, plane1, plane2, both],
+            }),
+            _ => Err(format!("palette {} needs 4 colours", text)),
+        }
+    }
+}
+
+/// Parse a colour written as 6 hex digits, optionally preceded by `#`.
+pub fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let digits = text.trim().trim_start_matches('#');
+    let value = match u32::from_str_radix(digits, 16) {
+        Ok(value) if digits.len() == 6 => value,
+        _ => return Err(format!("invalid colour {}", text)),
+    };
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+/// Settings for writing the framebuffer as an image, with every pixel drawn as a square of
+/// `scale` by `scale` image pixels. High resolution frames make images twice as large as low
+/// resolution ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Screenshot {
+    pub format: ImageFormat,
+    pub scale: usize,
+    pub palette: Palette,
+}
+
+impl Screenshot {
+    pub fn new(format: ImageFormat) -> Self {
+        Screenshot {
+            format,
+            scale: 1,
+            palette: Palette::DEFAULT,
+        }
+    }
+
+    /// The image file contents for `graphic`.
+    pub fn encode(&self, graphic: &Graphic) -> Vec<u8> {
+        match self.format {
+            ImageFormat::Png => self.png(graphic),
+            ImageFormat::Pbm => self.pbm(graphic),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, graphic: &Graphic, path: P) -> io::Result<()> {
+        fs::write(path, self.encode(graphic))
+    }
+
+    fn size(&self, graphic: &Graphic) -> (usize, usize) {
+        let scale = self.scale.max(1);
+        (graphic.width() * scale, graphic.height() * scale)
+    }
+
+    /// Pixel value at image position (x, y).
+    fn pixel(&self, graphic: &Graphic, x: usize, y: usize) -> u8 {
+        let scale = self.scale.max(1);
+        graphic.gfx[(y / scale) * graphic.width() + x / scale] & PLANE_MASK
+    }
+
+    fn pbm(&self, graphic: &Graphic) -> Vec<u8> {
+        let (width, height) = self.size(graphic);
+        let mut out = format!("P4\n{} {}\n", width, height).into_bytes();
+        for y in 0..height {
+            let mut byte = 0;
+            for x in 0..width {
+                if self.pixel(graphic, x, y) != 0 {
+                    byte |= 0x80 >> (x % 8);
+                }
+                if x % 8 == 7 || x + 1 == width {
+                    out.push(byte);
+                    byte = 0;
+                }
+            }
+        }
+        out
+    }
+
+    fn png(&self, graphic: &Graphic) -> Vec<u8> {
+        let (width, height) = self.size(graphic);
+        // every row starts with filter type 0
+        let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+        for y in 0..height {
+            raw.push(0);
+            for x in 0..width {
+                let color = self.palette.colors[self.pixel(graphic, x, y) as usize];
+                raw.extend_from_slice(&color);
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, deflate, no filtering, no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut out, b"IHDR", &header);
+        chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
+
+/// Append a PNG chunk of type `kind` holding `data`.
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap `data` in a zlib stream of uncompressed deflate blocks. Screens are small enough for
+/// the missing compression not to matter.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let length = block.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_palette() {
+        let palette: Palette = "#102030,ffffff,AAAAAA,000001".parse().unwrap();
+        assert_eq!(palette.background(), [0x10, 0x20, 0x30]);
+        assert_eq!(palette.colors[3], [0, 0, 1]);
+        assert_eq!(palette.to_string().parse::<Palette>().unwrap(), palette);
+        assert!("000000,ffffff".parse::<Palette>().is_err());
+        assert!(parse_color("12345").is_err());
+        assert_eq!(ImageFormat::from_path("shot.PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("shot.txt"), None);
+    }
+
+    #[test]
+    fn test_pbm() {
+        let mut graphic = Graphic::new();
+        graphic.set_sprite(0, 0, &[0b1010_0000], false);
+        let mut screenshot = Screenshot::new(ImageFormat::Pbm);
+        screenshot.scale = 2;
+        let pbm = screenshot.encode(&graphic);
+        let header = b"P4\n128 64\n";
+        assert_eq!(&pbm[..header.len()], header);
+        assert_eq!(pbm.len(), header.len() + 16 * 64);
+        assert_eq!(pbm[header.len()], 0b1100_1100);
+        assert_eq!(pbm[header.len() + 16], 0b1100_1100);
+        assert_eq!(pbm[header.len() + 32], 0);
+    }
+
+    #[test]
+    fn test_png() {
+        let mut graphic = Graphic::new();
+        graphic.set_hires(true);
+        graphic.select_planes(0b11);
+        graphic.set_sprite(0, 0, &[0x80, 0xC0], false);
+        let mut screenshot = Screenshot::new(ImageFormat::Png);
+        screenshot.palette.set_foreground([1, 2, 3]);
+        screenshot.palette.set_background([9, 9, 9]);
+        let png = screenshot.encode(&graphic);
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 128, 0, 0, 0, 64]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        // the IDAT chunk holds one stored block with the raw rows
+        let idat = &png[33..];
+        assert_eq!(&idat[4..8], b"IDAT");
+        let raw = &idat[8 + 2 + 5..];
+        // filter byte, then plane 1 and 2 lit, plane 2 lit, background
+        assert_eq!(
+            &raw[..10],
+            &[0, 0x55, 0x55, 0x55, 0xaa, 0xaa, 0xaa, 9, 9, 9]
+        );
+        let row = 1 + 128 * 3;
+        assert_eq!(&raw[row..row + 4], &[0, 9, 9, 9]);
+    }
+}
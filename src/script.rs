@@ -0,0 +1,182 @@
+use super::machine::Machine;
+use std::fmt;
+use std::str::FromStr;
+
+/// Frames a key is held by a `tap` without an explicit length.
+pub const SCRIPT_TAP_FRAMES: u32 = 2;
+
+/// What a scripted key event does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    Press,
+    Release,
+    /// Press the key and release it after the given number of frames.
+    Tap(u32),
+}
+
+/// Key event applied at the start of a frame, counted from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub action: KeyAction,
+}
+
+impl fmt::Display for KeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            KeyAction::Press => write!(f, "{} press {:X}", self.frame, self.key),
+            KeyAction::Release => write!(f, "{} release {:X}", self.frame, self.key),
+            KeyAction::Tap(frames) => write!(f, "{} tap {:X} {}", self.frame, self.key, frames),
+        }
+    }
+}
+
+/// Timeline of key events for running a ROM without a keyboard.
+/// The text form has one event per line, giving the frame, the action and the key in hex, with
+/// an optional length in frames for taps. Blank lines and lines starting with `#` are skipped:
+///
+/// ```text
+/// # start the game, then hold 4 for a second
+/// 10 tap F
+/// 60 press 4
+/// 120 release 4
+/// 130 tap 6 5
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    /// Events ordered by frame.
+    pub events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frame of the last event.
+    pub fn last_frame(&self) -> Option<u64> {
+        self.events.last().map(|event| event.frame)
+    }
+
+    /// Apply the events of `frame` to `machine`.
+    pub fn apply(&self, frame: u64, machine: &mut Machine) {
+        let start = self.events.partition_point(|event| event.frame < frame);
+        for event in self.events[start..]
+            .iter()
+            .take_while(|event| event.frame == frame)
+        {
+            match event.action {
+                KeyAction::Press => machine.press_key(event.key),
+                KeyAction::Release => machine.release_key(event.key),
+                KeyAction::Tap(frames) => machine.tap_key(event.key, frames),
+            }
+        }
+    }
+}
+
+impl FromStr for KeyScript {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: &str| format!("line {}: {}", index + 1, what);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let frame = fields[0]
+                .parse()
+                .map_err(|_| invalid("invalid frame number"))?;
+            let key = match fields.get(2).map(|key| u8::from_str_radix(key, 16)) {
+                Some(Ok(key)) if key < 16 => key,
+                _ => return Err(invalid("expected a key from 0 to F")),
+            };
+            let action = match (fields.get(1).copied(), fields.get(3)) {
+                (Some("press"), None) => KeyAction::Press,
+                (Some("release"), None) => KeyAction::Release,
+                (Some("tap"), None) => KeyAction::Tap(SCRIPT_TAP_FRAMES),
+                (Some("tap"), Some(frames)) => match frames.parse() {
+                    Ok(frames) if fields.len() == 4 => KeyAction::Tap(frames),
+                    _ => return Err(invalid("invalid tap length")),
+                },
+                _ => return Err(invalid("expected press, release or tap")),
+            };
+            events.push(KeyEvent { frame, key, action });
+        }
+        // keep events of the same frame in the order they were written
+        events.sort_by_key(|event| event.frame);
+        Ok(KeyScript { events })
+    }
+}
+
+impl fmt::Display for KeyScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script: KeyScript = "# comment\n20 release 4\n\n10 press 4\n20 tap a 5\n30 tap F\n"
+            .parse()
+            .unwrap();
+        let expected = KeyScript {
+            events: vec![
+                KeyEvent {
+                    frame: 10,
+                    key: 4,
+                    action: KeyAction::Press,
+                },
+                KeyEvent {
+                    frame: 20,
+                    key: 4,
+                    action: KeyAction::Release,
+                },
+                KeyEvent {
+                    frame: 20,
+                    key: 0xa,
+                    action: KeyAction::Tap(5),
+                },
+                KeyEvent {
+                    frame: 30,
+                    key: 0xf,
+                    action: KeyAction::Tap(SCRIPT_TAP_FRAMES),
+                },
+            ],
+        };
+        assert_eq!(script, expected);
+        assert_eq!(script.last_frame(), Some(30));
+        assert_eq!(script.to_string().parse::<KeyScript>().unwrap(), script);
+
+        assert!("x press 1".parse::<KeyScript>().is_err());
+        assert!("1 push 1".parse::<KeyScript>().is_err());
+        assert!("1 press 10".parse::<KeyScript>().is_err());
+        assert!("1 tap 1 x".parse::<KeyScript>().is_err());
+        assert!("1 press 1 2".parse::<KeyScript>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let script: KeyScript = "0 press 1\n2 release 1\n2 tap 2 1\n".parse().unwrap();
+        let mut machine = Machine::new();
+        script.apply(0, &mut machine);
+        assert!(machine.keypad.is_pressed(1));
+        script.apply(1, &mut machine);
+        assert!(machine.keypad.is_pressed(1));
+        script.apply(2, &mut machine);
+        assert!(!machine.keypad.is_pressed(1));
+        assert!(machine.keypad.is_pressed(2));
+        machine.end_frame();
+        assert!(!machine.keypad.is_pressed(2));
+    }
+}
@@ -0,0 +1,317 @@
+use super::cpu::Register;
+use super::error::EmulationError;
+use super::graphic::{Graphic, PLANE_MASK};
+use super::keypad::Keypad;
+use super::machine::Machine;
+use super::memory::{Memory, PROGRAM_START, STACK_DEPTH, XO_MEMORY_SIZE};
+use super::quirks::Quirks;
+use super::random::Random;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Version of the save state format written by `save`.
+/// Bump it whenever the layout below changes, `restore` only accepts this exact version.
+pub const STATE_VERSION: u16 = 2;
+
+/// Serialize the whole machine except for `cycles_per_frame`, which belongs to the frontend.
+/// All values are little endian. The layout is:
+/// magic, version, quirk bits, memory length and bytes, stack length and entries, registers,
+/// timers, RPL flags, audio pattern, pitch, halted flag, Fx0A target register or 0xFF, RNG state,
+/// resolution, selected planes, framebuffer, held keys, pressed keys, released keys and tap
+/// timeouts.
+pub fn save(machine: &Machine) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    put_u16(&mut out, STATE_VERSION);
+    out.push(quirk_bits(&machine.quirks));
+
+    let memory = &machine.memory;
+    put_u32(&mut out, memory.all.len() as u32);
+    out.extend_from_slice(&memory.all);
+    out.push(memory.stack.len() as u8);
+    for &address in &memory.stack {
+        put_u16(&mut out, address);
+    }
+
+    let register = &machine.register;
+    put_u16(&mut out, register.pc);
+    put_u16(&mut out, register.i);
+    out.extend_from_slice(&register.v);
+    out.push(register.delay_timer);
+    out.push(register.sound_timer);
+    out.extend_from_slice(&register.rpl);
+    out.extend_from_slice(&register.audio_pattern);
+    out.push(register.pitch);
+    out.push(register.halted as u8);
+    out.push(register.waiting_for_key.map_or(0xFF, |vx| vx as u8));
+    put_u32(&mut out, register.random.state);
+
+    let graphic = &machine.graphic;
+    out.push(graphic.is_hires() as u8);
+    out.push(graphic.selected_planes());
+    out.extend_from_slice(&graphic.gfx);
+
+    let keypad = &machine.keypad;
+    put_u16(&mut out, keypad.held);
+    put_u16(&mut out, keypad.pressed);
+    put_u16(&mut out, keypad.released);
+    for &timeout in &keypad.timeouts {
+        put_u32(&mut out, timeout);
+    }
+    out
+}
+
+/// Rebuild a machine from a save state written by `save`.
+/// The returned machine runs with the default number of cycles per frame.
+pub fn restore(data: &[u8]) -> Result<Machine, EmulationError> {
+    let mut reader = Reader { data };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(corrupt("not a save state"));
+    }
+    let version = reader.u16()?;
+    if version != STATE_VERSION {
+        return Err(EmulationError::IncompatibleState {
+            version,
+            expected: STATE_VERSION,
+        });
+    }
+    let quirks = quirks_from_bits(reader.u8()?)?;
+
+    let size = reader.u32()? as usize;
+    if !(PROGRAM_START..=XO_MEMORY_SIZE).contains(&size) {
+        return Err(corrupt("invalid memory size"));
+    }
+    let mut memory = Memory::with_size(size);
+    memory.all.copy_from_slice(reader.bytes(size)?);
+    let depth = reader.u8()? as usize;
+    if depth > STACK_DEPTH {
+        return Err(corrupt("call stack too deep"));
+    }
+    for _ in 0..depth {
+        memory.stack.push(reader.u16()?);
+    }
+
+    let mut register = Register::new();
+    register.pc = reader.u16()?;
+    register.i = reader.u16()?;
+    register.v.copy_from_slice(reader.bytes(16)?);
+    register.delay_timer = reader.u8()?;
+    register.sound_timer = reader.u8()?;
+    register.rpl.copy_from_slice(reader.bytes(16)?);
+    register.audio_pattern.copy_from_slice(reader.bytes(16)?);
+    register.pitch = reader.u8()?;
+    register.halted = reader.bool()?;
+    register.waiting_for_key = match reader.u8()? {
+        0xFF => None,
+        vx if vx < 16 => Some(vx as usize),
+        _ => return Err(corrupt("invalid key wait register")),
+    };
+    // xorshift never leaves a state of 0, so no save contains one
+    register.random = match reader.u32()? {
+        0 => return Err(corrupt("invalid random state")),
+        state => Random { state },
+    };
+
+    let mut graphic = Graphic::new();
+    graphic.set_hires(reader.bool()?);
+    let planes = reader.u8()?;
+    if planes & !PLANE_MASK != 0 {
+        return Err(corrupt("invalid plane selection"));
+    }
+    graphic.select_planes(planes);
+    let length = graphic.gfx.len();
+    graphic.gfx.copy_from_slice(reader.bytes(length)?);
+    if graphic.gfx.iter().any(|&pixel| pixel & !PLANE_MASK != 0) {
+        return Err(corrupt("invalid pixel"));
+    }
+
+    let mut keypad = Keypad::new();
+    keypad.held = reader.u16()?;
+    keypad.pressed = reader.u16()?;
+    keypad.released = reader.u16()?;
+    for timeout in keypad.timeouts.iter_mut() {
+        *timeout = reader.u32()?;
+    }
+
+    if !reader.data.is_empty() {
+        return Err(corrupt("trailing data"));
+    }
+    Ok(Machine {
+        memory,
+        register,
+        graphic,
+        keypad,
+        quirks,
+        ..Machine::new()
+    })
+}
+
+fn quirk_bits(quirks: &Quirks) -> u8 {
+    [
+        quirks.shift_in_place,
+        quirks.increment_i,
+        quirks.jump_with_vx,
+        quirks.reset_vf,
+        quirks.wrap_sprites,
+        quirks.count_collision_rows,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (i, &enabled)| bits | (enabled as u8) << i)
+}
+
+fn quirks_from_bits(bits: u8) -> Result<Quirks, EmulationError> {
+    if bits >> 6 != 0 {
+        return Err(corrupt("unknown quirks"));
+    }
+    let bit = |i: u8| bits & (1 << i) != 0;
+    Ok(Quirks {
+        shift_in_place: bit(0),
+        increment_i: bit(1),
+        jump_with_vx: bit(2),
+        reset_vf: bit(3),
+        wrap_sprites: bit(4),
+        count_collision_rows: bit(5),
+    })
+}
+
+fn corrupt(reason: &'static str) -> EmulationError {
+    EmulationError::CorruptState { reason }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], EmulationError> {
+        if self.data.len() < length {
+            return Err(corrupt("truncated"));
+        }
+        let (head, tail) = self.data.split_at(length);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, EmulationError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, EmulationError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(corrupt("invalid flag")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, EmulationError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, EmulationError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quirks::Profile;
+
+    fn machine() -> Machine {
+        let mut machine = Machine::with_profile(Profile::SuperChip);
+        machine.memory.all[0x300] = 0xab;
+        machine.memory.stack.push(0x246);
+        machine.register.pc = 0x31a;
+        machine.register.i = 0x123;
+        machine.register.v[0x7] = 0x42;
+        machine.register.delay_timer = 9;
+        machine.register.sound_timer = 3;
+        machine.register.waiting_for_key = Some(0x2);
+        machine.register.random = Random::with_seed(77);
+        machine.graphic.set_hires(true);
+        machine.graphic.gfx[130] = 1;
+        machine.keypad.tap(0xe, 4);
+        machine
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = machine();
+        let restored = restore(&save(&original)).unwrap();
+        assert_eq!(restored.memory.all, original.memory.all);
+        assert_eq!(restored.memory.stack, vec![0x246]);
+        assert_eq!(restored.register.pc, 0x31a);
+        assert_eq!(restored.register.i, 0x123);
+        assert_eq!(restored.register.v, original.register.v);
+        assert_eq!(restored.register.delay_timer, 9);
+        assert_eq!(restored.register.sound_timer, 3);
+        assert_eq!(restored.register.waiting_for_key, Some(0x2));
+        assert_eq!(restored.register.random, Random::with_seed(77));
+        assert!(restored.graphic.is_hires());
+        assert_eq!(restored.graphic.gfx, original.graphic.gfx);
+        assert!(restored.keypad.is_pressed(0xe));
+        assert_eq!(restored.keypad.timeouts[0xe], 4);
+        assert_eq!(restored.quirks, Quirks::SUPER_CHIP);
+        assert_eq!(save(&restored), save(&original));
+    }
+
+    #[test]
+    fn test_restore_other_version() {
+        let mut data = save(&machine());
+        data[4] = 0x7f;
+        let result = restore(&data);
+        assert!(matches!(
+            result,
+            Err(EmulationError::IncompatibleState {
+                version: 0x7f,
+                expected: STATE_VERSION
+            })
+        ));
+    }
+
+    #[test]
+    fn test_restore_truncated() {
+        let data = save(&machine());
+        let result = restore(&data[..data.len() - 1]);
+        assert!(matches!(
+            result,
+            Err(EmulationError::CorruptState {
+                reason: "truncated"
+            })
+        ));
+        let result = restore(b"not a state");
+        assert!(matches!(
+            result,
+            Err(EmulationError::CorruptState {
+                reason: "not a save state"
+            })
+        ));
+    }
+
+    #[test]
+    fn test_restore_zero_random_state() {
+        let mut data = save(&machine());
+        let seed = 77u32.to_le_bytes();
+        let offset = data.windows(4).position(|bytes| bytes == seed).unwrap();
+        data[offset..offset + 4].copy_from_slice(&[0; 4]);
+        let result = restore(&data);
+        assert!(matches!(
+            result,
+            Err(EmulationError::CorruptState {
+                reason: "invalid random state"
+            })
+        ));
+    }
+}
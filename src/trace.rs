@@ -0,0 +1,279 @@
+use super::disassembler::{mnemonic, Syntax};
+use super::machine::Machine;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// First line of every trace written by `Tracer`.
+pub const TRACE_HEADER: &str = "# chip-8 trace: cycle pc opcode v0-vf i sp dt st mnemonic";
+
+/// State of the machine when one instruction was fetched, written as one line of a trace:
+///
+/// ```text
+/// 00000012 0206 6202 01000000000000000000000000000000 0300 01 3C 00 LD V2, 0x02
+/// ```
+///
+/// The fields are separated by single spaces:
+///
+/// - cycle: number of instructions executed before this one, 8 decimal digits or more
+/// - PC, 4 hex digits
+/// - opcode, 4 hex digits. For F000 only the first word is shown.
+/// - V0 to VF, 2 hex digits each without separators
+/// - I, 4 hex digits
+/// - SP, the depth of the call stack, 2 decimal digits
+/// - delay timer and sound timer, 2 hex digits each
+/// - mnemonic in Cowgod syntax, `???` for unknown opcodes. It is informational only and not
+///   compared, so traces of other emulators may leave it out.
+///
+/// Lines starting with `#` are comments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceLine {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub mnemonic: String,
+}
+
+impl TraceLine {
+    /// Describe the instruction at PC of `machine`.
+    pub fn new(cycle: u64, machine: &Machine) -> Self {
+        let register = &machine.register;
+        let bytes = machine
+            .memory
+            .all
+            .get(register.pc as usize..)
+            .unwrap_or_default();
+        let opcode = match bytes {
+            [high, low, ..] => ((*high as u16) << 8) | *low as u16,
+            _ => 0,
+        };
+        TraceLine {
+            cycle,
+            pc: register.pc,
+            opcode,
+            v: register.v,
+            i: register.i,
+            sp: machine.memory.stack.len(),
+            delay_timer: register.delay_timer,
+            sound_timer: register.sound_timer,
+            mnemonic: mnemonic(bytes, Syntax::Cowgod).unwrap_or_else(|| "???".to_string()),
+        }
+    }
+
+    /// Names of the fields which differ from `other`, leaving out the mnemonic.
+    pub fn differences(&self, other: &TraceLine) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.cycle != other.cycle {
+            fields.push("cycle");
+        }
+        if self.pc != other.pc {
+            fields.push("pc");
+        }
+        if self.opcode != other.opcode {
+            fields.push("opcode");
+        }
+        for (vx, name) in VX_NAMES.iter().enumerate() {
+            if self.v[vx] != other.v[vx] {
+                fields.push(name);
+            }
+        }
+        if self.i != other.i {
+            fields.push("i");
+        }
+        if self.sp != other.sp {
+            fields.push("sp");
+        }
+        if self.delay_timer != other.delay_timer {
+            fields.push("dt");
+        }
+        if self.sound_timer != other.sound_timer {
+            fields.push("st");
+        }
+        fields
+    }
+}
+
+const VX_NAMES: [&str; 16] = [
+    "v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "va", "vb", "vc", "vd", "ve", "vf",
+];
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v: String = self
+            .v
+            .iter()
+            .map(|value| format!("{:02X}", value))
+            .collect();
+        write!(
+            f,
+            "{:08} {:04X} {:04X} {} {:04X} {:02} {:02X} {:02X} {}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            v,
+            self.i,
+            self.sp,
+            self.delay_timer,
+            self.sound_timer,
+            self.mnemonic
+        )
+    }
+}
+
+impl FromStr for TraceLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.splitn(9, ' ');
+        let mut field = |name: &str| {
+            fields
+                .next()
+                .filter(|field| !field.is_empty())
+                .ok_or(format!("missing {}", name))
+        };
+        let decimal = |text: &str, name: &str| {
+            text.parse::<u64>()
+                .map_err(|_| format!("invalid {} {}", name, text))
+        };
+        let hex = |text: &str, name: &str| {
+            u16::from_str_radix(text, 16).map_err(|_| format!("invalid {} {}", name, text))
+        };
+
+        let cycle = decimal(field("cycle")?, "cycle")?;
+        let pc = hex(field("pc")?, "pc")?;
+        let opcode = hex(field("opcode")?, "opcode")?;
+        let registers = field("v0-vf")?;
+        if registers.len() != 32 {
+            return Err(format!("invalid v0-vf {}", registers));
+        }
+        let mut v = [0; 16];
+        for (vx, value) in v.iter_mut().enumerate() {
+            let digits = registers.get(vx * 2..vx * 2 + 2).unwrap_or_default();
+            *value = u8::from_str_radix(digits, 16)
+                .map_err(|_| format!("invalid v0-vf {}", registers))?;
+        }
+        let i = hex(field("i")?, "i")?;
+        let sp = decimal(field("sp")?, "sp")? as usize;
+        let timer = |text: &str, name: &str| {
+            u8::from_str_radix(text, 16).map_err(|_| format!("invalid {} {}", name, text))
+        };
+        let delay_timer = timer(field("dt")?, "dt")?;
+        let sound_timer = timer(field("st")?, "st")?;
+        let mnemonic = fields.next().unwrap_or_default().to_string();
+        Ok(TraceLine {
+            cycle,
+            pc,
+            opcode,
+            v,
+            i,
+            sp,
+            delay_timer,
+            sound_timer,
+            mnemonic,
+        })
+    }
+}
+
+/// Writes a `TraceLine` for every instruction executed by a `Machine`.
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    cycle: u64,
+}
+
+impl Tracer {
+    /// Start a trace on `writer`, beginning with `TRACE_HEADER`.
+    pub fn new(mut writer: Box<dyn Write + Send>) -> io::Result<Self> {
+        writeln!(writer, "{}", TRACE_HEADER)?;
+        Ok(Tracer { writer, cycle: 0 })
+    }
+
+    /// Write the line for the instruction `machine` is about to execute.
+    pub fn record(&mut self, machine: &Machine) -> io::Result<()> {
+        writeln!(self.writer, "{}", TraceLine::new(self.cycle, machine))?;
+        self.cycle += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer whose output stays readable after it was handed to a `Tracer`.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut machine = Machine::new();
+        // 6a2b: LD VA, 0x2b
+        machine.memory.all[0x200] = 0x6a;
+        machine.memory.all[0x201] = 0x2b;
+        // 2300: CALL 0x300
+        machine.memory.all[0x202] = 0x23;
+        machine.memory.all[0x203] = 0x00;
+        machine.register.delay_timer = 0x3c;
+        let output = Shared::default();
+        machine.trace = Some(Tracer::new(Box::new(output.clone())).unwrap());
+        machine.run_cycles(3).unwrap();
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                TRACE_HEADER,
+                "00000000 0200 6A2B 00000000000000000000000000000000 0000 00 3C 00 LD VA, 0x2B",
+                "00000001 0202 2300 000000000000000000002B0000000000 0000 00 3C 00 CALL 0x300",
+                "00000002 0300 0000 000000000000000000002B0000000000 0000 01 3C 00 SYS 0x000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_and_differences() {
+        let text = "00000001 0202 2300 000000000000000000002B0000000000 0000 00 3C 00 CALL 0x300";
+        let line: TraceLine = text.parse().unwrap();
+        assert_eq!(line.to_string(), text);
+        assert_eq!(line.v[0xa], 0x2b);
+
+        let mut other: TraceLine =
+            "00000001 0202 2300 000000000000000000002C0000000000 0000 00 3B 00"
+                .parse()
+                .unwrap();
+        assert_eq!(other.mnemonic, "");
+        assert_eq!(line.differences(&other), ["va", "dt"]);
+        other.v[0xa] = 0x2b;
+        other.delay_timer = 0x3c;
+        assert!(line.differences(&other).is_empty());
+
+        assert_eq!(
+            "00000001 0202 2300 00".parse::<TraceLine>(),
+            Err("invalid v0-vf 00".to_string())
+        );
+        assert_eq!(
+            "00000001 0202 2300 000000000000000000002B0000000000 0000 00 13C 00"
+                .parse::<TraceLine>(),
+            Err("invalid dt 13C".to_string())
+        );
+    }
+}
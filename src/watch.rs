@@ -0,0 +1,164 @@
+use std::fmt;
+
+/// Condition checked on every memory or register access made by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// Any byte in `start..=end` is read, e.g. by Fx65 or a sprite drawn by Dxyn.
+    Read { start: usize, end: usize },
+    /// Any byte in `start..=end` is written, e.g. by Fx33 or Fx55.
+    Write { start: usize, end: usize },
+    /// Vx changes its value.
+    Register(usize),
+    /// I is set to an address in `start..=end`.
+    Index { start: u16, end: u16 },
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Watchpoint::Read { start, end } => write!(f, "read {:03X}-{:03X}", start, end),
+            Watchpoint::Write { start, end } => write!(f, "write {:03X}-{:03X}", start, end),
+            Watchpoint::Register(vx) => write!(f, "V{:X}", vx),
+            Watchpoint::Index { start, end } => write!(f, "I in {:03X}-{:03X}", start, end),
+        }
+    }
+}
+
+/// Access which triggered a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchHit {
+    Read { address: usize, length: usize },
+    Write { address: usize, length: usize },
+    Register { vx: usize, old: u8, new: u8 },
+    Index(u16),
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchHit::Read { address, length } => {
+                write!(f, "read of {} bytes at {:03X}", length, address)
+            }
+            WatchHit::Write { address, length } => {
+                write!(f, "write of {} bytes at {:03X}", length, address)
+            }
+            WatchHit::Register { vx, old, new } => {
+                write!(f, "V{:X} changed from {:02X} to {:02X}", vx, old, new)
+            }
+            WatchHit::Index(address) => write!(f, "I set to {:03X}", address),
+        }
+    }
+}
+
+/// Watchpoints of one component together with the hits recorded since they were last taken.
+#[derive(Clone, Debug, Default)]
+pub struct Watches {
+    pub points: Vec<Watchpoint>,
+    hits: Vec<WatchHit>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a read of `length` bytes at `address`.
+    pub fn read(&mut self, address: usize, length: usize) {
+        if self.points.iter().any(|point| match *point {
+            Watchpoint::Read { start, end } => overlaps(address, length, start, end),
+            _ => false,
+        }) {
+            self.hits.push(WatchHit::Read { address, length });
+        }
+    }
+
+    /// Record a write of `length` bytes at `address`.
+    pub fn write(&mut self, address: usize, length: usize) {
+        if self.points.iter().any(|point| match *point {
+            Watchpoint::Write { start, end } => overlaps(address, length, start, end),
+            _ => false,
+        }) {
+            self.hits.push(WatchHit::Write { address, length });
+        }
+    }
+
+    /// Record Vx changing from `old` to `new`.
+    pub fn register(&mut self, vx: usize, old: u8, new: u8) {
+        if old != new && self.points.contains(&Watchpoint::Register(vx)) {
+            self.hits.push(WatchHit::Register { vx, old, new });
+        }
+    }
+
+    /// Record I being set to `address`.
+    pub fn index(&mut self, address: u16) {
+        if self.points.iter().any(|point| match *point {
+            Watchpoint::Index { start, end } => start <= address && address <= end,
+            _ => false,
+        }) {
+            self.hits.push(WatchHit::Index(address));
+        }
+    }
+
+    /// Return the hits recorded so far and forget them.
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+fn overlaps(address: usize, length: usize, start: usize, end: usize) -> bool {
+    length > 0 && address <= end && start < address + length
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memory_ranges() {
+        let mut watches = Watches::new();
+        watches.points.push(Watchpoint::Write {
+            start: 0x300,
+            end: 0x30f,
+        });
+        watches.read(0x300, 4);
+        watches.write(0x2f0, 16);
+        watches.write(0x310, 2);
+        assert!(watches.take_hits().is_empty());
+        watches.write(0x2ff, 2);
+        assert_eq!(
+            watches.take_hits(),
+            vec![WatchHit::Write {
+                address: 0x2ff,
+                length: 2
+            }]
+        );
+        assert!(watches.take_hits().is_empty());
+    }
+
+    #[test]
+    fn test_register_and_index() {
+        let mut watches = Watches::new();
+        watches.points.push(Watchpoint::Register(0xf));
+        watches.points.push(Watchpoint::Index {
+            start: 0x000,
+            end: 0x04f,
+        });
+        watches.register(0xf, 1, 1);
+        watches.register(0xe, 0, 1);
+        watches.index(0x200);
+        assert!(watches.take_hits().is_empty());
+        watches.register(0xf, 0, 1);
+        watches.index(0x04f);
+        assert_eq!(
+            watches.take_hits(),
+            vec![
+                WatchHit::Register {
+                    vx: 0xf,
+                    old: 0,
+                    new: 1
+                },
+                WatchHit::Index(0x04f)
+            ]
+        );
+    }
+}
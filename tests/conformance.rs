@@ -0,0 +1,251 @@
+use chip_8::emulator::{
+    assemble_file, KeyScript, Machine, Profile, Profiler, Quirks, PROGRAM_START,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Frames a test ROM may run before it has to end in its halt loop.
+const MAX_FRAMES: u64 = 600;
+
+/// Expectations written in the `;;` comment lines of a test ROM source:
+///
+/// ```text
+/// ;; profile schip
+/// ;; quirk shift_in_place off
+/// ;; keys 3 tap 7 2
+/// ;; expect V0=05 VF=01 I=0300 DT=00 SP=0 [300]=01,02,03
+/// ;; hires
+/// ;; screen 2
+/// ;; ....####
+/// ;; ....#..#
+/// ```
+///
+/// Registers and memory are given in hex. The rows following `screen` describe the start of the
+/// screen rows from the given one on, 0 if omitted. Every other pixel must be unlit.
+struct Case {
+    profile: Profile,
+    quirks: Vec<(String, bool)>,
+    keys: KeyScript,
+    registers: Vec<(String, u16)>,
+    memory: Vec<(usize, Vec<u8>)>,
+    hires: bool,
+    screen: Vec<(usize, String)>,
+}
+
+impl Case {
+    fn parse(source: &str) -> Result<Case, String> {
+        let mut case = Case {
+            profile: Profile::default(),
+            quirks: Vec::new(),
+            keys: KeyScript::new(),
+            registers: Vec::new(),
+            memory: Vec::new(),
+            hires: false,
+            screen: Vec::new(),
+        };
+        let mut keys = String::new();
+        let mut screen_row = None;
+        for line in source.lines() {
+            let line = match line.trim().strip_prefix(";;") {
+                Some(line) => line.trim(),
+                None => continue,
+            };
+            if let Some(row) = screen_row.as_mut() {
+                if line.chars().all(|c| ".#+@".contains(c)) {
+                    case.screen.push((*row, line.to_string()));
+                    *row += 1;
+                    continue;
+                }
+            }
+            screen_row = None;
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("profile") => case.profile = words.next().unwrap_or_default().parse()?,
+                Some("quirk") => {
+                    let name = words.next().unwrap_or_default().to_string();
+                    case.quirks.push((name, words.next() == Some("on")));
+                }
+                Some("keys") => {
+                    keys.push_str(&words.collect::<Vec<_>>().join(" "));
+                    keys.push('\n');
+                }
+                Some("expect") => {
+                    for expectation in words {
+                        case.expect(expectation)?;
+                    }
+                }
+                Some("hires") => case.hires = true,
+                Some("screen") => {
+                    let row = words.next().map_or(Ok(0), str::parse);
+                    screen_row = Some(row.map_err(|_| format!("invalid row in {}", line))?);
+                }
+                _ => return Err(format!("unknown expectation {}", line)),
+            }
+        }
+        case.keys = keys.parse()?;
+        Ok(case)
+    }
+
+    /// Add an expectation like `V3=1F` or `[300]=01,02`.
+    fn expect(&mut self, expectation: &str) -> Result<(), String> {
+        let invalid = || format!("invalid expectation {}", expectation);
+        let hex = |text: &str| u16::from_str_radix(text, 16).map_err(|_| invalid());
+        let (name, value) = match expectation.split_once('=') {
+            Some(parts) => parts,
+            None => return Err(invalid()),
+        };
+        match name
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+        {
+            Some(address) => {
+                let bytes = value
+                    .split(',')
+                    .map(|byte| hex(byte).map(|byte| byte as u8))
+                    .collect::<Result<_, _>>()?;
+                self.memory.push((hex(address)? as usize, bytes));
+            }
+            None => self.registers.push((name.to_string(), hex(value)?)),
+        }
+        Ok(())
+    }
+
+    fn quirks(&self) -> Result<Quirks, String> {
+        let mut quirks = self.profile.quirks();
+        for (name, enabled) in &self.quirks {
+            if !quirks.set(name, *enabled) {
+                return Err(format!("unknown quirk {}", name));
+            }
+        }
+        Ok(quirks)
+    }
+
+    /// Run `rom` frame by frame until it reaches its halt loop.
+    fn run(&self, rom: &[u8]) -> Result<Machine, String> {
+        let mut machine = Machine::with_profile(self.profile);
+        machine.quirks = self.quirks()?;
+        machine.profiler = Some(Profiler::new());
+        machine.memory.all[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        for frame in 0..MAX_FRAMES {
+            self.keys.apply(frame, &mut machine);
+            for _ in 0..machine.cycles_per_frame {
+                if machine.is_in_halt_loop() {
+                    return Ok(machine);
+                }
+                let pc = machine.register.pc;
+                machine
+                    .step()
+                    .map_err(|err| format!("failed at {:03X}: {}", pc, err))?;
+            }
+            machine.end_frame();
+        }
+        Err(format!("no halt loop within {} frames", MAX_FRAMES))
+    }
+
+    /// Differences between the expectations and the final state of `machine`.
+    fn check(&self, machine: &Machine) -> Vec<String> {
+        let mut failures = Vec::new();
+        let register = &machine.register;
+        for (name, expected) in &self.registers {
+            let actual = match name.as_str() {
+                "I" => register.i,
+                "PC" => register.pc,
+                "DT" => register.delay_timer as u16,
+                "ST" => register.sound_timer as u16,
+                "SP" => machine.memory.stack.len() as u16,
+                "PITCH" => register.pitch as u16,
+                _ => match name.strip_prefix('V').map(|x| usize::from_str_radix(x, 16)) {
+                    Some(Ok(x)) if x < 16 => register.v[x] as u16,
+                    _ => {
+                        failures.push(format!("unknown register {}", name));
+                        continue;
+                    }
+                },
+            };
+            if actual != *expected {
+                failures.push(format!("{} is {:X}, expected {:X}", name, actual, expected));
+            }
+        }
+        for (address, expected) in &self.memory {
+            let actual = &machine.memory.all[*address..*address + expected.len()];
+            if actual != &expected[..] {
+                failures.push(format!(
+                    "memory at {:03X} is {:02X?}, expected {:02X?}",
+                    address, actual, expected
+                ));
+            }
+        }
+
+        let graphic = &machine.graphic;
+        if graphic.is_hires() != self.hires {
+            failures.push(format!("hires mode is {}", graphic.is_hires()));
+            return failures;
+        }
+        let mut expected = vec![".".repeat(graphic.width()); graphic.height()];
+        for (row, pixels) in &self.screen {
+            expected[*row].replace_range(..pixels.len(), pixels);
+        }
+        let text = graphic.to_text();
+        let actual: Vec<&str> = text.lines().collect();
+        if let Some(row) = (0..expected.len()).find(|&row| expected[row] != actual[row]) {
+            failures.push(format!(
+                "screen row {} differs\n  expected {}\n  actual   {}",
+                row, expected[row], actual[row]
+            ));
+        }
+        failures
+    }
+}
+
+/// Sources of the test ROMs, sorted by name.
+fn sources() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "8s"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Names of all instruction types, taken from the files of `src/instructions`.
+fn instruction_names() -> BTreeSet<String> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/instructions");
+    fs::read_dir(dir)
+        .unwrap()
+        .filter_map(|entry| {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            let opcode = name.strip_prefix("opcode_")?.strip_suffix(".rs")?;
+            Some(format!("Opcode{}", opcode))
+        })
+        .collect()
+}
+
+#[test]
+fn test_conformance_roms() {
+    let mut failures = Vec::new();
+    let mut executed = BTreeSet::new();
+    for path in sources() {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let result = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| Case::parse(&source))
+            .and_then(|case| {
+                let rom = assemble_file(&path).map_err(|err| err.to_string())?;
+                let machine = case.run(&rom)?;
+                let profiler = machine.profiler.as_ref().unwrap();
+                executed.extend(profiler.classes.keys().map(|class| class.to_string()));
+                Ok(case.check(&machine))
+            });
+        match result {
+            Ok(problems) => failures.extend(problems.iter().map(|p| format!("{}: {}", name, p))),
+            Err(err) => failures.push(format!("{}: {}", name, err)),
+        }
+    }
+    assert!(failures.is_empty(), "\n{}\n", failures.join("\n"));
+
+    let missing: Vec<_> = instruction_names().difference(&executed).cloned().collect();
+    assert!(missing.is_empty(), "not covered by any ROM: {:?}", missing);
+}